@@ -0,0 +1,56 @@
+#![no_main]
+use chip8::cpu::{Chip8, Quirks};
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+
+// bounds a run so looping ROMs finish quickly
+const MAX_CYCLES: usize = 10_000;
+
+#[derive(Arbitrary, Debug)]
+enum Event {
+    Press(u8),
+    Release(u8),
+    Cycles(u8),
+    Frame,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    rom: Vec<u8>,
+    quirks: [bool; 6],
+    events: Vec<Event>,
+}
+
+fuzz_target!(|input: Input| {
+    let q = input.quirks;
+    let mut cpu = Chip8::new();
+    cpu.set_quirks(Quirks {
+        shift: q[0],
+        load_store: q[1],
+        jump: q[2],
+        vf_reset: q[3],
+        display_wait: q[4],
+        wrap: q[5],
+    });
+
+    if cpu.load_rom(&input.rom).is_err() {
+        return;
+    }
+
+    let mut cycles = 0;
+    for event in input.events {
+        match event {
+            Event::Press(key) => cpu.press_key(usize::from(key) % 16),
+            Event::Release(key) => cpu.release_key(usize::from(key) % 16),
+            Event::Frame => cpu.tick_timers(),
+            Event::Cycles(n) => {
+                for _ in 0..n {
+                    cycles += 1;
+                    if cycles > MAX_CYCLES || cpu.execute_cycle().is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+});
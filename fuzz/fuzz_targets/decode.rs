@@ -0,0 +1,12 @@
+#![no_main]
+use chip8::cpu::OpCode;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|instruction: u16| {
+    let opcode = OpCode::decode(instruction);
+    opcode.cycles();
+    OpCode::disassemble(instruction);
+
+    assert_eq!(opcode.encode(), Ok(instruction));
+    assert_eq!(opcode.to_string().parse(), Ok(opcode));
+});
@@ -1,65 +1,507 @@
-use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired, AudioStatus};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
 use sdl2::AudioSubsystem;
+use speed::FRAME_HZ;
+use std::f32::consts::PI;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
-struct SquareWave {
-    phase_inc: f32,
+const RATE: i32 = 44_100;
+const FREQUENCY_MIN: f32 = 55.0;
+const FREQUENCY_MAX: f32 = 1760.0;
+const VOLUME_STEP: f32 = 0.05;
+// semitone ratio used by the pitch hotkeys
+const PITCH_STEP: f32 = 1.059_463_1;
+// time for the envelope to rise or fall, long enough to avoid clicks
+const RAMP_MS: f32 = 5.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Waveform {
+    #[default]
+    Square,
+    Sine,
+    Triangle,
+    Noise,
+}
+
+impl Waveform {
+    pub fn cycle(self) -> Waveform {
+        match self {
+            Waveform::Square => Waveform::Sine,
+            Waveform::Sine => Waveform::Triangle,
+            Waveform::Triangle => Waveform::Noise,
+            Waveform::Noise => Waveform::Square,
+        }
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Waveform::Square => write!(f, "square"),
+            Waveform::Sine => write!(f, "sine"),
+            Waveform::Triangle => write!(f, "triangle"),
+            Waveform::Noise => write!(f, "noise"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Device,
+    // discards all output, for headless runs
+    Null,
+    // records to the `wav` file
+    Wav,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    pub waveform: Waveform,
+    // tone frequency in Hz
+    pub frequency: f32,
+    pub volume: f32,
+    pub mute: bool,
+    pub backend: Backend,
+    pub wav: PathBuf,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            waveform: Waveform::Square,
+            frequency: 220.0,
+            volume: 0.25,
+            mute: false,
+            backend: Backend::Device,
+            wav: PathBuf::from("chip8.wav"),
+        }
+    }
+}
+
+impl AudioConfig {
+    pub fn louder(&mut self) {
+        self.volume = round(self.volume + VOLUME_STEP).min(1.0);
+    }
+
+    pub fn quieter(&mut self) {
+        self.volume = round(self.volume - VOLUME_STEP).max(0.0);
+    }
+
+    pub fn higher(&mut self) {
+        self.frequency = (self.frequency * PITCH_STEP).min(FREQUENCY_MAX);
+    }
+
+    pub fn lower(&mut self) {
+        self.frequency = (self.frequency / PITCH_STEP).max(FREQUENCY_MIN);
+    }
+
+    fn clamped(&self) -> AudioConfig {
+        AudioConfig {
+            frequency: self.frequency.clamp(FREQUENCY_MIN, FREQUENCY_MAX),
+            volume: self.volume.clamp(0.0, 1.0),
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Display for AudioConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {:.0} Hz, volume {:.0}%",
+            self.waveform,
+            self.frequency,
+            self.volume * 100.0
+        )?;
+        if self.mute {
+            write!(f, ", muted")?;
+        }
+        Ok(())
+    }
+}
+
+// volume steps are kept on whole percentages
+#[inline(always)]
+fn round(volume: f32) -> f32 {
+    (volume * 100.0).round() / 100.0
+}
+
+// tone generator run on the audio thread, fading in and out whenever the
+// gate, mute or volume change
+struct Tone {
+    config: AudioConfig,
+    rate: f32,
+    gate: bool,
     phase: f32,
-    volume: f32,
+    gain: f32,
+    noise: u32,
+    noise_sample: f32,
 }
 
-impl AudioCallback for SquareWave {
+impl Tone {
+    fn new(config: AudioConfig, rate: i32) -> Tone {
+        Tone {
+            config: config.clamped(),
+            rate: rate as f32,
+            gate: false,
+            phase: 0.0,
+            gain: 0.0,
+            noise: 0xACE1,
+            noise_sample: 1.0,
+        }
+    }
+
+    fn sample(&mut self) -> f32 {
+        let target = if self.gate && !self.config.mute {
+            self.config.volume
+        } else {
+            0.0
+        };
+        let step = 1000.0 / (RAMP_MS * self.rate);
+        self.gain = if self.gain < target {
+            (self.gain + step).min(target)
+        } else {
+            (self.gain - step).max(target)
+        };
+
+        if self.gain == 0.0 {
+            self.phase = 0.0;
+            return 0.0;
+        }
+
+        let wave = match self.config.waveform {
+            Waveform::Square => {
+                if self.phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (self.phase * 2.0 * PI).sin(),
+            Waveform::Triangle => 4.0 * (self.phase - 0.5).abs() - 1.0,
+            Waveform::Noise => self.noise_sample,
+        };
+
+        self.phase += self.config.frequency / self.rate;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+            self.next_noise();
+        }
+
+        wave * self.gain
+    }
+
+    // 16-bit Galois LFSR, stepped once per period so noise follows the pitch
+    fn next_noise(&mut self) {
+        let bit = self.noise & 1;
+        self.noise >>= 1;
+        if bit == 1 {
+            self.noise ^= 0xB400;
+        }
+        self.noise_sample = if bit == 1 { 1.0 } else { -1.0 };
+    }
+}
+
+impl AudioCallback for Tone {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
         for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
-            };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
+            *x = self.sample();
         }
     }
 }
 
-pub struct Audio {
-    device: AudioDevice<SquareWave>,
+// an audio output, driven once per emulated frame so that offline backends
+// follow the sound timer exactly rather than wall-clock time
+pub trait Audio {
+    fn configure(&mut self, config: &AudioConfig);
+
+    // called after each 60Hz frame with whether the buzzer sounded
+    fn frame(&mut self, sound: bool);
+
+    // called while emulation is not advancing, e.g. when paused
+    fn silence(&mut self) {}
+}
+
+// opens the configured backend, falling back to silence when the audio
+// device is unavailable
+pub fn open(audio: Option<&AudioSubsystem>, config: &AudioConfig) -> Box<dyn Audio> {
+    match config.backend {
+        Backend::Device => match audio.map(|audio| Device::new(audio, config)) {
+            Some(Ok(device)) => Box::new(device),
+            Some(Err(err)) => {
+                warn!("Audio device unavailable: {}", err);
+                Box::new(Null)
+            }
+            None => Box::new(Null),
+        },
+        Backend::Null => Box::new(Null),
+        Backend::Wav => match WavWriter::create(&config.wav, config) {
+            Ok(writer) => {
+                info!("Recording audio to {}", config.wav.display());
+                Box::new(writer)
+            }
+            Err(err) => {
+                error!("Error creating {}: {}", config.wav.display(), err);
+                Box::new(Null)
+            }
+        },
+    }
+}
+
+pub struct Null;
+
+impl Audio for Null {
+    fn configure(&mut self, _config: &AudioConfig) {}
+
+    fn frame(&mut self, _sound: bool) {}
 }
 
-impl Audio {
-    const FREQUENCY: i32 = 44_100;
-    const PHASE_INC: f32 = 220.0;
-    const PHASE: f32 = 0.0;
-    const VOLUME: f32 = 0.25;
-    const CHANNELS: u8 = 1;
+// plays the tone through the SDL audio device
+pub struct Device {
+    device: AudioDevice<Tone>,
+    gate: bool,
+}
 
-    pub fn new(audio: &AudioSubsystem) -> Audio {
+impl Device {
+    pub fn new(audio: &AudioSubsystem, config: &AudioConfig) -> Result<Device, String> {
         let desired_spec = AudioSpecDesired {
-            freq: Some(Self::FREQUENCY),
-            channels: Some(Self::CHANNELS),
+            freq: Some(RATE),
+            channels: Some(1),
             samples: None,
         };
 
-        let device = audio
-            .open_playback(None, &desired_spec, |spec| SquareWave {
-                phase_inc: Self::PHASE_INC / spec.freq as f32,
-                phase: Self::PHASE,
-                volume: Self::VOLUME,
-            })
-            .unwrap();
+        let device = audio.open_playback(None, &desired_spec, |spec| {
+            Tone::new(config.clone(), spec.freq)
+        })?;
 
-        Audio { device }
+        // the device runs continuously so the envelope can fade the tone
+        device.resume();
+        Ok(Device {
+            device,
+            gate: false,
+        })
     }
 
-    pub fn on(&self) {
-        if self.device.status() != AudioStatus::Playing {
-            self.device.resume();
+    fn set_gate(&mut self, gate: bool) {
+        if self.gate != gate {
+            self.gate = gate;
+            self.device.lock().gate = gate;
         }
     }
+}
+
+impl Audio for Device {
+    fn configure(&mut self, config: &AudioConfig) {
+        self.device.lock().config = config.clamped();
+    }
+
+    fn frame(&mut self, sound: bool) {
+        self.set_gate(sound);
+    }
+
+    fn silence(&mut self) {
+        self.set_gate(false);
+    }
+}
 
-    pub fn off(&self) {
-        if self.device.status() == AudioStatus::Playing {
-            self.device.pause();
+// renders the tone to a 16-bit mono WAV file, one frame's worth of samples
+// per emulated frame, so recordings line up with captured video
+pub struct WavWriter {
+    file: Option<BufWriter<File>>,
+    tone: Tone,
+    samples: u32,
+}
+
+impl WavWriter {
+    const HEADER_SIZE: u32 = 44;
+
+    pub fn create(path: &Path, config: &AudioConfig) -> io::Result<WavWriter> {
+        let mut file = BufWriter::new(File::create(path)?);
+        write_header(&mut file, 0)?;
+        Ok(WavWriter {
+            file: Some(file),
+            tone: Tone::new(config.clone(), RATE),
+            samples: 0,
+        })
+    }
+
+    fn write_frame(&mut self, sound: bool) -> io::Result<()> {
+        let file = match self.file {
+            Some(ref mut file) => file,
+            None => return Ok(()),
         };
+
+        self.tone.gate = sound;
+        for _ in 0..RATE as u32 / FRAME_HZ {
+            let sample = (self.tone.sample() * f32::from(i16::MAX)) as i16;
+            file.write_all(&sample.to_le_bytes())?;
+        }
+        self.samples += RATE as u32 / FRAME_HZ;
+        Ok(())
+    }
+
+    // patches the chunk sizes in the header once the length is known
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.seek(SeekFrom::Start(0))?;
+            write_header(&mut file, self.samples)?;
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Audio for WavWriter {
+    fn configure(&mut self, config: &AudioConfig) {
+        self.tone.config = config.clamped();
+    }
+
+    fn frame(&mut self, sound: bool) {
+        if let Err(err) = self.write_frame(sound) {
+            error!("Error writing audio: {}", err);
+            self.file = None;
+        }
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            error!("Error writing audio: {}", err);
+        }
+    }
+}
+
+fn write_header<W: Write>(out: &mut W, samples: u32) -> io::Result<()> {
+    let data_size = samples * 2;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(WavWriter::HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    // PCM, mono
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&(RATE as u32).to_le_bytes())?;
+    out.write_all(&(RATE as u32 * 2).to_le_bytes())?;
+    // block align and bits per sample
+    out.write_all(&2u16.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data_size.to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn ramp_samples() -> usize {
+        (RAMP_MS * RATE as f32 / 1000.0).ceil() as usize
+    }
+
+    #[test]
+    fn envelope() {
+        let mut tone = Tone::new(AudioConfig::default(), RATE);
+        assert_eq!(tone.sample(), 0.0);
+
+        tone.gate = true;
+        let first = tone.sample();
+        assert!(first > 0.0 && first < 0.01);
+        for _ in 0..ramp_samples() {
+            tone.sample();
+        }
+        assert_eq!(tone.gain, 0.25);
+
+        tone.config.mute = true;
+        tone.sample();
+        assert!(tone.gain < 0.25 && tone.gain > 0.0);
+        for _ in 0..ramp_samples() {
+            tone.sample();
+        }
+        assert_eq!(tone.sample(), 0.0);
+    }
+
+    #[test]
+    fn waveforms_in_range() {
+        let mut waveform = Waveform::Square;
+        loop {
+            let config = AudioConfig {
+                waveform,
+                volume: 1.0,
+                frequency: FREQUENCY_MAX * 2.0,
+                ..AudioConfig::default()
+            };
+            let mut tone = Tone::new(config, RATE);
+            tone.gate = true;
+
+            let samples: Vec<f32> = (0..RATE).map(|_| tone.sample()).collect();
+            assert!(samples.iter().all(|s| s.abs() <= 1.0));
+            assert!(samples.iter().any(|s| *s > 0.5));
+            assert!(samples.iter().any(|s| *s < -0.5));
+
+            waveform = waveform.cycle();
+            if waveform == Waveform::Square {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn adjust_settings() {
+        let mut config = AudioConfig::default();
+        for _ in 0..30 {
+            config.louder();
+        }
+        assert_eq!(config.volume, 1.0);
+        config.quieter();
+        assert_eq!(config.volume, 0.95);
+
+        for _ in 0..12 {
+            config.higher();
+        }
+        assert!((config.frequency - 440.0).abs() < 0.01);
+        for _ in 0..100 {
+            config.lower();
+        }
+        assert_eq!(config.frequency, FREQUENCY_MIN);
+
+        config.mute = true;
+        assert_eq!(config.to_string(), "square 55 Hz, volume 95%, muted");
+    }
+
+    #[test]
+    fn wav_follows_sound_timer() {
+        let path = env::temp_dir().join(format!("chip8-audio-{}.wav", std::process::id()));
+        let frame = (RATE as u32 / FRAME_HZ) as usize;
+        {
+            let mut writer = WavWriter::create(&path, &AudioConfig::default()).unwrap();
+            for sound in [true, true, false, false].iter() {
+                writer.frame(*sound);
+            }
+        }
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(bytes.len(), 44 + 4 * frame * 2);
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(&bytes[40..44], &(4 * frame as u32 * 2).to_le_bytes());
+
+        let samples: Vec<i16> = bytes[44..]
+            .chunks(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        assert!(samples[..2 * frame].iter().any(|s| *s > 8000));
+        assert!(samples[2 * frame + ramp_samples()..]
+            .iter()
+            .all(|s| *s == 0));
     }
 }
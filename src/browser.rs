@@ -0,0 +1,360 @@
+use rom;
+use romdb::RomDb;
+use sdl2::controller::Button;
+use sdl2::keyboard::Keycode;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const EXTENSIONS: [&str; 4] = ["ch8", "c8", "sc8", "xo8"];
+const MAX_PREVIEW: u64 = 64 * 1024;
+const PAGE: i32 = 10;
+
+pub const N_RECENT: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Nav {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Open,
+    Back,
+    Recent,
+    All,
+    Close,
+}
+
+impl Nav {
+    pub fn from_key(code: Keycode) -> Option<Nav> {
+        match code {
+            Keycode::Up => Some(Nav::Up),
+            Keycode::Down => Some(Nav::Down),
+            Keycode::PageUp => Some(Nav::PageUp),
+            Keycode::PageDown => Some(Nav::PageDown),
+            Keycode::Return | Keycode::KpEnter | Keycode::Right => Some(Nav::Open),
+            Keycode::Backspace | Keycode::Left => Some(Nav::Back),
+            Keycode::R => Some(Nav::Recent),
+            Keycode::A => Some(Nav::All),
+            Keycode::Escape => Some(Nav::Close),
+            _ => None,
+        }
+    }
+
+    pub fn from_button(button: Button) -> Option<Nav> {
+        match button {
+            Button::DPadUp => Some(Nav::Up),
+            Button::DPadDown => Some(Nav::Down),
+            Button::LeftShoulder => Some(Nav::PageUp),
+            Button::RightShoulder => Some(Nav::PageDown),
+            Button::A | Button::DPadRight => Some(Nav::Open),
+            Button::B | Button::DPadLeft => Some(Nav::Back),
+            Button::X => Some(Nav::Recent),
+            Button::Y => Some(Nav::All),
+            Button::Back => Some(Nav::Close),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Entry {
+    Parent,
+    Dir(PathBuf),
+    File { path: PathBuf, size: u64 },
+}
+
+impl Entry {
+    pub fn name(&self) -> String {
+        match self {
+            Entry::Parent => "..".to_owned(),
+            Entry::Dir(path) => format!("{}/", file_name(path)),
+            Entry::File { path, .. } => file_name(path),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum View {
+    Files,
+    Recent,
+}
+
+pub struct Browser {
+    dir: PathBuf,
+    view: View,
+    all: bool,
+    files: Vec<Entry>,
+    recent: Vec<Entry>,
+    selected: usize,
+    preview: String,
+}
+
+impl Browser {
+    pub fn new(dir: &Path, recent: &[PathBuf]) -> Browser {
+        let recent = recent
+            .iter()
+            .filter_map(|path| {
+                let meta = fs::metadata(path).ok()?;
+                Some(Entry::File {
+                    path: path.clone(),
+                    size: meta.len(),
+                })
+            })
+            .collect();
+
+        let mut browser = Browser {
+            dir: dir.to_path_buf(),
+            view: View::Files,
+            all: false,
+            files: Vec::new(),
+            recent,
+            selected: 0,
+            preview: String::new(),
+        };
+        browser.open(dir);
+        browser
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn view(&self) -> View {
+        self.view
+    }
+
+    pub fn all(&self) -> bool {
+        self.all
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn preview(&self) -> &str {
+        &self.preview
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        match self.view {
+            View::Files => &self.files,
+            View::Recent => &self.recent,
+        }
+    }
+
+    fn open(&mut self, dir: &Path) {
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        match read_dir(&dir, self.all) {
+            Ok(files) => {
+                self.dir = dir;
+                self.files = files;
+                self.view = View::Files;
+                self.selected = 0;
+            }
+            Err(err) => error!("Error opening {}: {}", dir.display(), err),
+        }
+    }
+
+    // returns the ROM to load when a file is chosen
+    pub fn navigate(&mut self, nav: Nav) -> Option<PathBuf> {
+        match nav {
+            Nav::Up => self.move_by(-1),
+            Nav::Down => self.move_by(1),
+            Nav::PageUp => self.move_by(-PAGE),
+            Nav::PageDown => self.move_by(PAGE),
+            Nav::Back => self.back(),
+            Nav::Recent => {
+                self.view = match self.view {
+                    View::Files => View::Recent,
+                    View::Recent => View::Files,
+                };
+                self.selected = 0;
+            }
+            Nav::All => {
+                self.all = !self.all;
+                let dir = self.dir.clone();
+                self.open(&dir);
+            }
+            Nav::Open => match self.entries().get(self.selected).cloned() {
+                Some(Entry::Parent) => self.back(),
+                Some(Entry::Dir(dir)) => self.open(&dir),
+                Some(Entry::File { path, .. }) => return Some(path),
+                None => (),
+            },
+            Nav::Close => (),
+        }
+        None
+    }
+
+    fn move_by(&mut self, delta: i32) {
+        let last = self.entries().len().saturating_sub(1) as i32;
+        self.selected = (self.selected as i32 + delta).max(0).min(last) as usize;
+    }
+
+    fn back(&mut self) {
+        if let Some(parent) = self.dir.parent().map(Path::to_path_buf) {
+            let child = self.dir.clone();
+            self.open(&parent);
+            if let Some(i) = self
+                .files
+                .iter()
+                .position(|e| *e == Entry::Dir(child.clone()))
+            {
+                self.selected = i;
+            }
+        }
+    }
+
+    pub fn update_preview(&mut self, db: &RomDb) {
+        self.preview = match self.entries().get(self.selected) {
+            Some(Entry::File { path, size }) => describe(path, *size, db),
+            _ => String::new(),
+        };
+    }
+}
+
+fn describe(path: &Path, size: u64, db: &RomDb) -> String {
+    if size > MAX_PREVIEW {
+        return format!("{} bytes", size);
+    }
+
+    match fs::read(path) {
+        Ok(bytes) => match db.get(&rom::hash(&bytes)) {
+            Some(info) => format!("{}, {} bytes", info, size),
+            None => format!("Unknown ROM, {} bytes", size),
+        },
+        Err(err) => format!("{}", err),
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+fn is_rom(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+// directories first, then files, each sorted case-insensitively
+pub fn read_dir(dir: &Path, all: bool) -> io::Result<Vec<Entry>> {
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let hidden = file_name(&path).starts_with('.');
+        let meta = match entry.metadata() {
+            Ok(meta) => meta,
+            Err(_) => continue,
+        };
+
+        if hidden && !all {
+            continue;
+        } else if meta.is_dir() {
+            dirs.push(Entry::Dir(path));
+        } else if all || is_rom(&path) {
+            files.push(Entry::File {
+                path,
+                size: meta.len(),
+            });
+        }
+    }
+
+    let key = |entry: &Entry| entry.name().to_lowercase();
+    dirs.sort_by_key(key);
+    files.sort_by_key(key);
+
+    let mut entries = Vec::with_capacity(dirs.len() + files.len() + 1);
+    if dir.parent().is_some() {
+        entries.push(Entry::Parent);
+    }
+    entries.extend(dirs);
+    entries.extend(files);
+    Ok(entries)
+}
+
+pub fn push_recent(recent: &mut Vec<PathBuf>, path: &Path) {
+    recent.retain(|p| p != path);
+    recent.insert(0, path.to_path_buf());
+    recent.truncate(N_RECENT);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_by_extension() {
+        let roms = Path::new("roms").canonicalize().unwrap();
+        let entries = read_dir(&roms, false).unwrap();
+        assert_eq!(entries[0], Entry::Parent);
+        assert!(entries.iter().skip(1).all(|e| is_rom(Path::new(&e.name()))));
+        assert!(entries.iter().any(|e| e.name() == "pong.ch8"));
+
+        let all = read_dir(&roms, true).unwrap();
+        assert!(all.len() > entries.len());
+        assert!(all.iter().any(|e| e.name() == "PONG"));
+    }
+
+    #[test]
+    fn navigate_and_open() {
+        let roms = Path::new("roms").canonicalize().unwrap();
+        let mut browser = Browser::new(&roms, &[]);
+        assert_eq!(browser.selected(), 0);
+
+        browser.navigate(Nav::Up);
+        assert_eq!(browser.selected(), 0);
+        browser.navigate(Nav::PageDown);
+        assert_eq!(browser.selected(), PAGE as usize);
+
+        let path = browser.navigate(Nav::Open).unwrap();
+        assert!(is_rom(&path));
+
+        browser.navigate(Nav::Back);
+        assert_eq!(browser.dir(), roms.parent().unwrap());
+        assert_eq!(
+            browser.entries()[browser.selected()],
+            Entry::Dir(roms.clone())
+        );
+
+        browser.navigate(Nav::Open);
+        assert_eq!(browser.dir(), roms.as_path());
+    }
+
+    #[test]
+    fn recent_files() {
+        let mut recent = Vec::new();
+        for i in 0..N_RECENT + 2 {
+            push_recent(&mut recent, Path::new(&format!("{}.ch8", i)));
+        }
+        push_recent(&mut recent, Path::new("5.ch8"));
+        assert_eq!(recent.len(), N_RECENT);
+        assert_eq!(recent[0], Path::new("5.ch8"));
+        assert_eq!(recent[1], Path::new("11.ch8"));
+        assert_eq!(
+            recent.iter().filter(|p| *p == Path::new("5.ch8")).count(),
+            1
+        );
+
+        let pong = Path::new("roms/pong.ch8").to_path_buf();
+        let missing = Path::new("roms/missing.ch8").to_path_buf();
+        let mut browser = Browser::new(Path::new("roms"), &[missing, pong.clone()]);
+        browser.navigate(Nav::Recent);
+        assert_eq!(browser.view(), View::Recent);
+        assert_eq!(browser.entries().len(), 1);
+
+        browser.update_preview(&RomDb::builtin());
+        assert_eq!(
+            browser.preview(),
+            "Pong [CHIP-8] by Paul Vervalin, 246 bytes"
+        );
+        assert_eq!(browser.navigate(Nav::Open), Some(pong));
+    }
+}
@@ -0,0 +1,320 @@
+use config;
+use cpu::Chip8State;
+use debugger::Location;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+const CHEATS_DIR: &str = "cheats";
+const CHEATS_EXT: &str = "cht";
+
+// how a byte must have changed since the last snapshot to stay a candidate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compare {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl Compare {
+    pub fn matches(self, old: u8, new: u8) -> bool {
+        match self {
+            Compare::Equal(value) => new == value,
+            Compare::Changed => new != old,
+            Compare::Unchanged => new == old,
+            Compare::Increased => new > old,
+            Compare::Decreased => new < old,
+        }
+    }
+}
+
+// narrows down the memory addresses holding a value across snapshots
+#[derive(Clone, Debug)]
+pub struct Search {
+    snapshot: Vec<u8>,
+    candidates: Vec<usize>,
+}
+
+impl Search {
+    pub fn new(state: &Chip8State) -> Search {
+        Search {
+            snapshot: state.memory().to_vec(),
+            candidates: (0..state.memory().len()).collect(),
+        }
+    }
+
+    // keeps the candidates that match, then takes a new snapshot
+    pub fn filter(&mut self, state: &Chip8State, compare: Compare) -> usize {
+        let (old, new) = (&self.snapshot, state.memory());
+        self.candidates
+            .retain(|&address| compare.matches(old[address], new[address]));
+        self.snapshot = new.to_vec();
+        self.candidates.len()
+    }
+
+    pub fn candidates(&self) -> &[usize] {
+        &self.candidates
+    }
+}
+
+// a named set of locations frozen to values every frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cheat {
+    pub name: String,
+    pub codes: Vec<(Location, u16)>,
+    pub enabled: bool,
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let codes: Vec<String> = self
+            .codes
+            .iter()
+            .map(|(location, value)| format!("{}={:02X}", location, value))
+            .collect();
+        let enabled = if self.enabled { '+' } else { '-' };
+        write!(f, "{} {}: {}", enabled, self.name, codes.join(", "))
+    }
+}
+
+impl FromStr for Cheat {
+    type Err = String;
+
+    // "+ Infinite lives: [03F0]=03, VE=00", where + enables it on load
+    fn from_str(line: &str) -> Result<Cheat, String> {
+        let line = line.trim();
+        let (enabled, line) = match line.chars().next() {
+            Some('+') => (true, &line[1..]),
+            Some('-') => (false, &line[1..]),
+            _ => (false, line),
+        };
+        let i = line.rfind(':').ok_or("expected name: codes")?;
+        let name = line[..i].trim();
+        if name.is_empty() {
+            return Err("missing name".to_owned());
+        }
+
+        let mut codes = Vec::new();
+        for code in line[i + 1..].split(',').map(str::trim) {
+            let mut split = code.splitn(2, '=');
+            let (location, value) = match (split.next(), split.next()) {
+                (Some(location), Some(value)) => (location.trim(), value.trim()),
+                _ => return Err(format!("bad code {}", code)),
+            };
+            let value =
+                u16::from_str_radix(value, 16).map_err(|_| format!("bad value {}", value))?;
+            codes.push((location.parse()?, value));
+        }
+
+        Ok(Cheat {
+            name: name.to_owned(),
+            codes,
+            enabled,
+        })
+    }
+}
+
+pub fn parse(text: &str) -> Result<Vec<Cheat>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| {
+            line.parse()
+                .map_err(|err| format!("line {}: {}", i + 1, err))
+        })
+        .collect()
+}
+
+// cheats for the loaded ROM, kept in a file named after its SHA-1
+#[derive(Clone, Debug, Default)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+    hash: Option<String>,
+    search: Option<Search>,
+}
+
+impl Cheats {
+    pub fn path(hash: &str) -> Option<PathBuf> {
+        config::path(CHEATS_DIR).map(|dir| dir.join(format!("{}.{}", hash, CHEATS_EXT)))
+    }
+
+    // where the loaded ROM's cheats are saved
+    pub fn file(&self) -> Option<PathBuf> {
+        self.hash.as_ref().and_then(|hash| Cheats::path(hash))
+    }
+
+    // replaces the cheats with those saved for a ROM
+    pub fn load(&mut self, hash: &str) {
+        self.hash = Some(hash.to_owned());
+        self.search = None;
+        self.cheats = Vec::new();
+
+        let path = match Cheats::path(hash) {
+            Some(ref path) if path.exists() => path.clone(),
+            _ => return,
+        };
+        let result = fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| parse(&text));
+        match result {
+            Ok(cheats) => {
+                info!("Loaded {} cheats", cheats.len());
+                self.cheats = cheats;
+            }
+            Err(err) => error!("Error reading {}: {}", path.display(), err),
+        }
+    }
+
+    fn save(&self) {
+        let path = match self.file() {
+            Some(path) => path,
+            None => return,
+        };
+
+        let mut text = String::new();
+        for cheat in &self.cheats {
+            text.push_str(&format!("{}\n", cheat));
+        }
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, text));
+        if let Err(err) = result {
+            error!("Error writing {}: {}", path.display(), err);
+        }
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn toggle(&mut self, i: usize) -> Option<&Cheat> {
+        let cheat = self.cheats.get_mut(i)?;
+        cheat.enabled = !cheat.enabled;
+        self.save();
+        self.cheats.get(i)
+    }
+
+    // freezes a single location, named after it so it can be unfrozen
+    pub fn freeze(&mut self, location: Location, value: u16) {
+        let cheat = Cheat {
+            name: location.to_string(),
+            codes: vec![(location, value)],
+            enabled: true,
+        };
+        match self.cheats.iter().position(|c| c.name == cheat.name) {
+            Some(i) => self.cheats[i] = cheat,
+            None => self.cheats.push(cheat),
+        }
+        self.save();
+    }
+
+    pub fn unfreeze(&mut self, location: Location) -> bool {
+        let n = self.cheats.len();
+        self.cheats.retain(|c| c.name != location.to_string());
+        self.save();
+        self.cheats.len() != n
+    }
+
+    // the locations and values to write this frame
+    pub fn frozen(&self) -> impl Iterator<Item = &(Location, u16)> {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .flat_map(|cheat| cheat.codes.iter())
+    }
+
+    pub fn search(&self) -> Option<&Search> {
+        self.search.as_ref()
+    }
+
+    pub fn start_search(&mut self, state: &Chip8State) -> &Search {
+        self.search.insert(Search::new(state))
+    }
+
+    // narrows the current search, starting one if needed
+    pub fn filter(&mut self, state: &Chip8State, compare: Compare) -> &Search {
+        let search = self.search.get_or_insert_with(|| Search::new(state));
+        search.filter(state, compare);
+        search
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::Chip8;
+    use opcode::Register;
+
+    #[test]
+    fn parse_cheats() {
+        let cheats = parse(
+            "# BRIX\n\
+             + Infinite lives: [03F0]=03\n\
+             \n\
+             Slow ball: VA=01, 300=FF\n",
+        )
+        .unwrap();
+        assert_eq!(cheats.len(), 2);
+        assert_eq!(cheats[0].name, "Infinite lives");
+        assert!(cheats[0].enabled);
+        assert_eq!(cheats[0].codes, vec![(Location::Memory(0x3F0), 3)]);
+        assert!(!cheats[1].enabled);
+        assert_eq!(
+            cheats[1].codes,
+            vec![
+                (Location::Register(Register::V(0xA)), 1),
+                (Location::Memory(0x300), 0xFF)
+            ]
+        );
+
+        assert_eq!(cheats[1].to_string(), "- Slow ball: VA=01, [0300]=FF");
+        assert_eq!(parse(&cheats[0].to_string()).unwrap()[0], cheats[0]);
+        assert_eq!(
+            parse("\n\nLives 03F0=03").unwrap_err(),
+            "line 3: expected name: codes"
+        );
+        assert!(parse("Lives: 03F0").is_err());
+        assert!(parse("Lives: 03F0=XY").is_err());
+        assert!(parse("Lives: 1000=03").is_err());
+    }
+
+    #[test]
+    fn iterative_search() {
+        let mut cpu = Chip8::new();
+        cpu.poke(0x300, &[3, 3]).unwrap();
+
+        let mut search = Search::new(cpu.state());
+        assert_eq!(search.filter(cpu.state(), Compare::Equal(3)), 2);
+
+        cpu.poke(0x300, &[2, 4]).unwrap();
+        assert_eq!(search.filter(cpu.state(), Compare::Changed), 2);
+        cpu.poke(0x300, &[1]).unwrap();
+        assert_eq!(search.filter(cpu.state(), Compare::Decreased), 1);
+        assert_eq!(search.candidates(), &[0x300]);
+        assert_eq!(search.filter(cpu.state(), Compare::Unchanged), 1);
+        assert_eq!(search.filter(cpu.state(), Compare::Increased), 0);
+    }
+
+    #[test]
+    fn freeze_locations() {
+        let mut cheats = Cheats::default();
+        cheats.freeze(Location::Memory(0x3F0), 3);
+        cheats.freeze(Location::Memory(0x3F0), 5);
+        cheats.freeze(Location::Register(Register::V(0xE)), 0);
+        assert_eq!(cheats.cheats().len(), 2);
+        assert_eq!(cheats.frozen().count(), 2);
+
+        assert!(!cheats.toggle(1).unwrap().enabled);
+        assert_eq!(
+            cheats.frozen().collect::<Vec<_>>(),
+            vec![&(Location::Memory(0x3F0), 5)]
+        );
+        assert!(cheats.unfreeze(Location::Memory(0x3F0)));
+        assert!(!cheats.unfreeze(Location::Memory(0x3F0)));
+        assert_eq!(cheats.frozen().count(), 0);
+    }
+}
@@ -0,0 +1,274 @@
+use audio::AudioConfig;
+use cpu::Quirks;
+use logger::LogConfig;
+use speed::Timing;
+use dirs;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use toml;
+use watch::WatchMode;
+
+const APP_DIR: &str = "chip8";
+const CONFIG_FILE: &str = "config.toml";
+const ROMS_FILE: &str = "roms.toml";
+
+pub const IPF_DEFAULT: u32 = 8;
+pub const FPS_DEFAULT: u32 = 60;
+pub const WINDOW_WIDTH: u32 = 1024;
+pub const WINDOW_HEIGHT: u32 = 576;
+
+pub fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP_DIR))
+}
+
+pub fn path(name: &str) -> Option<PathBuf> {
+    dir().map(|dir| dir.join(name))
+}
+
+pub fn load<T: DeserializeOwned>(name: &str) -> Option<T> {
+    let path = path(name)?;
+    if !path.exists() {
+        return None;
+    }
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            error!("Error reading {}: {}", path.display(), err);
+            return None;
+        }
+    };
+
+    match toml::from_str(&contents) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            error!("Error parsing {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+pub fn save<T: Serialize>(name: &str, value: &T) {
+    let path = match path(name) {
+        Some(path) => path,
+        None => return,
+    };
+
+    let result = toml::to_string(value)
+        .map_err(|err| err.to_string())
+        .and_then(|contents| {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+            }
+            fs::write(&path, contents).map_err(|err| err.to_string())
+        });
+
+    if let Err(err) = result {
+        error!("Error writing {}: {}", path.display(), err);
+    }
+}
+
+// quirks are either a preset name or a table of individual flags
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum QuirksConfig {
+    Preset(String),
+    Custom(Quirks),
+}
+
+impl QuirksConfig {
+    pub fn quirks(&self) -> Option<Quirks> {
+        match self {
+            QuirksConfig::Preset(name) => {
+                let quirks = Quirks::preset(name);
+                if quirks.is_none() {
+                    warn!("Unknown quirks preset {}", name);
+                }
+                quirks
+            }
+            QuirksConfig::Custom(quirks) => Some(*quirks),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub ipf: u32,
+    pub timing: Timing,
+    pub fps: u32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font: Option<PathBuf>,
+    pub watch: WatchMode,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recent: Vec<PathBuf>,
+    // tables must come after plain values when saving
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quirks: Option<QuirksConfig>,
+    pub audio: AudioConfig,
+    pub log: LogConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            ipf: IPF_DEFAULT,
+            timing: Timing::Ipf,
+            fps: FPS_DEFAULT,
+            width: WINDOW_WIDTH,
+            height: WINDOW_HEIGHT,
+            theme: None,
+            filter: None,
+            font: None,
+            watch: WatchMode::Off,
+            recent: Vec::new(),
+            quirks: None,
+            audio: AudioConfig::default(),
+            log: LogConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Config {
+        load(CONFIG_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        save(CONFIG_FILE, self);
+    }
+
+    // quirks used when a ROM has none of its own
+    pub fn default_quirks(&self) -> Quirks {
+        self.quirks
+            .as_ref()
+            .and_then(|quirks| quirks.quirks())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RomSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipf: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quirks: Option<QuirksConfig>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub keys: HashMap<String, Vec<String>>,
+}
+
+// per-ROM settings keyed by the SHA-1 of the ROM image
+#[derive(Debug, Default)]
+pub struct RomStore {
+    roms: HashMap<String, RomSettings>,
+}
+
+impl RomStore {
+    pub fn load() -> RomStore {
+        RomStore {
+            roms: load(ROMS_FILE).unwrap_or_default(),
+        }
+    }
+
+    pub fn save(&self) {
+        save(ROMS_FILE, &self.roms);
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&RomSettings> {
+        self.roms.get(hash)
+    }
+
+    pub fn update<F: FnOnce(&mut RomSettings)>(&mut self, hash: &str, f: F) {
+        f(self.roms.entry(hash.to_owned()).or_default());
+        self.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use audio::Waveform;
+
+    #[test]
+    fn config_defaults() {
+        let config: Config = toml::from_str("ipf = 12\ntheme = \"amber\"").unwrap();
+        assert_eq!(config.ipf, 12);
+        assert_eq!(config.fps, FPS_DEFAULT);
+        assert_eq!(config.theme, Some("amber".to_owned()));
+        assert_eq!(config.quirks, None);
+        assert_eq!(config.audio, AudioConfig::default());
+        assert_eq!(config.log, LogConfig::default());
+    }
+
+    #[test]
+    fn log_config() {
+        let config: Config =
+            toml::from_str("[log]\nfilter = \"warn,cpu=debug\"\nfile = \"chip8.log\"").unwrap();
+        assert_eq!(config.log.filter, "warn,cpu=debug");
+        assert_eq!(config.log.file, Some(PathBuf::from("chip8.log")));
+        assert_eq!(config.log.max_size, LogConfig::default().max_size);
+
+        let contents = toml::to_string(&config).unwrap();
+        assert_eq!(toml::from_str::<Config>(&contents).unwrap(), config);
+    }
+
+    #[test]
+    fn audio_config() {
+        let mut config: Config =
+            toml::from_str("[audio]
+waveform = \"sine\"
+volume = 0.5").unwrap();
+        assert_eq!(config.audio.waveform, Waveform::Sine);
+        assert_eq!(config.audio.volume, 0.5);
+        assert_eq!(config.audio.frequency, AudioConfig::default().frequency);
+
+        config.quirks = Some(QuirksConfig::Custom(Quirks::chip8()));
+        let contents = toml::to_string(&config).unwrap();
+        assert_eq!(toml::from_str::<Config>(&contents).unwrap(), config);
+    }
+
+    #[test]
+    fn rom_settings_round_trip() {
+        let mut settings = RomSettings {
+            name: Some("pong".to_owned()),
+            ipf: Some(12),
+            quirks: Some(QuirksConfig::Custom(Quirks::chip8())),
+            ..RomSettings::default()
+        };
+        settings.keys.insert("1".to_owned(), vec!["Up".to_owned()]);
+
+        let mut roms = HashMap::new();
+        roms.insert("abc123".to_owned(), settings.clone());
+        roms.insert("def456".to_owned(), RomSettings::default());
+
+        let contents = toml::to_string(&roms).unwrap();
+        let parsed: HashMap<String, RomSettings> = toml::from_str(&contents).unwrap();
+        assert_eq!(parsed["abc123"], settings);
+        assert_eq!(parsed["def456"], RomSettings::default());
+    }
+
+    #[test]
+    fn quirks_presets() {
+        let settings: RomSettings = toml::from_str("quirks = \"schip\"").unwrap();
+        assert_eq!(settings.quirks.unwrap().quirks(), Some(Quirks::schip()));
+
+        let settings: RomSettings = toml::from_str("[quirks]\njump = true").unwrap();
+        let quirks = settings.quirks.unwrap().quirks().unwrap();
+        assert!(quirks.jump);
+        assert!(quirks.shift);
+    }
+}
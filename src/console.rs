@@ -0,0 +1,858 @@
+use cheat::Compare;
+use cpu::{Chip8, Chip8State, Fault, OpCode, Quirks};
+use debugger::{parse_address, Location};
+use log::LevelFilter;
+use logger::Logger;
+use sdl2::keyboard::Keycode;
+use speed::{Speed, Timing, FRAME_HZ};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const N_HISTORY: usize = 100;
+// search candidates listed after each search
+const N_CANDIDATES: usize = 8;
+
+// name, arguments and description, in the order help lists them
+const COMMANDS: [(&str, &str, &str); 24] = [
+    ("help", "", "list commands"),
+    ("load", "<path>", "load a ROM"),
+    ("reset", "", "restart the loaded ROM"),
+    ("pause", "", "pause execution"),
+    ("continue", "", "resume execution"),
+    ("step", "[n]", "execute n instructions"),
+    ("frames", "[n]", "run n frames"),
+    ("break", "[address]", "set a breakpoint, or list them"),
+    ("delete", "[address]", "remove a breakpoint, or all of them"),
+    ("watch", "[location]", "stop when a register or byte changes"),
+    ("unwatch", "<location>", "remove a watch"),
+    ("set", "<location> <value>", "write a register or memory byte"),
+    ("poke", "<address> <bytes>", "write hex bytes to memory"),
+    ("search", "[compare]", "start a memory search, or narrow it"),
+    ("freeze", "<location> <value>", "hold a register or byte at a value"),
+    ("unfreeze", "<location>", "release a frozen location"),
+    ("cheat", "[n]", "toggle a cheat, or list them"),
+    ("regs", "", "show the registers"),
+    ("hz", "<rate>", "set instructions per second"),
+    ("save", "<slot>", "save the machine state to a slot"),
+    ("restore", "<slot>", "restore the machine state from a slot"),
+    ("quirks", "<preset>", "use chip8, schip, xochip or default quirks"),
+    ("trace", "<on|off>", "log every executed instruction"),
+    ("quit", "", "exit the emulator"),
+];
+
+const QUIRKS: [&str; 4] = ["chip8", "schip", "xochip", "default"];
+const REGISTERS: [&str; 5] = ["I", "DT", "ST", "SP", "PC"];
+const COMPARES: [&str; 5] = ["eq", "changed", "unchanged", "inc", "dec"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Help,
+    Load(PathBuf),
+    Reset,
+    Pause,
+    Continue,
+    Step(u32),
+    Frames(u32),
+    Break(Option<usize>),
+    Delete(Option<usize>),
+    Watch(Option<Location>),
+    Unwatch(Location),
+    Set(Location, u16),
+    Poke(usize, Vec<u8>),
+    Search(Option<Compare>),
+    Freeze(Location, u16),
+    Unfreeze(Location),
+    Cheat(Option<usize>),
+    Regs,
+    Hz(u32),
+    Save(u8),
+    Restore(u8),
+    Quirks(Quirks),
+    Trace(bool),
+    Quit,
+}
+
+impl Command {
+    // whether it leaves the machine as it was, so can't put netplay peers out of step
+    pub fn read_only(&self) -> bool {
+        matches!(
+            self,
+            Command::Help
+                | Command::Break(None)
+                | Command::Delete(_)
+                | Command::Watch(None)
+                | Command::Unwatch(_)
+                | Command::Search(_)
+                | Command::Cheat(None)
+                | Command::Regs
+                | Command::Save(_)
+                | Command::Trace(_)
+                | Command::Quit
+        )
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Command, String> {
+        let line = line.trim();
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default().to_lowercase();
+        let args: Vec<&str> = words.collect();
+        let arg = |i: usize| {
+            args.get(i)
+                .cloned()
+                .ok_or_else(|| format!("usage: {}", usage(&name)))
+        };
+
+        let command = match name.as_str() {
+            "help" | "?" => Command::Help,
+            "load" => {
+                arg(0)?;
+                Command::Load(PathBuf::from(line[name.len()..].trim()))
+            }
+            "reset" => Command::Reset,
+            "pause" => Command::Pause,
+            "continue" | "c" => Command::Continue,
+            "step" | "s" => Command::Step(parse_count(args.first())?),
+            "frames" => Command::Frames(parse_count(args.first())?),
+            "break" | "b" => Command::Break(args.first().map(|a| parse_address(a)).transpose()?),
+            "delete" => Command::Delete(args.first().map(|a| parse_address(a)).transpose()?),
+            "watch" => Command::Watch(args.first().map(|a| a.parse()).transpose()?),
+            "unwatch" => Command::Unwatch(arg(0)?.parse()?),
+            "set" => Command::Set(arg(0)?.parse()?, parse_value(arg(1)?)?),
+            "poke" => {
+                arg(1)?;
+                Command::Poke(parse_address(arg(0)?)?, parse_bytes(&args[1..])?)
+            }
+            "search" => match args.first() {
+                Some(_) => Command::Search(Some(parse_compare(&args)?)),
+                None => Command::Search(None),
+            },
+            "freeze" => Command::Freeze(arg(0)?.parse()?, parse_value(arg(1)?)?),
+            "unfreeze" => Command::Unfreeze(arg(0)?.parse()?),
+            "cheat" => match args.first() {
+                Some(n) => Command::Cheat(Some(parse_count(Some(n))? as usize)),
+                None => Command::Cheat(None),
+            },
+            "regs" => Command::Regs,
+            "hz" => match parse_value(arg(0)?)? {
+                0 => return Err("rate must be at least 1".to_owned()),
+                hz => Command::Hz(u32::from(hz)),
+            },
+            "save" => Command::Save(parse_slot(arg(0)?)?),
+            "restore" => Command::Restore(parse_slot(arg(0)?)?),
+            "quirks" => {
+                let name = arg(0)?;
+                Command::Quirks(
+                    Quirks::preset(name).ok_or_else(|| format!("unknown quirks {}", name))?,
+                )
+            }
+            "trace" => match arg(0)?.to_lowercase().as_str() {
+                "on" => Command::Trace(true),
+                "off" => Command::Trace(false),
+                _ => return Err(format!("usage: {}", usage(&name))),
+            },
+            "quit" | "exit" => Command::Quit,
+            "" => return Err("empty command".to_owned()),
+            _ => return Err(format!("unknown command {}, try help", name)),
+        };
+        Ok(command)
+    }
+}
+
+fn usage(name: &str) -> String {
+    COMMANDS
+        .iter()
+        .find(|(command, _, _)| *command == name)
+        .map_or_else(|| name.to_owned(), |(name, args, _)| format!("{} {}", name, args))
+}
+
+// counts are decimal and default to one
+fn parse_count(arg: Option<&&str>) -> Result<u32, String> {
+    match arg {
+        Some(arg) => match arg.parse() {
+            Ok(0) | Err(_) => Err(format!("bad count {}", arg)),
+            Ok(n) => Ok(n),
+        },
+        None => Ok(1),
+    }
+}
+
+// values are decimal unless prefixed with 0x, # or $
+fn parse_value(s: &str) -> Result<u16, String> {
+    let hex = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .or_else(|| s.strip_prefix('#'))
+        .or_else(|| s.strip_prefix('$'));
+    match hex {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| format!("bad value {}", s))
+}
+
+// bytes are hex, either one per argument or run together
+fn parse_bytes(args: &[&str]) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for arg in args {
+        let hex = arg.trim_start_matches("0x");
+        if hex.is_empty() || hex.len() > 2 && hex.len() % 2 != 0 {
+            return Err(format!("bad bytes {}", arg));
+        }
+
+        let mut i = 0;
+        while i < hex.len() {
+            let end = (i + 2).min(hex.len());
+            let byte = hex
+                .get(i..end)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| format!("bad bytes {}", arg))?;
+            bytes.push(byte);
+            i = end;
+        }
+    }
+    Ok(bytes)
+}
+
+// eq <value>, changed, unchanged, inc or dec
+fn parse_compare(args: &[&str]) -> Result<Compare, String> {
+    let compare = match (args[0].to_lowercase().as_str(), args.get(1)) {
+        ("eq", Some(value)) => match parse_value(value)? {
+            value if value <= 0xFF => Compare::Equal(value as u8),
+            _ => return Err(format!("bad byte {}", value)),
+        },
+        ("changed", None) => Compare::Changed,
+        ("unchanged", None) => Compare::Unchanged,
+        ("inc", None) => Compare::Increased,
+        ("dec", None) => Compare::Decreased,
+        _ => return Err(format!("usage: search [{}]", COMPARES.join("|"))),
+    };
+    Ok(compare)
+}
+
+fn parse_slot(s: &str) -> Result<u8, String> {
+    s.parse().map_err(|_| format!("bad slot {}", s))
+}
+
+// what console commands act on, i.e. the windowed VM or the headless runner
+pub trait Host {
+    fn cpu(&mut self) -> &mut Chip8;
+    fn speed(&mut self) -> &mut Speed;
+    fn states(&mut self) -> &mut HashMap<u8, Chip8State>;
+    fn logger(&self) -> &Logger;
+    fn load(&mut self, path: &Path);
+    fn reset(&mut self);
+    fn pause(&mut self, paused: bool);
+    fn run_frames(&mut self, n: u32);
+    // applies quirks chosen at runtime, remembering them where the host can
+    fn set_quirks(&mut self, quirks: Quirks);
+    fn fault(&mut self, fault: Fault);
+    fn quit(&mut self);
+}
+
+pub fn execute<H: Host>(host: &mut H, command: Command) {
+    match command {
+        Command::Help => {
+            for (name, args, description) in COMMANDS.iter() {
+                info!("{:<8} {:<18} {}", name, args, description);
+            }
+        }
+        Command::Load(path) => host.load(&path),
+        Command::Reset => host.reset(),
+        Command::Pause => host.pause(true),
+        Command::Continue => host.pause(false),
+        Command::Step(n) => {
+            host.pause(true);
+            for _ in 0..n {
+                if let Err(fault) = host.cpu().execute_cycle() {
+                    host.fault(fault);
+                    return;
+                }
+            }
+            let state = host.cpu().state();
+            let (op, params) = OpCode::disassemble(state.fetch(state.pc()));
+            info!("{:04X} {} {}", state.pc(), op, params);
+        }
+        Command::Frames(n) => host.run_frames(n),
+        Command::Break(Some(address)) => {
+            host.cpu().debugger_mut().add_breakpoint(address);
+            info!("Breakpoint at {:04X}", address);
+        }
+        Command::Break(None) => {
+            let breakpoints: Vec<String> = host
+                .cpu()
+                .debugger()
+                .breakpoints()
+                .map(|address| format!("{:04X}", address))
+                .collect();
+            info!("Breakpoints: {}", list(&breakpoints));
+        }
+        Command::Delete(Some(address)) => {
+            if host.cpu().debugger_mut().remove_breakpoint(address) {
+                info!("Deleted breakpoint at {:04X}", address);
+            } else {
+                warn!("No breakpoint at {:04X}", address);
+            }
+        }
+        Command::Delete(None) => {
+            host.cpu().debugger_mut().clear_breakpoints();
+            info!("Deleted all breakpoints");
+        }
+        Command::Watch(Some(location)) => {
+            if host.cpu().watch(location) {
+                info!("Watching {}", location);
+            } else {
+                warn!("Can't watch {}", location);
+            }
+        }
+        Command::Watch(None) => {
+            let watches: Vec<String> = host
+                .cpu()
+                .debugger()
+                .watches()
+                .map(Location::to_string)
+                .collect();
+            info!("Watches: {}", list(&watches));
+        }
+        Command::Unwatch(location) => {
+            if host.cpu().debugger_mut().remove_watch(location) {
+                info!("Stopped watching {}", location);
+            } else {
+                warn!("Not watching {}", location);
+            }
+        }
+        Command::Set(location, value) => match host.cpu().set(location, value) {
+            Ok(()) => info!("{} = {:02X}", location, value),
+            Err(err) => warn!("Error setting {}: {}", location, err),
+        },
+        Command::Poke(address, bytes) => match host.cpu().poke(address, &bytes) {
+            Ok(()) => info!("Wrote {} bytes at {:04X}", bytes.len(), address),
+            Err(err) => warn!("Error writing {:04X}: {}", address, err),
+        },
+        Command::Search(compare) => {
+            let search = host.cpu().search(compare);
+            let candidates: Vec<String> = search
+                .candidates()
+                .iter()
+                .take(N_CANDIDATES)
+                .map(|address| format!("{:04X}", address))
+                .collect();
+            let more = search.candidates().len().saturating_sub(N_CANDIDATES);
+            match (compare, more) {
+                (None, _) => info!("Searching {} bytes", search.candidates().len()),
+                (Some(_), 0) => info!("Candidates: {}", list(&candidates)),
+                (Some(_), more) => info!("Candidates: {} and {} more", list(&candidates), more),
+            }
+        }
+        Command::Freeze(location, value) => {
+            host.cpu().cheats_mut().freeze(location, value);
+            info!("Froze {} at {:02X}", location, value);
+        }
+        Command::Unfreeze(location) => {
+            if host.cpu().cheats_mut().unfreeze(location) {
+                info!("Released {}", location);
+            } else {
+                warn!("{} isn't frozen", location);
+            }
+        }
+        Command::Cheat(Some(n)) => match host.cpu().cheats_mut().toggle(n - 1) {
+            Some(cheat) => info!("{}: {}", cheat.name, if cheat.enabled { "on" } else { "off" }),
+            None => warn!("No cheat {}", n),
+        },
+        Command::Cheat(None) => {
+            let cheats = host.cpu().cheats().cheats();
+            if cheats.is_empty() {
+                info!("Cheats: none");
+            }
+            for (i, cheat) in cheats.iter().enumerate() {
+                info!("{} {}", i + 1, cheat);
+            }
+        }
+        Command::Regs => registers(host.cpu().state()),
+        Command::Hz(hz) => {
+            let speed = host.speed();
+            speed.timing = Timing::Ipf;
+            speed.set_ipf((hz + FRAME_HZ / 2) / FRAME_HZ);
+            info!("Speed: {} IPF ({} Hz)", speed.ipf, speed.ipf * FRAME_HZ);
+        }
+        Command::Save(slot) => {
+            let state = host.cpu().save_state();
+            host.states().insert(slot, state);
+            info!("Saved state {}", slot);
+        }
+        Command::Restore(slot) => match host.states().get(&slot).cloned() {
+            Some(state) => {
+                host.cpu().load_state(state);
+                info!("Restored state {}", slot);
+            }
+            None => warn!("No state saved in slot {}", slot),
+        },
+        Command::Quirks(quirks) => {
+            host.set_quirks(quirks);
+            info!("Quirks: {:?}", quirks);
+        }
+        Command::Trace(on) => {
+            host.cpu().debugger_mut().set_trace(on);
+            if on {
+                host.logger().override_level("cpu", LevelFilter::Trace);
+            } else {
+                host.logger().restore_level("cpu");
+            }
+            info!("Trace: {}", if on { "on" } else { "off" });
+        }
+        Command::Quit => host.quit(),
+    }
+}
+
+fn list(items: &[String]) -> String {
+    if items.is_empty() {
+        "none".to_owned()
+    } else {
+        items.join(" ")
+    }
+}
+
+pub fn registers(state: &Chip8State) {
+    let hex = |regs: &[u8]| {
+        regs.iter()
+            .map(|r| format!("{:02X}", r))
+            .collect::<Vec<String>>()
+            .join(" ")
+    };
+    let v = state.registers();
+    info!("V0-V7 {}", hex(&v[..8]));
+    info!("V8-VF {}", hex(&v[8..]));
+    info!(
+        "PC {:04X} I {:04X} SP {:X} DT {:02X} ST {:02X}",
+        state.pc(),
+        state.i(),
+        state.sp(),
+        state.dt(),
+        state.st()
+    );
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edit {
+    Submit,
+    Close,
+    Backspace,
+    Previous,
+    Next,
+    Complete,
+}
+
+impl Edit {
+    pub fn from_key(code: Keycode) -> Option<Edit> {
+        match code {
+            Keycode::Return | Keycode::KpEnter => Some(Edit::Submit),
+            Keycode::Escape => Some(Edit::Close),
+            Keycode::Backspace => Some(Edit::Backspace),
+            Keycode::Up => Some(Edit::Previous),
+            Keycode::Down => Some(Edit::Next),
+            Keycode::Tab => Some(Edit::Complete),
+            _ => None,
+        }
+    }
+}
+
+// the command line typed into the log panel
+#[derive(Default)]
+pub struct Console {
+    input: String,
+    history: Vec<String>,
+    // the history entry being edited, None for a new line
+    cursor: Option<usize>,
+    active: bool,
+}
+
+impl Console {
+    pub fn new() -> Console {
+        Self::default()
+    }
+
+    #[inline(always)]
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn open(&mut self) {
+        self.active = true;
+    }
+
+    pub fn type_text(&mut self, text: &str) {
+        self.input.push_str(text);
+    }
+
+    // returns a submitted line
+    pub fn edit(&mut self, edit: Edit) -> Option<String> {
+        match edit {
+            Edit::Submit => {
+                let line = self.input.trim().to_owned();
+                self.input.clear();
+                self.cursor = None;
+                if line.is_empty() {
+                    return None;
+                }
+
+                if self.history.last() != Some(&line) {
+                    self.history.push(line.clone());
+                    if self.history.len() > N_HISTORY {
+                        self.history.remove(0);
+                    }
+                }
+                return Some(line);
+            }
+            Edit::Close => {
+                self.active = false;
+                self.input.clear();
+                self.cursor = None;
+            }
+            Edit::Backspace => {
+                self.input.pop();
+            }
+            Edit::Previous if !self.history.is_empty() => {
+                let i = self
+                    .cursor
+                    .map_or(self.history.len() - 1, |i| i.saturating_sub(1));
+                self.cursor = Some(i);
+                self.input = self.history[i].clone();
+            }
+            Edit::Next => match self.cursor {
+                Some(i) if i + 1 < self.history.len() => {
+                    self.cursor = Some(i + 1);
+                    self.input = self.history[i + 1].clone();
+                }
+                Some(_) => {
+                    self.cursor = None;
+                    self.input.clear();
+                }
+                None => (),
+            },
+            Edit::Complete => self.input = complete(&self.input),
+            Edit::Previous => (),
+        }
+        None
+    }
+}
+
+// extends the last word to the longest prefix shared by its candidates
+pub fn complete(input: &str) -> String {
+    let (head, word) = match input.rfind(' ') {
+        Some(i) => (&input[..=i], &input[i + 1..]),
+        None => ("", input),
+    };
+    let words: Vec<&str> = head.split_whitespace().collect();
+
+    let candidates: Vec<String> = match words.as_slice() {
+        [] => COMMANDS.iter().map(|(name, _, _)| name.to_string()).collect(),
+        ["watch"] | ["unwatch"] | ["set"] | ["freeze"] | ["unfreeze"] => (0..16)
+            .map(|x| format!("V{:X}", x))
+            .chain(REGISTERS.iter().map(|r| r.to_string()))
+            .collect(),
+        ["search"] => COMPARES.iter().map(|c| c.to_string()).collect(),
+        ["quirks"] => QUIRKS.iter().map(|q| q.to_string()).collect(),
+        ["trace"] => vec!["on".to_owned(), "off".to_owned()],
+        ["load", ..] => paths(&input[input.find(' ').unwrap_or(0) + 1..]),
+        _ => Vec::new(),
+    };
+
+    let word = match words.first() {
+        Some(&"load") => &input[input.find(' ').unwrap_or(0) + 1..],
+        _ => word,
+    };
+    let matches: Vec<&String> = candidates
+        .iter()
+        .filter(|c| c.to_lowercase().starts_with(&word.to_lowercase()))
+        .collect();
+    let first = match matches.first() {
+        Some(first) => first,
+        None => return input.to_owned(),
+    };
+
+    let mut prefix = first.to_string();
+    for candidate in &matches[1..] {
+        while !candidate.to_lowercase().starts_with(&prefix.to_lowercase()) {
+            prefix.pop();
+        }
+    }
+
+    let head = &input[..input.len() - word.len()];
+    let space = if matches.len() == 1 && !prefix.ends_with('/') {
+        " "
+    } else {
+        ""
+    };
+    format!("{}{}{}", head, prefix, space)
+}
+
+// files and directories starting with a partial path
+fn paths(partial: &str) -> Vec<String> {
+    let (dir, _) = match partial.rfind('/') {
+        Some(i) => partial.split_at(i + 1),
+        None => ("", partial),
+    };
+    let entries = match fs::read_dir(if dir.is_empty() { "." } else { dir }) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut paths: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            format!("{}{}{}", dir, name, slash)
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opcode::Register;
+    use std::env;
+
+    struct TestHost {
+        cpu: Chip8,
+        speed: Speed,
+        states: HashMap<u8, Chip8State>,
+        log: Logger,
+        paused: bool,
+    }
+
+    impl Host for TestHost {
+        fn cpu(&mut self) -> &mut Chip8 {
+            &mut self.cpu
+        }
+
+        fn speed(&mut self) -> &mut Speed {
+            &mut self.speed
+        }
+
+        fn states(&mut self) -> &mut HashMap<u8, Chip8State> {
+            &mut self.states
+        }
+
+        fn logger(&self) -> &Logger {
+            &self.log
+        }
+
+        fn load(&mut self, _path: &Path) {}
+
+        fn reset(&mut self) {
+            self.cpu.soft_reset();
+        }
+
+        fn pause(&mut self, paused: bool) {
+            self.paused = paused;
+        }
+
+        fn run_frames(&mut self, n: u32) {
+            for _ in 0..n {
+                self.cpu.run_frame(self.speed.ipf).unwrap();
+                if self.cpu.take_stop().is_some() {
+                    return;
+                }
+            }
+        }
+
+        fn set_quirks(&mut self, quirks: Quirks) {
+            self.cpu.set_quirks(quirks);
+        }
+
+        fn fault(&mut self, _fault: Fault) {}
+
+        fn quit(&mut self) {}
+    }
+
+    fn run(host: &mut TestHost, line: &str) {
+        execute(host, line.parse().unwrap());
+    }
+
+    #[test]
+    fn parse_commands() {
+        let parse = |line: &str| line.parse::<Command>();
+        assert_eq!(parse("break 0x23A"), Ok(Command::Break(Some(0x23A))));
+        assert_eq!(parse("b 23a"), Ok(Command::Break(Some(0x23A))));
+        assert_eq!(
+            parse("watch I"),
+            Ok(Command::Watch(Some(Location::Register(Register::I))))
+        );
+        assert_eq!(
+            parse("set V3 0x10"),
+            Ok(Command::Set(Location::Register(Register::V(3)), 0x10))
+        );
+        assert_eq!(parse("set VA 16"), parse("set va 0x10"));
+        assert_eq!(parse("poke 0x300 ff"), Ok(Command::Poke(0x300, vec![0xFF])));
+        assert_eq!(
+            parse("poke 300 a0b1 2"),
+            Ok(Command::Poke(0x300, vec![0xA0, 0xB1, 0x2]))
+        );
+        assert_eq!(parse("step"), Ok(Command::Step(1)));
+        assert_eq!(parse("step 100"), Ok(Command::Step(100)));
+        assert_eq!(parse("hz 800"), Ok(Command::Hz(800)));
+        assert_eq!(parse("save 1"), Ok(Command::Save(1)));
+        assert_eq!(parse("quirks schip"), Ok(Command::Quirks(Quirks::schip())));
+        assert_eq!(parse("trace on"), Ok(Command::Trace(true)));
+        assert_eq!(parse("search"), Ok(Command::Search(None)));
+        assert!(parse("regs").unwrap().read_only());
+        assert!(parse("break").unwrap().read_only());
+        assert!(!parse("break 0x200").unwrap().read_only());
+        assert!(!parse("set V0 1").unwrap().read_only());
+        assert_eq!(
+            parse("search eq 0x3"),
+            Ok(Command::Search(Some(Compare::Equal(3))))
+        );
+        assert_eq!(
+            parse("search dec"),
+            Ok(Command::Search(Some(Compare::Decreased)))
+        );
+        assert_eq!(
+            parse("freeze [3F0] 3"),
+            Ok(Command::Freeze(Location::Memory(0x3F0), 3))
+        );
+        assert_eq!(parse("cheat 2"), Ok(Command::Cheat(Some(2))));
+        assert_eq!(
+            parse("load roms/PONG 2.ch8"),
+            Ok(Command::Load(PathBuf::from("roms/PONG 2.ch8")))
+        );
+
+        assert_eq!(parse("load"), Err("usage: load <path>".to_owned()));
+        assert!(parse("poke 300 abc").is_err());
+        assert!(parse("step 0").is_err());
+        assert!(parse("quirks fast").is_err());
+        assert!(parse("trace maybe").is_err());
+        assert!(parse("search eq 256").is_err());
+        assert!(parse("search inc 1").is_err());
+        assert!(parse("cheat 0").is_err());
+        assert!(parse("jump 200").is_err());
+        assert!(parse("  ").is_err());
+    }
+
+    #[test]
+    fn complete_words() {
+        assert_eq!(complete("br"), "break ");
+        assert_eq!(complete("re"), "re");
+        assert_eq!(complete("res"), "res");
+        assert_eq!(complete("rese"), "reset ");
+        assert_eq!(complete("set v"), "set V");
+        assert_eq!(complete("set vf"), "set VF ");
+        assert_eq!(complete("quirks x"), "quirks xochip ");
+        assert_eq!(complete("trace o"), "trace o");
+        assert_eq!(complete("search ch"), "search changed ");
+        assert_eq!(complete("freeze v"), "freeze V");
+        assert_eq!(complete("step 1"), "step 1");
+
+        let dir = env::temp_dir().join(format!("chip8-complete-{}", std::process::id()));
+        fs::create_dir_all(dir.join("roms")).unwrap();
+        fs::write(dir.join("roms/PONG.ch8"), [0u8]).unwrap();
+        let load = format!("load {}/r", dir.display());
+        assert_eq!(complete(&load), format!("load {}/roms/", dir.display()));
+        assert_eq!(
+            complete(&format!("{}oms/P", load)),
+            format!("load {}/roms/PONG.ch8 ", dir.display())
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn history() {
+        let mut console = Console::new();
+        console.open();
+        for line in &["step", "regs", "regs"] {
+            console.type_text(line);
+            assert_eq!(console.edit(Edit::Submit), Some(line.to_string()));
+        }
+        assert_eq!(console.edit(Edit::Submit), None);
+
+        console.edit(Edit::Previous);
+        assert_eq!(console.input(), "regs");
+        console.edit(Edit::Previous);
+        console.edit(Edit::Previous);
+        assert_eq!(console.input(), "step");
+        console.edit(Edit::Next);
+        assert_eq!(console.input(), "regs");
+        console.edit(Edit::Next);
+        assert_eq!(console.input(), "");
+
+        console.edit(Edit::Close);
+        assert!(!console.active());
+    }
+
+    #[test]
+    fn execute_commands() {
+        let mut host = TestHost {
+            cpu: Chip8::new(),
+            speed: Speed::new(10, Timing::Vip),
+            states: HashMap::new(),
+            log: Logger::new(10),
+            paused: false,
+        };
+        // 0200: V0 += 1, jump 0200
+        host.cpu.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+
+        run(&mut host, "step 4");
+        assert!(host.paused);
+        assert_eq!(host.cpu.state().registers()[0], 2);
+
+        run(&mut host, "save 1");
+        run(&mut host, "set V0 0x10");
+        run(&mut host, "poke 300 12 34");
+        assert_eq!(host.cpu.state().registers()[0], 0x10);
+        assert_eq!(&host.cpu.state().memory()[0x300..0x302], &[0x12, 0x34]);
+        run(&mut host, "restore 1");
+        assert_eq!(host.cpu.state().registers()[0], 2);
+
+        run(&mut host, "hz 800");
+        assert_eq!(host.speed.timing, Timing::Ipf);
+        assert_eq!(host.speed.ipf, 13);
+
+        run(&mut host, "break 202");
+        run(&mut host, "frames 5");
+        assert_eq!(host.cpu.state().pc(), 0x202);
+        assert_eq!(host.cpu.state().registers()[0], 3);
+        run(&mut host, "delete");
+
+        run(&mut host, "watch V0");
+        run(&mut host, "frames 5");
+        assert_eq!(host.cpu.state().pc(), 0x202);
+        assert_eq!(host.cpu.state().registers()[0], 4);
+        run(&mut host, "unwatch V0");
+        run(&mut host, "frames 1");
+        assert_eq!(host.cpu.state().registers()[0], 4 + 6);
+
+        // 0200: V0 += 1, jump 0200 counts up unless V0 is frozen
+        run(&mut host, "search");
+        run(&mut host, "frames 1");
+        run(&mut host, "search inc");
+        assert!(host.cpu.cheats().search().unwrap().candidates().is_empty());
+        run(&mut host, "freeze V0 0x20");
+        run(&mut host, "frames 1");
+        assert_eq!(host.cpu.state().registers()[0], 0x20 + 6);
+        run(&mut host, "cheat 1");
+        assert!(!host.cpu.cheats().cheats()[0].enabled);
+        run(&mut host, "unfreeze V0");
+        assert!(host.cpu.cheats().cheats().is_empty());
+
+        run(&mut host, "quirks chip8");
+        assert_eq!(host.cpu.quirks(), Quirks::chip8());
+        run(&mut host, "trace on");
+        assert!(host.cpu.debugger().trace());
+        assert_eq!(host.log.level("cpu"), LevelFilter::Trace);
+        run(&mut host, "trace off");
+        assert!(!host.cpu.debugger().trace());
+        assert_eq!(host.log.level("cpu"), LevelFilter::Info);
+    }
+}
@@ -0,0 +1,761 @@
+use console::Host;
+use cpu::Chip8State;
+use debugger::{Location, Stop};
+use screenshot::{self, MAX_SCALE};
+use serde_json::{self, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_PORT: u16 = 8643;
+// how long a request waits for the emulator to pick it up
+const REPLY_TIMEOUT_SECS: u64 = 10;
+const READ_TIMEOUT_SECS: u64 = 5;
+const WRITE_TIMEOUT_SECS: u64 = 5;
+// how often an idle event stream is written to, to notice clients leaving
+const HEARTBEAT_SECS: u64 = 15;
+const MAX_BODY: usize = 64 * 1024;
+// requests run on the emulator's thread, so a step or frames count is kept
+// to what runs without freezing the window
+const MAX_STEPS: u64 = 100_000;
+const MAX_FRAMES: u64 = 600;
+
+// json-rpc error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const EMULATOR_ERROR: i64 = -32000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: &str) -> RpcError {
+        RpcError {
+            code,
+            message: message.to_owned(),
+        }
+    }
+
+    fn params(message: &str) -> RpcError {
+        RpcError::new(INVALID_PARAMS, message)
+    }
+}
+
+// pushed to every client streaming /events
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Stop(Stop),
+    Fault(String),
+}
+
+impl Event {
+    fn name(&self) -> &'static str {
+        match self {
+            Event::Stop(Stop::Breakpoint(_)) => "breakpoint",
+            Event::Stop(Stop::Watch { .. }) => "watch",
+            Event::Fault(_) => "error",
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            Event::Stop(Stop::Breakpoint(address)) => json!({ "address": address }),
+            Event::Stop(Stop::Watch { location, old, new }) => json!({
+                "location": location.to_string(),
+                "old": old,
+                "new": new,
+            }),
+            Event::Fault(message) => json!({ "message": message }),
+        }
+    }
+}
+
+fn number(params: &Value, name: &str, default: Option<u64>) -> Result<u64, RpcError> {
+    match params.get(name) {
+        Some(value) => value
+            .as_u64()
+            .ok_or_else(|| RpcError::params(&format!("{} must be a number", name))),
+        None => default.ok_or_else(|| RpcError::params(&format!("missing {}", name))),
+    }
+}
+
+fn string<'a>(params: &'a Value, name: &str) -> Result<&'a str, RpcError> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::params(&format!("missing {}", name)))
+}
+
+fn count(params: &Value, max: u64) -> Result<u32, RpcError> {
+    match number(params, "n", Some(1))? {
+        n if n <= max => Ok(n as u32),
+        _ => Err(RpcError::params(&format!("n must be at most {}", max))),
+    }
+}
+
+fn key(params: &Value) -> Result<usize, RpcError> {
+    match number(params, "key", None)? {
+        key if key < 16 => Ok(key as usize),
+        key => Err(RpcError::params(&format!("bad key {}", key))),
+    }
+}
+
+// a memory range from an address and a length, which must fit in memory
+fn range(params: &Value, length: u64) -> Result<Range<usize>, RpcError> {
+    let address = number(params, "address", None)?;
+    let end = address.saturating_add(length);
+    if end > Chip8State::MEMORY_SIZE as u64 {
+        return Err(RpcError::params("address out of range"));
+    }
+    Ok(address as usize..end as usize)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn registers<H: Host>(host: &mut H) -> Value {
+    let state = host.cpu().state();
+    json!({
+        "v": state.registers(),
+        "i": state.i(),
+        "pc": state.pc(),
+        "sp": state.sp(),
+        "dt": state.dt(),
+        "st": state.st(),
+        "stack": &state.stack()[..state.sp()],
+    })
+}
+
+// runs one request against the emulator
+pub fn dispatch<H: Host>(host: &mut H, method: &str, params: &Value) -> Result<Value, RpcError> {
+    let emulator = |err: String| RpcError::new(EMULATOR_ERROR, &err);
+    let result = match method {
+        "load" => {
+            host.load(Path::new(string(params, "path")?));
+            Value::Null
+        }
+        "reset" => {
+            host.reset();
+            Value::Null
+        }
+        "pause" => {
+            host.pause(true);
+            Value::Null
+        }
+        "resume" => {
+            host.pause(false);
+            Value::Null
+        }
+        // instructions, stopping early on a fault
+        "step" => {
+            host.pause(true);
+            for _ in 0..count(params, MAX_STEPS)? {
+                if let Err(fault) = host.cpu().execute_cycle() {
+                    let message = fault.to_string();
+                    host.fault(fault);
+                    return Err(emulator(message));
+                }
+            }
+            json!({ "pc": host.cpu().state().pc() })
+        }
+        "frames" => {
+            host.run_frames(count(params, MAX_FRAMES)?);
+            json!({ "pc": host.cpu().state().pc() })
+        }
+        "read_memory" => {
+            let range = range(params, number(params, "length", Some(1))?)?;
+            json!(host.cpu().state().memory()[range])
+        }
+        "write_memory" => {
+            let bytes: Vec<u8> = params
+                .get("bytes")
+                .and_then(|bytes| serde_json::from_value(bytes.clone()).ok())
+                .ok_or_else(|| RpcError::params("bytes must be a list of bytes"))?;
+            let range = range(params, bytes.len() as u64)?;
+            host.cpu()
+                .poke(range.start, &bytes)
+                .map_err(|err| emulator(err.to_string()))?;
+            Value::Null
+        }
+        "registers" => registers(host),
+        "quit" => {
+            host.quit();
+            Value::Null
+        }
+        "set_register" => {
+            let location: Location = string(params, "register")?
+                .parse()
+                .map_err(|err: String| RpcError::params(&err))?;
+            if let Location::Memory(_) = location {
+                return Err(RpcError::params("expected a register"));
+            }
+            let value = number(params, "value", None)? as u16;
+            host.cpu()
+                .set(location, value)
+                .map_err(|err| emulator(err.to_string()))?;
+            registers(host)
+        }
+        "press" => {
+            host.cpu().press_key(key(params)?);
+            Value::Null
+        }
+        "release" => {
+            host.cpu().release_key(key(params)?);
+            Value::Null
+        }
+        "framebuffer" => {
+            let video = host.cpu().state().video().to_vec();
+            match params
+                .get("format")
+                .and_then(Value::as_str)
+                .unwrap_or("bits")
+            {
+                // a row-major bit per pixel, most significant bit first
+                "bits" => json!({
+                    "width": Chip8State::WIDTH,
+                    "height": Chip8State::HEIGHT,
+                    "bits": hex(&video),
+                }),
+                "png" => {
+                    let mut png = Vec::new();
+                    let scale = number(params, "scale", Some(1))?.clamp(1, MAX_SCALE.into());
+                    screenshot::encode(&mut png, &video, scale as u32)
+                        .map_err(|err| emulator(err.to_string()))?;
+                    json!({ "png": hex(&png) })
+                }
+                format => return Err(RpcError::params(&format!("bad format {}", format))),
+            }
+        }
+        _ => return Err(RpcError::new(METHOD_NOT_FOUND, "method not found")),
+    };
+    Ok(result)
+}
+
+// a request waiting for the emulator, answered from its run loop
+pub struct Call {
+    method: String,
+    params: Value,
+    reply: Sender<Result<Value, RpcError>>,
+}
+
+impl Call {
+    pub fn answer<H: Host>(self, host: &mut H) {
+        let result = dispatch(host, &self.method, &self.params);
+        // the client may have given up waiting
+        let _ = self.reply.send(result);
+    }
+
+    pub fn refuse(self, message: &str) {
+        let _ = self.reply.send(Err(RpcError::new(EMULATOR_ERROR, message)));
+    }
+
+    // whether it only looks at the machine, so can't put netplay peers out of step
+    pub fn read_only(&self) -> bool {
+        matches!(
+            self.method.as_str(),
+            "registers" | "read_memory" | "framebuffer"
+        )
+    }
+}
+
+type Subscribers = Arc<Mutex<Vec<Sender<Event>>>>;
+
+// a json-rpc over http server on localhost, handling connections on its own
+// threads so the emulator only ever polls it:
+//   POST /rpc             {"jsonrpc": "2.0", "id": 1, "method": "step", "params": {"n": 10}}
+//   GET /framebuffer.png  the screen, scaled by ?scale=N
+//   GET /events           server-sent breakpoint, watch and error events
+pub struct Server {
+    address: SocketAddr,
+    calls: Receiver<Call>,
+    subscribers: Subscribers,
+}
+
+impl Server {
+    pub fn bind(port: u16) -> io::Result<Server> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        let address = listener.local_addr()?;
+        let (sender, calls) = mpsc::channel();
+        let subscribers = Subscribers::default();
+
+        let connections = subscribers.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        warn!("Control connection failed: {}", err);
+                        continue;
+                    }
+                };
+                let (sender, subscribers) = (sender.clone(), connections.clone());
+                thread::spawn(move || {
+                    if let Err(err) = handle(stream, &sender, &subscribers) {
+                        debug!("Control connection closed: {}", err);
+                    }
+                });
+            }
+        });
+
+        Ok(Server {
+            address,
+            calls,
+            subscribers,
+        })
+    }
+
+    // binds the server, logging where it listens or why it couldn't
+    pub fn start(port: u16) -> Option<Server> {
+        match Server::bind(port) {
+            Ok(server) => {
+                info!("Control server listening on {}", server.address());
+                Some(server)
+            }
+            Err(err) => {
+                error!("Control server failed: {}", err);
+                None
+            }
+        }
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    // requests that have arrived since the last poll, never blocking
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.try_iter().collect()
+    }
+
+    pub fn publish(&self, event: &Event) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+fn call(sender: &Sender<Call>, method: &str, params: Value) -> Result<Value, RpcError> {
+    let (reply, result) = mpsc::channel();
+    let call = Call {
+        method: method.to_owned(),
+        params,
+        reply,
+    };
+    let busy = || RpcError::new(EMULATOR_ERROR, "emulator not responding");
+    sender.send(call).map_err(|_| busy())?;
+    result
+        .recv_timeout(Duration::from_secs(REPLY_TIMEOUT_SECS))
+        .map_err(|_| busy())?
+}
+
+fn rpc(sender: &Sender<Call>, body: &[u8]) -> Value {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(err) => {
+            let error = RpcError::new(PARSE_ERROR, &err.to_string());
+            return response(Value::Null, Err(error));
+        }
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let result = match request.get("method").and_then(Value::as_str) {
+        Some(method) => {
+            let params = request.get("params").cloned().unwrap_or(Value::Null);
+            call(sender, method, params)
+        }
+        None => Err(RpcError::new(INVALID_REQUEST, "missing method")),
+    };
+    response(id, result)
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(err) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": err.code, "message": err.message },
+        }),
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, kind: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        kind,
+        body.len()
+    )?;
+    stream.write_all(body)
+}
+
+// a Host header naming this machine, with or without a port
+fn local_host(host: &str) -> bool {
+    let name = match host.rfind(':') {
+        Some(i) if !host.ends_with(']') => &host[..i],
+        _ => host,
+    };
+    matches!(
+        name.to_lowercase().as_str(),
+        "localhost" | "127.0.0.1" | "[::1]"
+    )
+}
+
+fn local_origin(origin: &str) -> bool {
+    origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"))
+        .is_some_and(local_host)
+}
+
+fn handle(
+    mut stream: TcpStream,
+    sender: &Sender<Call>,
+    subscribers: &Subscribers,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(READ_TIMEOUT_SECS)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut request = line.split_whitespace();
+    let (method, target) = (request.next().unwrap_or(""), request.next().unwrap_or(""));
+    let mut length = 0;
+    let (mut json, mut local) = (false, true);
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        let mut split = header.splitn(2, ':');
+        if let (Some(name), Some(value)) = (split.next(), split.next()) {
+            let value = value.trim();
+            match name.trim().to_lowercase().as_str() {
+                "content-length" => length = value.parse().unwrap_or(0),
+                "content-type" => json = value.split(';').next() == Some("application/json"),
+                "host" => local &= local_host(value),
+                "origin" => local &= local_origin(value),
+                _ => (),
+            }
+        }
+    }
+    // web pages can't reach the server, whether cross-site or by rebinding dns
+    if !local {
+        return respond(&mut stream, "403 Forbidden", "text/plain", b"");
+    }
+    if length > MAX_BODY {
+        return respond(&mut stream, "413 Payload Too Large", "text/plain", b"");
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    let mut split = target.splitn(2, '?');
+    let (path, query) = (split.next().unwrap_or(""), split.next().unwrap_or(""));
+    match (method, path) {
+        ("POST", "/") | ("POST", "/rpc") if !json => {
+            respond(&mut stream, "415 Unsupported Media Type", "text/plain", b"")
+        }
+        ("POST", "/") | ("POST", "/rpc") => {
+            let response = rpc(sender, &body).to_string();
+            respond(
+                &mut stream,
+                "200 OK",
+                "application/json",
+                response.as_bytes(),
+            )
+        }
+        ("GET", "/framebuffer.png") => {
+            let scale = query
+                .split('&')
+                .find(|param| param.starts_with("scale="))
+                .map_or(1, |param| param[6..].parse().unwrap_or(1))
+                .clamp(1, MAX_SCALE);
+            let params = json!({ "format": "png", "scale": scale });
+            let png = call(sender, "framebuffer", params)
+                .ok()
+                .and_then(|result| unhex(result["png"].as_str()?));
+            match png {
+                Some(png) => respond(&mut stream, "200 OK", "image/png", &png),
+                None => respond(&mut stream, "503 Service Unavailable", "text/plain", b""),
+            }
+        }
+        ("GET", "/events") => {
+            let (events, received) = mpsc::channel();
+            subscribers.lock().unwrap().push(events);
+            stream.set_write_timeout(Some(Duration::from_secs(WRITE_TIMEOUT_SECS)))?;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n"
+            )?;
+            stream.flush()?;
+            // runs until the client goes away, which a comment sent while idle
+            // notices between events
+            loop {
+                match received.recv_timeout(Duration::from_secs(HEARTBEAT_SECS)) {
+                    Ok(event) => write!(
+                        stream,
+                        "event: {}\ndata: {}\n\n",
+                        event.name(),
+                        event.to_json()
+                    )?,
+                    Err(RecvTimeoutError::Timeout) => write!(stream, ":\n\n")?,
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+                stream.flush()?;
+            }
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Config;
+    use headless::Headless;
+    use logger::Logger;
+
+    fn headless() -> Headless {
+        let log = Box::leak(Box::new(Logger::new(10)));
+        let mut headless = Headless::with_config(log, Config::default());
+        // V0 = 2A, draw the font sprite for 0, then loop
+        headless
+            .cpu()
+            .load_rom(&[0x60, 0x2A, 0xA0, 0x00, 0xD1, 0x15, 0x12, 0x06])
+            .unwrap();
+        headless
+    }
+
+    // sends one http request and returns the status line and body
+    fn request(address: SocketAddr, head: &str, body: &str) -> (String, Vec<u8>) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "{}\r\nContent-Length: {}\r\n\r\n{}",
+            head,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let line = response.windows(2).position(|w| w == b"\r\n").unwrap();
+        let status = String::from_utf8_lossy(&response[..line]).into_owned();
+        let end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        (status, response.split_off(end + 4))
+    }
+
+    // answers requests from the test thread until the client is done
+    fn serve<T: Send + 'static>(
+        host: &mut Headless,
+        server: &Server,
+        client: thread::JoinHandle<T>,
+    ) -> T {
+        while !client.is_finished() {
+            for call in server.calls() {
+                call.answer(host);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        client.join().unwrap()
+    }
+
+    #[test]
+    fn dispatch_methods() {
+        let mut host = headless();
+        let step = dispatch(&mut host, "step", &json!({ "n": 2 })).unwrap();
+        assert_eq!(step["pc"], 0x204);
+
+        let registers = dispatch(&mut host, "registers", &Value::Null).unwrap();
+        assert_eq!(registers["v"][0], 0x2A);
+        let registers = dispatch(
+            &mut host,
+            "set_register",
+            &json!({ "register": "v3", "value": 7 }),
+        )
+        .unwrap();
+        assert_eq!(registers["v"][3], 7);
+
+        let params = json!({ "address": 0x300, "bytes": [1, 2, 3] });
+        dispatch(&mut host, "write_memory", &params).unwrap();
+        let params = json!({ "address": 0x301, "length": 2 });
+        assert_eq!(
+            dispatch(&mut host, "read_memory", &params).unwrap(),
+            json!([2, 3])
+        );
+
+        dispatch(&mut host, "press", &json!({ "key": 5 })).unwrap();
+        assert!(host.cpu().state().keys()[5]);
+        let err = dispatch(&mut host, "press", &json!({ "key": 16 })).unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+        let err = dispatch(&mut host, "read_memory", &json!({ "address": 0x1000 }));
+        assert_eq!(err.unwrap_err().code, INVALID_PARAMS);
+        let params = json!({ "n": MAX_STEPS + 1 });
+        let err = dispatch(&mut host, "step", &params).unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+        let params = json!({ "n": 1u64 << 32 });
+        let err = dispatch(&mut host, "frames", &params).unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+        let err = dispatch(&mut host, "fly", &Value::Null).unwrap_err();
+        assert_eq!(err.code, METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn framebuffer_formats() {
+        let mut host = headless();
+        dispatch(&mut host, "step", &json!({ "n": 3 })).unwrap();
+
+        let bits = dispatch(&mut host, "framebuffer", &Value::Null).unwrap();
+        let video = unhex(bits["bits"].as_str().unwrap()).unwrap();
+        assert_eq!(video, host.cpu().state().video());
+        // the top of the 0 sprite, F0, drawn at 0, 0
+        assert_eq!(video[0], 0xF0);
+
+        let params = json!({ "format": "png", "scale": 2 });
+        let png = dispatch(&mut host, "framebuffer", &params).unwrap();
+        let png = unhex(png["png"].as_str().unwrap()).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+    }
+
+    #[test]
+    fn serve_http() {
+        let mut host = headless();
+        let server = Server::bind(0).unwrap();
+        let address = server.address();
+
+        let client = thread::spawn(move || {
+            let post = "POST /rpc HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json";
+            let body = r#"{"jsonrpc": "2.0", "id": 7, "method": "step", "params": {"n": 1}}"#;
+            let step = request(address, post, body).1;
+            let error = request(address, post, "{").1;
+            let png = request(address, "GET /framebuffer.png?scale=4 HTTP/1.1", "").1;
+            (step, error, png)
+        });
+        let (step, error, png) = serve(&mut host, &server, client);
+
+        let step: Value = serde_json::from_slice(&step).unwrap();
+        assert_eq!(
+            step,
+            json!({ "jsonrpc": "2.0", "id": 7, "result": { "pc": 0x202 } })
+        );
+        let error: Value = serde_json::from_slice(&error).unwrap();
+        assert_eq!(error["error"]["code"], PARSE_ERROR);
+        assert_eq!(&png[1..4], b"PNG");
+    }
+
+    #[test]
+    fn headless_events() {
+        let mut host = headless();
+        host.cpu().debugger_mut().add_breakpoint(0x206);
+        let server = Server::bind(0).unwrap();
+        let address = server.address();
+
+        let client = thread::spawn(move || {
+            let mut reader = BufReader::new(TcpStream::connect(address).unwrap());
+            write!(reader.get_mut(), "GET /events HTTP/1.1\r\n\r\n").unwrap();
+            let mut line = String::new();
+            while line != "\r\n" {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+            }
+
+            let post = "POST /rpc HTTP/1.1\r\nContent-Type: application/json";
+            let body = r#"{"jsonrpc": "2.0", "id": 1, "method": "frames", "params": {"n": 2}}"#;
+            request(address, post, body);
+            let mut event = String::new();
+            for _ in 0..3 {
+                reader.read_line(&mut event).unwrap();
+            }
+            request(
+                address,
+                post,
+                r#"{"jsonrpc": "2.0", "id": 2, "method": "quit"}"#,
+            );
+            event
+        });
+        host.serve(server);
+
+        let event = client.join().unwrap();
+        assert_eq!(event, "event: breakpoint\ndata: {\"address\":518}\n\n");
+    }
+
+    #[test]
+    fn refuse_browsers() {
+        let server = Server::bind(0).unwrap();
+        let address = server.address();
+        let status = |head: &str| request(address, head, "{}").0;
+
+        let post = "POST /rpc HTTP/1.1\r\nContent-Type: application/json";
+        assert!(status("POST /rpc HTTP/1.1\r\nContent-Type: text/plain").contains("415"));
+        assert!(status(&format!("{}\r\nOrigin: http://evil.com", post)).contains("403"));
+        assert!(status(&format!("{}\r\nHost: evil.com:8643", post)).contains("403"));
+        let head = "GET /events HTTP/1.1\r\nOrigin: https://evil.com";
+        assert!(status(head).contains("403"));
+
+        assert!(local_host("127.0.0.1:8643") && local_host("[::1]") && local_host("LOCALHOST"));
+        assert!(local_origin("http://localhost:3000"));
+        assert!(!local_origin("http://localhost.evil.com") && !local_origin("null"));
+    }
+
+    #[test]
+    fn bounded_params() {
+        let mut host = headless();
+        let params = json!({ "address": 0xFFF, "bytes": [1, 2] });
+        let err = dispatch(&mut host, "write_memory", &params).unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+        let params = json!({ "address": 1, "length": u64::MAX });
+        let err = dispatch(&mut host, "read_memory", &params).unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+        let params = json!({ "address": u64::MAX, "bytes": [1] });
+        assert!(dispatch(&mut host, "write_memory", &params).is_err());
+
+        let params = json!({ "format": "png", "scale": 1000 });
+        let result = dispatch(&mut host, "framebuffer", &params).unwrap();
+        let bytes = unhex(result["png"].as_str().unwrap()).unwrap();
+        let info = png::Decoder::new(&bytes[..])
+            .read_info()
+            .unwrap()
+            .info()
+            .clone();
+        assert_eq!(info.width, 64 * MAX_SCALE);
+    }
+
+    #[test]
+    fn stream_events() {
+        let server = Server::bind(0).unwrap();
+        let mut stream = TcpStream::connect(server.address()).unwrap();
+        write!(stream, "GET /events HTTP/1.1\r\n\r\n").unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("HTTP/1.1 200"));
+        while line.trim() != "" {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+
+        server.publish(&Event::Stop(Stop::Breakpoint(0x204)));
+        let mut event = String::new();
+        for _ in 0..3 {
+            reader.read_line(&mut event).unwrap();
+        }
+        assert_eq!(event, "event: breakpoint\ndata: {\"address\":516}\n\n");
+    }
+}
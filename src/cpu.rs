@@ -1,7 +1,13 @@
+use cheat::{Cheats, Compare, Search};
+use debugger::{Debugger, Location, Stop};
+use opcode::Register;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use rom;
 use std::error::Error;
 use std::fmt;
-use std::string::String;
+
+pub use opcode::OpCode;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Chip8Error {
@@ -14,153 +20,131 @@ pub enum Chip8Error {
 
 impl fmt::Display for Chip8Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
-    }
-}
-
-impl Error for Chip8Error {
-    fn description(&self) -> &str {
-        match *self {
+        let description = match *self {
             Chip8Error::UnknownInstructionError => "instruction unknown",
             Chip8Error::AddressOutOfRangeError => "memory address out of range",
             Chip8Error::ProgramLoadError => "error loading program rom",
             Chip8Error::StackOverflowError => "stack overflow",
             Chip8Error::StackUnderflowError => "stack underflow",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+impl Error for Chip8Error {}
+
+// an error raised while executing an instruction, with the machine state at the fault
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fault {
+    pub error: Chip8Error,
+    pub pc: usize,
+    pub instruction: u16,
+    pub i: usize,
+    pub sp: usize,
+    pub address: Option<usize>,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at {:04X} ({:04X})",
+            self.error, self.pc, self.instruction
+        )?;
+        if let Some(address) = self.address {
+            write!(f, ", address {:04X}", address)?;
         }
+        Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub enum OpCode {
-    ClearScreen,
-    Return,
-    Jump { address: usize },
-    Call { address: usize },
-    SkipByteEqual { x: usize, byte: u8 },
-    SkipByteNotEqual { x: usize, byte: u8 },
-    SkipEqual { x: usize, y: usize },
-    SkipNotEqual { x: usize, y: usize },
-    LoadByte { x: usize, byte: u8 },
-    AddByte { x: usize, byte: u8 },
-    Load { x: usize, y: usize },
-    Or { x: usize, y: usize },
-    And { x: usize, y: usize },
-    Xor { x: usize, y: usize },
-    Add { x: usize, y: usize },
-    Sub { x: usize, y: usize },
-    ShiftRight { x: usize },
-    SubReverse { x: usize, y: usize },
-    ShiftLeft { x: usize },
-    LoadAddress { address: usize },
-    JumpOffset { address: usize },
-    Random { x: usize, byte: u8 },
-    Draw { x: usize, y: usize, n: u8 },
-    SkipKeyPressed { x: usize },
-    SkipNotPressed { x: usize },
-    LoadFromDelayTimer { x: usize },
-    WaitKey { x: usize },
-    LoadDelayTimer { x: usize },
-    LoadSoundTimer { x: usize },
-    AddAddress { x: usize },
-    LoadFont { x: usize },
-    BCD { x: usize },
-    Save { x: usize },
-    Restore { x: usize },
-    Unknown { instruction: u16 },
+impl Error for Fault {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
 }
 
-impl OpCode {
-    pub fn decode(instruction: u16) -> OpCode {
-        let address = (instruction & 0xFFF) as usize;
-        let byte = (instruction & 0xFF) as u8;
-        let x = (instruction >> 8 & 0xF) as usize;
-        let y = (instruction >> 4 & 0xF) as usize;
-        let n = (instruction & 0xF) as u8;
-
-        match instruction {
-            0x00E0 => OpCode::ClearScreen,
-            0x00EE => OpCode::Return,
-            i if i & 0xF000 == 0x1000 => OpCode::Jump { address },
-            i if i & 0xF000 == 0x2000 => OpCode::Call { address },
-            i if i & 0xF000 == 0x3000 => OpCode::SkipByteEqual { x, byte },
-            i if i & 0xF000 == 0x4000 => OpCode::SkipByteNotEqual { x, byte },
-            i if i & 0xF000 == 0x5000 => OpCode::SkipEqual { x, y },
-            i if i & 0xF000 == 0x6000 => OpCode::LoadByte { x, byte },
-            i if i & 0xF000 == 0x7000 => OpCode::AddByte { x, byte },
-            i if i & 0xF00F == 0x8000 => OpCode::Load { x, y },
-            i if i & 0xF00F == 0x8001 => OpCode::Or { x, y },
-            i if i & 0xF00F == 0x8002 => OpCode::And { x, y },
-            i if i & 0xF00F == 0x8003 => OpCode::Xor { x, y },
-            i if i & 0xF00F == 0x8004 => OpCode::Add { x, y },
-            i if i & 0xF00F == 0x8005 => OpCode::Sub { x, y },
-            i if i & 0xF00F == 0x8006 => OpCode::ShiftRight { x },
-            i if i & 0xF00F == 0x8007 => OpCode::SubReverse { x, y },
-            i if i & 0xF00F == 0x800E => OpCode::ShiftLeft { x },
-            i if i & 0xF00F == 0x9000 => OpCode::SkipNotEqual { x, y },
-            i if i & 0xF000 == 0xA000 => OpCode::LoadAddress { address },
-            i if i & 0xF000 == 0xB000 => OpCode::JumpOffset { address },
-            i if i & 0xF000 == 0xC000 => OpCode::Random { x, byte },
-            i if i & 0xF000 == 0xD000 => OpCode::Draw { x, y, n },
-            i if i & 0xF0FF == 0xE09E => OpCode::SkipKeyPressed { x },
-            i if i & 0xF0FF == 0xE0A1 => OpCode::SkipNotPressed { x },
-            i if i & 0xF0FF == 0xF007 => OpCode::LoadFromDelayTimer { x },
-            i if i & 0xF0FF == 0xF00A => OpCode::WaitKey { x },
-            i if i & 0xF0FF == 0xF015 => OpCode::LoadDelayTimer { x },
-            i if i & 0xF0FF == 0xF018 => OpCode::LoadSoundTimer { x },
-            i if i & 0xF0FF == 0xF01E => OpCode::AddAddress { x },
-            i if i & 0xF0FF == 0xF029 => OpCode::LoadFont { x },
-            i if i & 0xF0FF == 0xF033 => OpCode::BCD { x },
-            i if i & 0xF0FF == 0xF055 => OpCode::Save { x },
-            i if i & 0xF0FF == 0xF065 => OpCode::Restore { x },
-            _ => OpCode::Unknown { instruction },
-        }
-    }
-
-    pub fn disassemble(instruction: u16) -> (String, String) {
-        use cpu::OpCode::*;
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Quirks {
+    // 8XY6 / 8XYE shift VX in place rather than shifting VY into VX
+    pub shift: bool,
+    // FX55 / FX65 leave I unchanged rather than incrementing it
+    pub load_store: bool,
+    // BNNN jumps to XNN + VX rather than NNN + V0
+    pub jump: bool,
+    // 8XY1 / 8XY2 / 8XY3 reset VF to 0
+    pub vf_reset: bool,
+    // DXYN waits for the display interrupt under VIP timing, ending the frame
+    pub display_wait: bool,
+    // memory accesses past 4K wrap around rather than raising an error
+    pub wrap: bool,
+}
 
-        let (op, params) = match Self::decode(instruction) {
-            ClearScreen => ("CLS", String::new()),
-            Return => ("RET", String::new()),
-            Jump { address } => ("JUMP", format!("#{:04X}", address)),
-            Call { address } => ("CALL", format!("#{:04X}", address)),
-            SkipByteEqual { x, byte } => ("SE", format!("V{:X}, {:02X}", x, byte)),
-            SkipByteNotEqual { x, byte } => ("SNE", format!("V{:X}, {:02X}", x, byte)),
-            SkipEqual { x, y } => ("SE", format!("V{:X}, V{}", x, y)),
-            LoadByte { x, byte } => ("LOAD", format!("V{:X}, {:02X}", x, byte)),
-            AddByte { x, byte } => ("ADD", format!("V{:X}, {:02X}", x, byte)),
-            Load { x, y } => ("LOAD", format!("V{:X}, V{:X}", x, y)),
-            Or { x, y } => ("OR", format!("V{:X}, V{:X}", x, y)),
-            And { x, y } => ("AND", format!("V{:X}, V{:X}", x, y)),
-            Xor { x, y } => ("XOR", format!("V{:X}, V{:X}", x, y)),
-            Add { x, y } => ("ADD", format!("V{:X}, V{:X}", x, y)),
-            Sub { x, y } => ("SUB", format!("V{:X}, V{:X}", x, y)),
-            ShiftRight { x } => ("SHR", format!("V{:X}", x)),
-            SubReverse { x, y } => ("SUBN", format!("V{:X}, V{:X}", x, y)),
-            ShiftLeft { x } => ("SHL", format!("V{:X}", x)),
-            SkipNotEqual { x, y } => ("SNE", format!("V{:X}, V{:X}", x, y)),
-            LoadAddress { address } => ("LOAD", format!("I, #{:04X}", address)),
-            JumpOffset { address } => ("JUMP", format!("V0, #{:04X}", address)),
-            Random { x, byte } => ("RND", format!("V{:X}, #{:02X}", x, byte)),
-            Draw { x, y, n } => ("DRAW", format!("V{:X}, V{:X}, {}", x, y, n)),
-            SkipKeyPressed { x } => ("SKP", format!("V{:X}", x)),
-            SkipNotPressed { x } => ("SKNP", format!("V{:X}", x)),
-            LoadFromDelayTimer { x } => ("LOAD", format!("V{:X}, DT", x)),
-            WaitKey { x } => ("LOAD", format!("V{:X}, K", x)),
-            LoadDelayTimer { x } => ("LOAD", format!("DT, V{:X}", x)),
-            LoadSoundTimer { x } => ("LOAD", format!("ST, V{:X}", x)),
-            AddAddress { x } => ("ADD", format!("I, V{:X}", x)),
-            LoadFont { x } => ("FONT", format!("I, V{:X}", x)),
-            BCD { x } => ("BCD", format!("I, V{:X}", x)),
-            Save { x } => ("SAV", format!("[I], V{:X}", x)),
-            Restore { x } => ("RST", format!("V{:X}, [I]", x)),
-            Unknown { .. } => ("???", String::new()),
-        };
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift: true,
+            load_store: true,
+            jump: false,
+            vf_reset: false,
+            display_wait: false,
+            wrap: false,
+        }
+    }
+}
 
-        (op.to_owned(), params)
+impl Quirks {
+    pub fn chip8() -> Quirks {
+        Quirks {
+            shift: false,
+            load_store: false,
+            jump: false,
+            vf_reset: true,
+            display_wait: true,
+            wrap: false,
+        }
+    }
+
+    pub fn schip() -> Quirks {
+        Quirks {
+            shift: true,
+            load_store: true,
+            jump: true,
+            vf_reset: false,
+            display_wait: false,
+            wrap: false,
+        }
+    }
+
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift: false,
+            load_store: false,
+            jump: false,
+            vf_reset: false,
+            display_wait: false,
+            wrap: false,
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name.to_lowercase().as_str() {
+            "default" => Some(Quirks::default()),
+            "chip8" | "chip-8" | "vip" => Some(Quirks::chip8()),
+            "schip" | "superchip" => Some(Quirks::schip()),
+            "xochip" | "xo-chip" => Some(Quirks::xochip()),
+            _ => None,
+        }
     }
 }
 
+// a VIP machine cycle is 8 clocks of the 1.76MHz CDP1802, giving 3668 per
+// 60Hz frame, of which the display interrupt and DMA take roughly 1100
+const VIP_FRAME_CYCLES: u32 = 3668;
+const VIP_DISPLAY_CYCLES: u32 = 1100;
+
+#[derive(Clone)]
 pub struct Chip8State {
     video: [u8; Chip8State::VIDEO_SIZE],
     memory: [u8; Chip8State::MEMORY_SIZE],
@@ -173,6 +157,7 @@ pub struct Chip8State {
     dt: u8,
     st: u8,
     error: Option<Chip8Error>,
+    error_address: Option<usize>,
 }
 
 impl Default for Chip8State {
@@ -189,6 +174,7 @@ impl Default for Chip8State {
             dt: 0,
             st: 0,
             error: None,
+            error_address: None,
         };
         state.memory[..rom::ROM.len()].clone_from_slice(&rom::ROM);
         state
@@ -198,13 +184,20 @@ impl Default for Chip8State {
 #[allow(dead_code)]
 impl Chip8State {
     const PROGRAM_START: usize = 512;
-    const MEMORY_SIZE: usize = 4096;
+    pub const MEMORY_SIZE: usize = 4096;
     const VIDEO_SIZE: usize = 256;
     const MAX_PROGRAM_SIZE: usize = 3584;
     const STACK_SIZE: usize = 16;
     const N_REGISTERS: usize = 16;
     const N_KEYS: usize = 16;
     const PITCH: usize = 8;
+    pub const WIDTH: usize = Self::PITCH * 8;
+    pub const HEIGHT: usize = Self::VIDEO_SIZE / Self::PITCH;
+    const SERIALIZED_SIZE: usize = Self::MEMORY_SIZE
+        + Self::VIDEO_SIZE
+        + Self::N_REGISTERS
+        + (Self::STACK_SIZE + 2) * 2
+        + 3;
 
     pub fn new() -> Chip8State {
         Self::default()
@@ -223,6 +216,51 @@ impl Chip8State {
         state
     }
 
+    // memory, video and registers, leaving out the keys and any error
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::SERIALIZED_SIZE);
+        bytes.extend_from_slice(&self.memory);
+        bytes.extend_from_slice(&self.video);
+        bytes.extend_from_slice(&self.v);
+        for address in self.stack.iter().chain(&[self.pc, self.i]) {
+            bytes.extend_from_slice(&(*address as u16).to_be_bytes());
+        }
+        bytes.extend_from_slice(&[self.sp as u8, self.dt, self.st]);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Chip8State> {
+        if bytes.len() != Self::SERIALIZED_SIZE {
+            return None;
+        }
+
+        let mut state = Chip8State::new();
+        let (memory, rest) = bytes.split_at(Self::MEMORY_SIZE);
+        let (video, rest) = rest.split_at(Self::VIDEO_SIZE);
+        let (v, rest) = rest.split_at(Self::N_REGISTERS);
+        let (addresses, rest) = rest.split_at((Self::STACK_SIZE + 2) * 2);
+        state.memory.copy_from_slice(memory);
+        state.video.copy_from_slice(video);
+        state.v.copy_from_slice(v);
+
+        let mut addresses = addresses
+            .chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as usize);
+        for slot in state.stack.iter_mut() {
+            *slot = addresses.next()?;
+        }
+        state.pc = addresses.next()?;
+        state.i = addresses.next()?;
+        state.sp = rest[0] as usize;
+        state.dt = rest[1];
+        state.st = rest[2];
+
+        if state.sp > Self::STACK_SIZE || state.pc >= Self::MEMORY_SIZE {
+            return None;
+        }
+        Some(state)
+    }
+
     #[inline(always)]
     pub fn video(&self) -> &[u8] {
         &self.video
@@ -268,20 +306,67 @@ impl Chip8State {
         self.pc
     }
 
+    #[inline(always)]
+    pub fn keys(&self) -> &[bool] {
+        &self.keys
+    }
+
+    fn write(&mut self, location: Location, value: u16) -> Result<(), Chip8Error> {
+        match location {
+            Location::Register(Register::V(x)) if x < Self::N_REGISTERS => self.v[x] = value as u8,
+            Location::Register(Register::I) => self.i = value as usize,
+            Location::Register(Register::DT) => self.dt = value as u8,
+            Location::Register(Register::ST) => self.st = value as u8,
+            Location::Register(Register::SP) if (value as usize) < Self::STACK_SIZE => {
+                self.sp = value as usize
+            }
+            Location::Pc if (value as usize) < Self::MEMORY_SIZE => self.pc = value as usize,
+            Location::Memory(address) if address < Self::MEMORY_SIZE => {
+                self.memory[address] = value as u8
+            }
+            _ => return Err(Chip8Error::AddressOutOfRangeError),
+        }
+        Ok(())
+    }
+
+    // reads the instruction at an address, wrapping at the end of memory
     #[inline(always)]
     pub fn fetch(&self, address: usize) -> u16 {
-        (self.memory[address as usize] as u16) << 8 | (self.memory[address as usize + 1] as u16)
+        let hi = self.memory[address % Self::MEMORY_SIZE];
+        let lo = self.memory[(address + 1) % Self::MEMORY_SIZE];
+        u16::from(hi) << 8 | u16::from(lo)
     }
 }
 
+// the outcome of an instruction with the debugger attached
+enum Next {
+    Continue(OpCode),
+    Stop { cycles: u32 },
+}
+
 pub struct Chip8 {
     state: Chip8State,
+    quirks: Quirks,
+    // VIP cycles the last instruction ran past the end of the previous frame
+    overrun: u32,
+    // whether the buzzer sounded during the last frame
+    sound: bool,
+    debugger: Debugger,
+    cheats: Cheats,
+    // seeded so that peers in a netplay session draw the same numbers
+    rng: Option<StdRng>,
 }
 
 impl Default for Chip8 {
     fn default() -> Self {
         Chip8 {
             state: Chip8State::new(),
+            quirks: Quirks::default(),
+            overrun: 0,
+            sound: false,
+            debugger: Debugger::default(),
+            cheats: Cheats::default(),
+            rng: None,
         }
     }
 }
@@ -297,13 +382,106 @@ impl Chip8 {
         &self.state
     }
 
+    #[inline(always)]
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    #[inline(always)]
+    pub fn sound(&self) -> bool {
+        self.sound
+    }
+
+    #[inline(always)]
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    #[inline(always)]
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    #[inline(always)]
+    pub fn cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    #[inline(always)]
+    pub fn cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cheats
+    }
+
+    // writes the frozen values, ignoring locations that are out of range,
+    // without cancelling a resume from a breakpoint
+    fn apply_cheats(&mut self) {
+        for &(location, value) in self.cheats.frozen() {
+            let _ = self.state.write(location, value);
+        }
+        self.debugger.sync_watches(&self.state);
+    }
+
+    // starts a memory search, or narrows the current one
+    pub fn search(&mut self, compare: Option<Compare>) -> &Search {
+        match compare {
+            Some(compare) => self.cheats.filter(&self.state, compare),
+            None => self.cheats.start_search(&self.state),
+        }
+    }
+
+    pub fn watch(&mut self, location: Location) -> bool {
+        self.debugger.add_watch(location, &self.state)
+    }
+
+    pub fn take_stop(&mut self) -> Option<Stop> {
+        self.debugger.take_stop()
+    }
+
+    pub fn seed_random(&mut self, seed: u64) {
+        self.rng = Some(StdRng::seed_from_u64(seed));
+    }
+
+    pub fn save_state(&self) -> Chip8State {
+        self.state.clone()
+    }
+
+    pub fn load_state(&mut self, state: Chip8State) {
+        self.state = state;
+        self.overrun = 0;
+        self.debugger.sync(&self.state);
+    }
 
     pub fn soft_reset(&mut self) {
         self.state = Chip8State::from_state(&self.state);
+        self.debugger.sync(&self.state);
     }
 
     pub fn hard_reset(&mut self) {
         self.state = Chip8State::new();
+        self.overrun = 0;
+        self.debugger.sync(&self.state);
+    }
+
+    // writes a register or memory byte, values are truncated to its width
+    pub fn set(&mut self, location: Location, value: u16) -> Result<(), Chip8Error> {
+        self.state.write(location, value)?;
+        self.debugger.sync(&self.state);
+        Ok(())
+    }
+
+    pub fn poke(&mut self, address: usize, bytes: &[u8]) -> Result<(), Chip8Error> {
+        let end = address
+            .checked_add(bytes.len())
+            .filter(|&end| end <= Chip8State::MEMORY_SIZE)
+            .ok_or(Chip8Error::AddressOutOfRangeError)?;
+
+        self.state.memory[address..end].clone_from_slice(bytes);
+        self.debugger.sync(&self.state);
+        Ok(())
     }
 
     pub fn press_key(&mut self, key: usize) {
@@ -314,17 +492,89 @@ impl Chip8 {
         self.state.keys[key] = false;
     }
 
-    pub fn load_rom(&mut self, bytes: &[u8]) -> Result<usize, Chip8Error> {
+    // whether a program fits in memory, so callers can check before resetting
+    pub fn check_rom(bytes: &[u8]) -> Result<(), Chip8Error> {
         if bytes.len() > Chip8State::MAX_PROGRAM_SIZE {
             return Err(Chip8Error::ProgramLoadError);
         }
+        Ok(())
+    }
 
+    pub fn load_rom(&mut self, bytes: &[u8]) -> Result<usize, Chip8Error> {
+        Chip8::check_rom(bytes)?;
         self.state = Chip8State::from_rom(bytes);
+        self.overrun = 0;
+        self.debugger.sync(&self.state);
         Ok(bytes.len())
     }
 
-    pub fn execute_cycle(&mut self) -> Result<(), Chip8Error> {
-        let instruction = self.state.fetch(self.state.pc);
+    // replaces the program in memory, keeping registers, stack, timers and the screen
+    pub fn patch_rom(&mut self, bytes: &[u8]) -> Result<usize, Chip8Error> {
+        Chip8::check_rom(bytes)?;
+        let old = &self.state;
+        let mut state = Chip8State::from_rom(bytes);
+        state.video = old.video;
+        state.v = old.v;
+        state.stack = old.stack;
+        state.keys = old.keys;
+        state.pc = old.pc;
+        state.sp = old.sp;
+        state.i = old.i;
+        state.dt = old.dt;
+        state.st = old.st;
+        self.state = state;
+        self.debugger.sync(&self.state);
+        Ok(bytes.len())
+    }
+
+    pub fn execute_cycle(&mut self) -> Result<(), Fault> {
+        self.step()?;
+        self.debugger.sync(&self.state);
+        Ok(())
+    }
+
+    // on error the PC is left at the faulting instruction
+    fn step(&mut self) -> Result<OpCode, Fault> {
+        let (pc, i, sp) = (self.state.pc, self.state.i, self.state.sp);
+        let instruction = self.state.fetch(pc);
+        let opcode = OpCode::decode(instruction);
+        self.skip();
+
+        if self.debugger.trace() {
+            let (op, params) = OpCode::disassemble(instruction);
+            trace!("{:04X} {:04X} {} {}", pc, instruction, op, params);
+        }
+
+        let result = match self.address(pc + 1) {
+            Some(_) => self.execute(opcode),
+            None => Err(Chip8Error::AddressOutOfRangeError),
+        };
+
+        result.map(|_| opcode).map_err(|error| {
+            self.state.error = None;
+            self.state.pc = pc;
+            Fault {
+                error,
+                pc,
+                instruction,
+                i,
+                sp,
+                address: self.state.error_address.take(),
+            }
+        })
+    }
+
+    #[inline(always)]
+    fn waits_for_display(&self, opcode: OpCode) -> bool {
+        match opcode {
+            OpCode::Draw { .. } => self.quirks.display_wait,
+            _ => false,
+        }
+    }
+
+    // timers count down at 60Hz, independent of the instruction rate
+    pub fn tick_timers(&mut self) {
+        self.sound = self.state.st > 0;
 
         if self.state.dt > 0 {
             self.state.dt -= 1;
@@ -333,9 +583,67 @@ impl Chip8 {
         if self.state.st > 0 {
             self.state.st -= 1;
         }
+    }
+
+    // steps unless a breakpoint stops execution first
+    #[inline(always)]
+    fn debug_step(&mut self) -> Result<Next, Fault> {
+        if !self.debugger.active() {
+            return self.step().map(Next::Continue);
+        }
 
-        self.state.pc += 2;
-        self.execute(OpCode::decode(instruction))
+        if self.debugger.before(self.state.pc) {
+            return Ok(Next::Stop { cycles: 0 });
+        }
+        let opcode = self.step()?;
+        if self.debugger.after(&self.state) {
+            return Ok(Next::Stop {
+                cycles: opcode.cycles(),
+            });
+        }
+        Ok(Next::Continue(opcode))
+    }
+
+    // runs one 60Hz frame: frozen values are written, then `ipf` instructions
+    // run followed by a timer tick, ending early without a tick if the
+    // debugger stops execution
+    pub fn run_frame(&mut self, ipf: u32) -> Result<(), Fault> {
+        self.apply_cheats();
+        for _ in 0..ipf {
+            if let Next::Stop { .. } = self.debug_step()? {
+                return Ok(());
+            }
+        }
+
+        self.tick_timers();
+        Ok(())
+    }
+
+    // runs one 60Hz frame on a budget of VIP machine cycles, carrying any
+    // overrun into the next frame
+    pub fn run_vip_frame(&mut self) -> Result<(), Fault> {
+        let budget = VIP_FRAME_CYCLES - VIP_DISPLAY_CYCLES;
+        let mut cycles = self.overrun;
+        self.apply_cheats();
+
+        while cycles < budget {
+            let opcode = match self.debug_step()? {
+                Next::Continue(opcode) => opcode,
+                Next::Stop { cycles: n } => {
+                    // the rest of the frame runs when execution resumes
+                    self.overrun = cycles + n;
+                    return Ok(());
+                }
+            };
+            cycles += opcode.cycles();
+            if self.waits_for_display(opcode) {
+                cycles = budget;
+            }
+        }
+
+        self.overrun = cycles - budget;
+        self.tick_timers();
+        Ok(())
     }
 
     pub fn execute_all(&mut self, opcodes: &[OpCode]) -> Result<(), Chip8Error> {
@@ -366,9 +674,9 @@ impl Chip8 {
             Xor { x, y } => self.xor(x, y),
             Add { x, y } => self.add(x, y),
             Sub { x, y } => self.sub(x, y),
-            ShiftRight { x } => self.shift_right(x),
+            ShiftRight { x, y } => self.shift_right(x, y),
             SubReverse { x, y } => self.sub_reverse(x, y),
-            ShiftLeft { x } => self.shift_left(x),
+            ShiftLeft { x, y } => self.shift_left(x, y),
             SkipNotEqual { x, y } => self.skip_not_equal(x, y),
             LoadAddress { address } => self.load_address(address),
             JumpOffset { address } => self.jump_offset(address),
@@ -396,6 +704,27 @@ impl Chip8 {
         }
     }
 
+    // resolves an access to memory, wrapping past 4K or flagging an error
+    // depending on the wrap quirk
+    fn address(&mut self, address: usize) -> Option<usize> {
+        if address < Chip8State::MEMORY_SIZE {
+            Some(address)
+        } else if self.quirks.wrap {
+            Some(address % Chip8State::MEMORY_SIZE)
+        } else {
+            self.state.error = Some(Chip8Error::AddressOutOfRangeError);
+            self.state.error_address = Some(address);
+            None
+        }
+    }
+
+    // checks a run of `len` bytes from `start` up front, so a faulting
+    // instruction leaves memory untouched; accesses within the run are then
+    // made modulo the memory size
+    fn check_range(&mut self, start: usize, len: usize) -> bool {
+        len == 0 || self.address(start + len - 1).is_some()
+    }
+
     #[inline(always)]
     fn clear_screen(&mut self) {
         self.state.video = [0; Chip8State::VIDEO_SIZE];
@@ -411,6 +740,16 @@ impl Chip8 {
         }
     }
 
+    // moves past an instruction, wrapping at the end of memory with the wrap
+    // quirk and otherwise leaving the next fetch to fault
+    #[inline(always)]
+    fn skip(&mut self) {
+        self.state.pc += 2;
+        if self.quirks.wrap {
+            self.state.pc %= Chip8State::MEMORY_SIZE;
+        }
+    }
+
     #[inline(always)]
     fn jump(&mut self, address: usize) {
         self.state.pc = address;
@@ -418,7 +757,10 @@ impl Chip8 {
 
     #[inline(always)]
     fn jump_offset(&mut self, address: usize) {
-        self.state.pc = self.state.v[0] as usize + address;
+        let x = if self.quirks.jump { address >> 8 } else { 0 };
+        if let Some(address) = self.address(self.state.v[x] as usize + address) {
+            self.state.pc = address;
+        }
     }
 
     fn call(&mut self, address: usize) {
@@ -426,6 +768,7 @@ impl Chip8 {
 
         if address >= Chip8State::MAX_PROGRAM_SIZE {
             state.error = Some(Chip8Error::AddressOutOfRangeError);
+            state.error_address = Some(address);
         } else if state.sp >= Chip8State::STACK_SIZE {
             state.error = Some(Chip8Error::StackOverflowError);
         } else {
@@ -438,28 +781,28 @@ impl Chip8 {
     #[inline(always)]
     fn skip_byte_equal(&mut self, x: usize, byte: u8) {
         if self.state.v[x] == byte {
-            self.state.pc += 2;
+            self.skip();
         }
     }
 
     #[inline(always)]
     fn skip_equal(&mut self, x: usize, y: usize) {
         if self.state.v[x] == self.state.v[y] {
-            self.state.pc += 2;
+            self.skip();
         }
     }
 
     #[inline(always)]
     fn skip_byte_not_equal(&mut self, x: usize, byte: u8) {
         if self.state.v[x] != byte {
-            self.state.pc += 2;
+            self.skip();
         }
     }
 
     #[inline(always)]
     fn skip_not_equal(&mut self, x: usize, y: usize) {
         if self.state.v[x] != self.state.v[y] {
-            self.state.pc += 2;
+            self.skip();
         }
     }
 
@@ -487,14 +830,24 @@ impl Chip8 {
 
     fn or(&mut self, x: usize, y: usize) {
         self.state.v[x] |= self.state.v[y];
+        self.vf_reset();
     }
 
     fn and(&mut self, x: usize, y: usize) {
         self.state.v[x] &= self.state.v[y];
+        self.vf_reset();
     }
 
     fn xor(&mut self, x: usize, y: usize) {
         self.state.v[x] ^= self.state.v[y];
+        self.vf_reset();
+    }
+
+    #[inline(always)]
+    fn vf_reset(&mut self) {
+        if self.quirks.vf_reset {
+            self.state.v[0xF] = 0;
+        }
     }
 
     fn sub(&mut self, x: usize, y: usize) {
@@ -508,16 +861,16 @@ impl Chip8 {
         // ((self.state.v[x] as i32) - (self.state.v[y] as i32)) as u8;
     }
 
-    fn shift_right(&mut self, x: usize) {
-        let v = self.state.v[x];
-        self.state.v[0xF] = v & 0x1;
+    fn shift_right(&mut self, x: usize, y: usize) {
+        let v = self.state.v[if self.quirks.shift { x } else { y }];
         self.state.v[x] = v >> 1;
+        self.state.v[0xF] = v & 0x1;
     }
 
-    fn shift_left(&mut self, x: usize) {
-        let v = self.state.v[x];
-        self.state.v[0xF] = v >> 7;
+    fn shift_left(&mut self, x: usize, y: usize) {
+        let v = self.state.v[if self.quirks.shift { x } else { y }];
         self.state.v[x] = v << 1;
+        self.state.v[0xF] = v >> 7;
     }
 
     fn sub_reverse(&mut self, x: usize, y: usize) {
@@ -530,7 +883,10 @@ impl Chip8 {
     }
 
     fn random(&mut self, x: usize, byte: u8) {
-        let r = rand::random::<u8>();
+        let r = match self.rng {
+            Some(ref mut rng) => rng.gen::<u8>(),
+            None => rand::random::<u8>(),
+        };
         self.state.v[x] = r & byte;
     }
 
@@ -550,7 +906,9 @@ impl Chip8 {
             }
         }
 
-        self.state.pc -= 2;
+        // wait on this instruction, which may have wrapped to the start
+        let size = Chip8State::MEMORY_SIZE;
+        self.state.pc = (self.state.pc + size - 2) % size;
     }
 
     fn ld_st_v(&mut self, x: usize) {
@@ -559,45 +917,46 @@ impl Chip8 {
 
     fn skip_key_pressed(&mut self, x: usize) {
         if self.state.keys[x] {
-            self.state.pc += 2;
+            self.skip();
         }
     }
 
     fn skip_not_pressed(&mut self, x: usize) {
         if !self.state.keys[x] {
-            self.state.pc += 2;
+            self.skip();
         }
     }
 
+    // sprites start at a position wrapped onto the screen and are clipped
+    // at the right and bottom edges
     fn draw(&mut self, vx: usize, vy: usize, n: u8) {
         let mut carry: u8 = 0;
-        let x = self.state.v[vx];
-        let y = self.state.v[vy];
+        let rows = Chip8State::VIDEO_SIZE / Chip8State::PITCH;
+        let x = self.state.v[vx] as usize % (Chip8State::PITCH * 8);
+        let y = self.state.v[vy] as usize % rows;
+
+        if !self.check_range(self.state.i, n as usize) {
+            return;
+        }
 
-        for i in 0..n {
+        for i in 0..(n as usize).min(rows - y) {
             let x_offset = x >> 3;
             let x_bit = x & 7;
-            let y_offset = ((y + i) as usize) * Chip8State::PITCH;
-            let mem_addr = i as usize + self.state.i;
-            let mem_byte = self.state.memory[mem_addr as usize];
+            let y_offset = (y + i) * Chip8State::PITCH;
+            let mem_addr = (i + self.state.i) % Chip8State::MEMORY_SIZE;
+            let mem_byte = self.state.memory[mem_addr];
 
-            let video_addr = y_offset + (x_offset as usize);
-
-            if video_addr >= 255 {
-                // TODO fix
-                break;
-            }
+            let video_addr = y_offset + x_offset;
 
             let byte_0 = self.state.video[video_addr];
-            let byte_1 = self.state.video[video_addr + 1];
             self.state.video[video_addr] ^= mem_byte >> x_bit;
+            carry |= byte_0 & !self.state.video[video_addr];
 
-            if x_bit > 0 {
+            if x_bit > 0 && x_offset + 1 < Chip8State::PITCH {
+                let byte_1 = self.state.video[video_addr + 1];
                 self.state.video[video_addr + 1] ^= mem_byte << (8 - x_bit);
+                carry |= byte_1 & !self.state.video[video_addr + 1];
             }
-
-            carry |= byte_0 & !self.state.video[video_addr];
-            carry |= byte_1 & !self.state.video[video_addr + 1];
         }
 
         self.state.v[0xF] = match carry {
@@ -606,35 +965,67 @@ impl Chip8 {
         };
     }
 
+    // I is only checked when memory is accessed through it
     fn add_address(&mut self, x: usize) {
-        self.state.i += self.state.v[x] as usize;
+        let n = self.state.v[x] as usize;
+        self.add_i(n);
     }
 
     fn load_font(&mut self, x: usize) {
-        let addr = 0 + self.state.v[x] * 5;
-        self.state.i = addr as usize;
+        self.state.i = (self.state.v[x] & 0xF) as usize * 5;
     }
 
     fn bcd(&mut self, x: usize) {
         let value = self.state.v[x];
         let addr = self.state.i;
 
-        self.state.memory[addr] = ((value as u16 % 1000) / 100) as u8;
-        self.state.memory[addr + 1] = (value % 100) / 10;
-        self.state.memory[addr + 2] = value % 10;
+        if !self.check_range(addr, 3) {
+            return;
+        }
+
+        let digits = [value / 100, (value % 100) / 10, value % 10];
+        for (i, digit) in digits.iter().enumerate() {
+            self.state.memory[(addr + i) % Chip8State::MEMORY_SIZE] = *digit;
+        }
     }
 
     fn save(&mut self, x: usize) {
         let addr = self.state.i;
 
+        if !self.check_range(addr, x + 1) {
+            return;
+        }
+
         for i in 0..=x {
-            self.state.memory[addr + i] = self.state.v[i];
+            self.state.memory[(addr + i) % Chip8State::MEMORY_SIZE] = self.state.v[i];
+        }
+
+        if !self.quirks.load_store {
+            self.add_i(x + 1);
         }
     }
 
     fn restore(&mut self, x: usize) {
+        let addr = self.state.i;
+
+        if !self.check_range(addr, x + 1) {
+            return;
+        }
+
         for i in 0..=x {
-            self.state.v[i] = self.state.memory[self.state.i + i];
+            self.state.v[i] = self.state.memory[(addr + i) % Chip8State::MEMORY_SIZE];
+        }
+
+        if !self.quirks.load_store {
+            self.add_i(x + 1);
+        }
+    }
+
+    #[inline(always)]
+    fn add_i(&mut self, n: usize) {
+        self.state.i += n;
+        if self.quirks.wrap {
+            self.state.i %= Chip8State::MEMORY_SIZE;
         }
     }
 }
@@ -643,6 +1034,7 @@ impl Chip8 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn clear_screen() {
@@ -688,6 +1080,35 @@ mod tests {
         assert_eq!(result.err(), Some(Chip8Error::StackOverflowError));
     }
 
+    #[test]
+    fn fault_context() {
+        let mut cpu = Chip8::new();
+        cpu.load_rom(&[0xA1, 0x23, 0x2F, 0x00]).unwrap();
+        cpu.execute_cycle().unwrap();
+
+        let fault = cpu.execute_cycle().unwrap_err();
+        assert_eq!(
+            fault,
+            Fault {
+                error: Chip8Error::AddressOutOfRangeError,
+                pc: 0x202,
+                instruction: 0x2F00,
+                i: 0x123,
+                sp: 0,
+                address: Some(0xF00),
+            }
+        );
+        assert_eq!(cpu.state.pc, 0x202);
+        assert_eq!(
+            fault.to_string(),
+            "memory address out of range at 0202 (2F00), address 0F00"
+        );
+        assert_eq!(
+            fault.source().map(|err| err.to_string()),
+            Some("memory address out of range".to_owned())
+        );
+    }
+
     #[test]
     fn jumps() {
         let mut cpu = Chip8::new();
@@ -879,6 +1300,137 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn quirks() {
+        let mut cpu = Chip8::new();
+        cpu.set_quirks(Quirks::chip8());
+        let result = cpu.execute_all(&[
+            OpCode::LoadByte { x: 0, byte: 0 },
+            OpCode::LoadByte { x: 1, byte: 0x81 },
+            OpCode::ShiftRight { x: 0, y: 1 },
+            OpCode::LoadAddress { address: 0x300 },
+            OpCode::Save { x: 1 },
+            OpCode::Or { x: 1, y: 1 },
+        ]);
+
+        assert!(result.is_ok());
+        assert_eq!(cpu.state.v[0], 0x40);
+        assert_eq!(cpu.state.v[0xF], 0);
+        assert_eq!(cpu.state.i, 0x302);
+
+        let mut cpu = Chip8::new();
+        cpu.set_quirks(Quirks::schip());
+        let result = cpu.execute_all(&[
+            OpCode::LoadByte { x: 0, byte: 0x81 },
+            OpCode::LoadByte { x: 1, byte: 0 },
+            OpCode::LoadByte { x: 2, byte: 4 },
+            OpCode::ShiftLeft { x: 0, y: 1 },
+            OpCode::LoadAddress { address: 0x300 },
+            OpCode::Save { x: 1 },
+            OpCode::JumpOffset { address: 0x210 },
+        ]);
+
+        assert!(result.is_ok());
+        assert_eq!(cpu.state.v[0], 0x02);
+        assert_eq!(cpu.state.v[0xF], 1);
+        assert_eq!(cpu.state.i, 0x300);
+        assert_eq!(cpu.state.pc, 0x214);
+        assert_eq!(Quirks::preset("SCHIP"), Some(Quirks::schip()));
+        assert_eq!(Quirks::preset("bogus"), None);
+    }
+
+    #[test]
+    fn patch_rom() {
+        let mut cpu = Chip8::new();
+        cpu.load_rom(&[0x60, 0x05, 0x00, 0xE0]).unwrap();
+        cpu.execute_cycle().unwrap();
+        cpu.state.video[0] = 0xFF;
+
+        let result = cpu.patch_rom(&[0x60, 0x05, 0x61, 0x07]);
+        assert_eq!(result, Ok(4));
+        assert_eq!(cpu.state.v[0], 5);
+        assert_eq!(cpu.state.pc, 0x202);
+        assert_eq!(cpu.state.video[0], 0xFF);
+        assert_eq!(cpu.state.fetch(0x202), 0x6107);
+
+        let result = cpu.patch_rom(&[0; 4096]);
+        assert_eq!(result, Err(Chip8Error::ProgramLoadError));
+        assert!(cpu.load_rom(&[0; 4096]).is_err());
+        assert_eq!(cpu.state.fetch(0x202), 0x6107);
+    }
+
+    #[test]
+    fn cheats_keep_resume() {
+        let mut cpu = Chip8::new();
+        cpu.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        cpu.debugger_mut().add_breakpoint(0x202);
+        cpu.cheats_mut().freeze(Location::Memory(0x300), 5);
+
+        assert!(cpu.run_frame(10).is_ok());
+        assert_eq!(cpu.take_stop(), Some(Stop::Breakpoint(0x202)));
+        assert!(cpu.run_frame(10).is_ok());
+        assert_eq!(cpu.take_stop(), Some(Stop::Breakpoint(0x202)));
+        assert_eq!(cpu.state.v[0], 2);
+        assert_eq!(cpu.state.memory[0x300], 5);
+    }
+
+    #[test]
+    fn poke_bounds() {
+        let mut cpu = Chip8::new();
+        assert_eq!(cpu.poke(0xFFE, &[1, 2]), Ok(()));
+        assert_eq!(cpu.poke(0xFFF, &[1, 2]), Err(Chip8Error::AddressOutOfRangeError));
+        assert_eq!(cpu.poke(usize::MAX, &[1]), Err(Chip8Error::AddressOutOfRangeError));
+    }
+
+    #[test]
+    fn run_frame() {
+        let mut cpu = Chip8::new();
+        cpu.load_rom(&[0x60, 0x03, 0xF0, 0x15, 0x70, 0x01, 0x12, 0x04]).unwrap();
+
+        assert!(cpu.run_frame(10).is_ok());
+        assert_eq!(cpu.state.dt, 2);
+        assert_eq!(cpu.state.v[0], 7);
+        assert_eq!(cpu.state.pc, 0x204);
+
+        assert!(cpu.run_frame(0).is_ok());
+        assert_eq!(cpu.state.dt, 1);
+        assert_eq!(cpu.state.v[0], 7);
+        assert!(!cpu.sound());
+
+        cpu.state.st = 2;
+        let mut frames = 0;
+        while cpu.run_frame(0).is_ok() && cpu.sound() {
+            frames += 1;
+        }
+        assert_eq!(frames, 2);
+    }
+
+    #[test]
+    fn vip_timing() {
+        let rom = [0x70, 0x01, 0xD0, 0x01, 0x12, 0x00];
+        let mut cpu = Chip8::new();
+        cpu.set_quirks(Quirks::chip8());
+        cpu.load_rom(&rom).unwrap();
+
+        assert!(cpu.run_vip_frame().is_ok());
+        assert_eq!(cpu.state.v[0], 1);
+        // fixed-rate frames ignore the display wait
+        assert!(cpu.run_frame(100).is_ok());
+        assert_eq!(cpu.state.v[0], 34);
+
+        let mut cpu = Chip8::new();
+        cpu.load_rom(&rom).unwrap();
+        assert!(cpu.run_vip_frame().is_ok());
+
+        let loop_cycles = OpCode::AddByte { x: 0, byte: 1 }.cycles()
+            + OpCode::Draw { x: 0, y: 0, n: 1 }.cycles()
+            + OpCode::Jump { address: 0x200 }.cycles();
+        let budget = VIP_FRAME_CYCLES - VIP_DISPLAY_CYCLES;
+        let loops = budget.div_ceil(loop_cycles);
+        assert_eq!(u32::from(cpu.state.v[0]), loops);
+        assert!(cpu.overrun < loop_cycles);
+    }
+
     #[test]
     fn key_press_release() {
         let mut cpu = Chip8::new();
@@ -888,4 +1440,159 @@ mod tests {
         cpu.release_key(1);
         assert_eq!(cpu.state().keys[1], false);;
     }
+
+    #[test]
+    fn out_of_range_memory() {
+        let mut cpu = Chip8::new();
+        cpu.state.i = 0xFFE;
+        cpu.state.v[..3].clone_from_slice(&[1, 2, 3]);
+
+        let result = cpu.execute(OpCode::Save { x: 2 });
+        assert_eq!(result, Err(Chip8Error::AddressOutOfRangeError));
+        assert_eq!(cpu.state.error_address, Some(0x1000));
+        assert_eq!(cpu.state.memory[0xFFE], 0);
+
+        cpu.set_quirks(Quirks {
+            wrap: true,
+            load_store: false,
+            ..Quirks::default()
+        });
+        assert!(cpu.execute(OpCode::Save { x: 2 }).is_ok());
+        assert_eq!(cpu.state.memory[0xFFE..], [1, 2]);
+        assert_eq!(cpu.state.memory[0], 3);
+        assert_eq!(cpu.state.i, 1);
+
+        cpu.state.i = 0xFFF;
+        cpu.state.v[0] = 0x10;
+        assert!(cpu.execute(OpCode::AddAddress { x: 0 }).is_ok());
+        assert_eq!(cpu.state.i, 0xF);
+    }
+
+    #[test]
+    fn pc_past_memory() {
+        let mut cpu = Chip8::new();
+        cpu.state.v[0] = 0xFF;
+        let result = cpu.execute(OpCode::JumpOffset { address: 0xFFF });
+        assert_eq!(result, Err(Chip8Error::AddressOutOfRangeError));
+
+        cpu.state.pc = 0xFFF;
+        let fault = cpu.execute_cycle().unwrap_err();
+        assert_eq!(fault.address, Some(0x1000));
+        assert_eq!(cpu.state.pc, 0xFFF);
+
+        cpu.set_quirks(Quirks {
+            wrap: true,
+            ..Quirks::chip8()
+        });
+        cpu.state.memory[0xFFF] = 0x61;
+        assert!(cpu.execute_cycle().is_ok());
+        assert_eq!(cpu.state.v[1], rom::ROM[0]);
+        assert_eq!(cpu.state.pc, 0x1);
+        assert!(cpu.execute(OpCode::JumpOffset { address: 0xFFF }).is_ok());
+        assert_eq!(cpu.state.pc, 0xFE);
+    }
+
+    #[test]
+    fn draw_clips() {
+        let mut cpu = Chip8::new();
+        cpu.state.i = 0x300;
+        cpu.state.memory[0x300..0x304].clone_from_slice(&[0xFF; 4]);
+        cpu.state.v[0] = 60;
+        cpu.state.v[1] = 30;
+
+        assert!(cpu.execute(OpCode::Draw { x: 0, y: 1, n: 4 }).is_ok());
+        assert_eq!(cpu.state.video[30 * 8 + 7], 0x0F);
+        assert_eq!(cpu.state.video[31 * 8 + 7], 0x0F);
+        assert_eq!(cpu.state.video[0], 0);
+        assert_eq!(cpu.state.video.iter().filter(|b| **b != 0).count(), 2);
+
+        cpu.state.v[0] = 64 + 60;
+        cpu.state.v[1] = 32 + 30;
+        assert!(cpu.execute(OpCode::Draw { x: 0, y: 1, n: 4 }).is_ok());
+        assert_eq!(cpu.state.v[0xF], 1);
+        assert!(cpu.state.video.iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn state_bytes_round_trip() {
+        let mut cpu = Chip8::new();
+        cpu.load_rom(&[0x60, 0x05, 0xA3, 0x00, 0x22, 0x08]).unwrap();
+        cpu.execute_all(&[OpCode::Draw { x: 0, y: 0, n: 5 }]).unwrap();
+        for _ in 0..3 {
+            cpu.execute_cycle().unwrap();
+        }
+        cpu.state.dt = 7;
+
+        let bytes = cpu.state().to_bytes();
+        let state = Chip8State::from_bytes(&bytes).unwrap();
+        assert_eq!(state.to_bytes(), bytes);
+        assert_eq!((state.pc(), state.i(), state.sp()), (0x208, 0x300, 1));
+        assert_eq!(state.stack(), cpu.state().stack());
+        assert_eq!(state.video(), cpu.state().video());
+        assert!(Chip8State::from_bytes(&bytes[1..]).is_none());
+    }
+
+    #[test]
+    fn seeded_random() {
+        let mut a = Chip8::new();
+        let mut b = Chip8::new();
+        a.seed_random(42);
+        b.seed_random(42);
+        for _ in 0..8 {
+            a.random(0, 0xFF);
+            b.random(0, 0xFF);
+            assert_eq!(a.state.v[0], b.state.v[0]);
+        }
+    }
+
+    // instructions that move the PC, seeded at the end of memory where
+    // random bytes rarely put them
+    const PC_OPS: [u16; 10] = [
+        0xF00A, 0x3000, 0x4000, 0x5000, 0x9010, 0xE09E, 0xE0A1, 0x00EE, 0x2FFE, 0xBFFF,
+    ];
+
+    #[test]
+    fn wait_key_at_end_with_wrap() {
+        let mut cpu = Chip8::new();
+        cpu.set_quirks(Quirks { wrap: true, ..Quirks::chip8() });
+        cpu.state.memory[0xFFE..].copy_from_slice(&[0xF0, 0x0A]);
+        cpu.state.pc = 0xFFE;
+
+        cpu.execute_cycle().unwrap();
+        assert_eq!(cpu.state.pc, 0xFFE);
+        cpu.press_key(3);
+        cpu.execute_cycle().unwrap();
+        assert_eq!((cpu.state.pc, cpu.state.v[0]), (0, 3));
+    }
+
+    proptest! {
+        #[test]
+        fn execute_cycle_never_panics(
+            memory in prop::collection::vec(any::<u8>(), Chip8State::MEMORY_SIZE),
+            v in prop::array::uniform16(any::<u8>()),
+            pc in 0..Chip8State::MEMORY_SIZE + 0x100,
+            i in 0..Chip8State::MEMORY_SIZE + 0x100,
+            sp in 0..=Chip8State::STACK_SIZE,
+            wrap in any::<bool>(),
+            end in prop::option::of(prop::sample::select(PC_OPS.to_vec())),
+        ) {
+            let mut cpu = Chip8::new();
+            cpu.set_quirks(Quirks { wrap, ..Quirks::default() });
+            cpu.state.memory.clone_from_slice(&memory);
+            cpu.state.v = v;
+            cpu.state.pc = pc;
+            cpu.state.i = i;
+            cpu.state.sp = sp;
+            if let Some(op) = end {
+                cpu.state.memory[0xFFE..].copy_from_slice(&op.to_be_bytes());
+                cpu.state.pc = 0xFFE;
+            }
+
+            for _ in 0..1000 {
+                if cpu.execute_cycle().is_err() {
+                    break;
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,246 @@
+use cpu::Chip8State;
+use opcode::Register;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+
+// a register or memory byte that can be watched or set from the console
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Location {
+    Register(Register),
+    Pc,
+    Memory(usize),
+}
+
+impl Location {
+    pub fn read(self, state: &Chip8State) -> Option<u16> {
+        let value = match self {
+            Location::Register(Register::V(x)) => u16::from(*state.registers().get(x)?),
+            Location::Register(Register::I) => state.i() as u16,
+            Location::Register(Register::DT) => u16::from(state.dt()),
+            Location::Register(Register::ST) => u16::from(state.st()),
+            Location::Register(Register::SP) => state.sp() as u16,
+            Location::Pc => state.pc() as u16,
+            Location::Memory(address) => u16::from(*state.memory().get(address)?),
+        };
+        Some(value)
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Location::Register(Register::V(x)) => write!(f, "V{:X}", x),
+            Location::Register(register) => write!(f, "{:?}", register),
+            Location::Pc => write!(f, "PC"),
+            Location::Memory(address) => write!(f, "[{:04X}]", address),
+        }
+    }
+}
+
+impl FromStr for Location {
+    type Err = String;
+
+    // register names, or a hex address for a memory byte
+    fn from_str(s: &str) -> Result<Location, String> {
+        let location = match s.to_uppercase().as_str() {
+            "I" => Location::Register(Register::I),
+            "DT" => Location::Register(Register::DT),
+            "ST" => Location::Register(Register::ST),
+            "SP" => Location::Register(Register::SP),
+            "PC" => Location::Pc,
+            s if s.len() == 2 && s.starts_with('V') => usize::from_str_radix(&s[1..], 16)
+                .map(|x| Location::Register(Register::V(x)))
+                .map_err(|_| format!("unknown register {}", s))?,
+            _ => Location::Memory(parse_address(s)?),
+        };
+        Ok(location)
+    }
+}
+
+// addresses are hex, with or without a 0x, # or $ prefix
+pub fn parse_address(s: &str) -> Result<usize, String> {
+    let digits = s
+        .trim_start_matches("0x")
+        .trim_start_matches("0X")
+        .trim_start_matches(['#', '$'])
+        .trim_matches(['[', ']']);
+    match usize::from_str_radix(digits, 16) {
+        Ok(address) if address < Chip8State::MEMORY_SIZE => Ok(address),
+        Ok(_) => Err(format!("address out of range {}", s)),
+        Err(_) => Err(format!("bad address {}", s)),
+    }
+}
+
+// why execution stopped before the end of a frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stop {
+    Breakpoint(usize),
+    Watch { location: Location, old: u16, new: u16 },
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Breakpoint(address) => write!(f, "Breakpoint at {:04X}", address),
+            Stop::Watch { location, old, new } => {
+                write!(f, "Watch {}: {:02X} -> {:02X}", location, old, new)
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<usize>,
+    // watched locations with their last seen values
+    watches: Vec<(Location, u16)>,
+    trace: bool,
+    // set on a breakpoint so resuming runs the instruction it stopped at
+    resume: bool,
+    stop: Option<Stop>,
+}
+
+impl Debugger {
+    #[inline(always)]
+    pub fn active(&self) -> bool {
+        !self.breakpoints.is_empty() || !self.watches.is_empty()
+    }
+
+    pub fn add_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    #[inline(always)]
+    pub fn is_breakpoint(&self, address: usize) -> bool {
+        self.breakpoints.contains(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &usize> {
+        self.breakpoints.iter()
+    }
+
+    pub fn add_watch(&mut self, location: Location, state: &Chip8State) -> bool {
+        match location.read(state) {
+            Some(value) if !self.watches.iter().any(|(l, _)| *l == location) => {
+                self.watches.push((location, value));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn remove_watch(&mut self, location: Location) -> bool {
+        let n = self.watches.len();
+        self.watches.retain(|(l, _)| *l != location);
+        self.watches.len() != n
+    }
+
+    pub fn watches(&self) -> impl Iterator<Item = &Location> {
+        self.watches.iter().map(|(location, _)| location)
+    }
+
+    #[inline(always)]
+    pub fn trace(&self) -> bool {
+        self.trace
+    }
+
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    // whether to stop before executing the instruction at `pc`
+    pub fn before(&mut self, pc: usize) -> bool {
+        if mem::replace(&mut self.resume, false) || !self.breakpoints.contains(&pc) {
+            return false;
+        }
+
+        self.stop = Some(Stop::Breakpoint(pc));
+        self.resume = true;
+        true
+    }
+
+    // whether to stop after an instruction changed a watched location
+    pub fn after(&mut self, state: &Chip8State) -> bool {
+        let mut stop = None;
+        for (location, value) in self.watches.iter_mut() {
+            let new = location.read(state).unwrap_or(*value);
+            if new != *value && stop.is_none() {
+                stop = Some(Stop::Watch {
+                    location: *location,
+                    old: *value,
+                    new,
+                });
+            }
+            *value = new;
+        }
+
+        self.stop = stop.or(self.stop);
+        stop.is_some()
+    }
+
+    // takes on the current values without stopping, e.g. after a reset
+    pub fn sync(&mut self, state: &Chip8State) {
+        self.sync_watches(state);
+        self.resume = false;
+    }
+
+    // as sync, but a pending resume from a breakpoint still goes ahead
+    pub fn sync_watches(&mut self, state: &Chip8State) {
+        for (location, value) in self.watches.iter_mut() {
+            *value = location.read(state).unwrap_or(*value);
+        }
+    }
+
+    pub fn take_stop(&mut self) -> Option<Stop> {
+        self.stop.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_locations() {
+        assert_eq!("v3".parse(), Ok(Location::Register(Register::V(3))));
+        assert_eq!("VF".parse(), Ok(Location::Register(Register::V(0xF))));
+        assert_eq!("I".parse(), Ok(Location::Register(Register::I)));
+        assert_eq!("pc".parse(), Ok(Location::Pc));
+        assert_eq!("0x300".parse(), Ok(Location::Memory(0x300)));
+        assert_eq!("[2A0]".parse(), Ok(Location::Memory(0x2A0)));
+        assert!("VG".parse::<Location>().is_err());
+        assert!("foo".parse::<Location>().is_err());
+        assert!("1000".parse::<Location>().is_err());
+        assert!(parse_address("ffffffffffffffff").is_err());
+
+        assert_eq!(Location::Register(Register::V(0xA)).to_string(), "VA");
+        assert_eq!(Location::Memory(0x300).to_string(), "[0300]");
+    }
+
+    #[test]
+    fn breakpoint_resumes() {
+        let mut debugger = Debugger::default();
+        assert!(!debugger.active());
+        debugger.add_breakpoint(0x23A);
+        assert!(debugger.active());
+
+        assert!(!debugger.before(0x238));
+        assert!(debugger.before(0x23A));
+        assert_eq!(debugger.take_stop(), Some(Stop::Breakpoint(0x23A)));
+        assert!(!debugger.before(0x23A));
+        assert!(debugger.before(0x23A));
+
+        assert!(debugger.remove_breakpoint(0x23A));
+        assert!(!debugger.remove_breakpoint(0x23A));
+    }
+}
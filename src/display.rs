@@ -1,43 +1,33 @@
 use util::Cache;
-use cpu::OpCode;
-use logger::Logger;
+use browser::{Entry, View};
+use config::Config;
+use cpu::{Chip8State, OpCode};
+use filter::{Filter, FrameFilter};
+use input::{Input, KEYPAD};
+use layout::{Layout, Mode, Scaling};
+use log::Level;
+use logview::ROWS;
 use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
 use sdl2::render::{Canvas, Texture};
 use sdl2::ttf::{Font, Sdl2TtfContext};
-use sdl2::video::Window;
+use sdl2::video::{FullscreenType, Window};
 use sdl2::Sdl;
-use sdl2_sys::SDL_WindowFlags;
-use std::cell::RefCell;
-use std::collections::VecDeque;
+use sdl2_sys::{SDL_RendererFlags, SDL_WindowFlags};
+use std::cell::{Cell, RefCell};
 use std::fmt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use theme::{Theme, Themes};
 use vm::UpdateState;
 
 
-const WINDOW_WIDTH: u32 = 1024;
-const WINDOW_HEIGHT: u32 = 576;
 const FONT_SIZE: u16 = 28;
 const LINE_HEIGHT: i32 = 43;
-const N_MESSAGES: usize = 7;
 const N_INSTRUCTIONS: usize = 25;
 
 lazy_static! {
     static ref FONT_PATH: &'static Path = Path::new("../../resources/SourceCodePro-Semibold.ttf");
-    static ref COLOR_DEFAULT: Color = Color::RGBA(166, 172, 205, 0xFF);
-    static ref COLOR_BLUE: Color = Color::RGBA(130, 170, 255, 255);
-    static ref COLOR_MAGENTA: Color = Color::RGBA(198, 146, 233, 255);
-    static ref COLOR_BG: Color = Color::RGBA(27, 31, 43, 0xFF);
-    static ref COLOR_PANEL: Color = Color::RGBA(42, 46, 62, 0xFF);
-    static ref COLOR_HIGHLIGHT: Color = Color::RGBA(27, 31, 43, 255);
-    static ref COLOR_PX_OFF: Color = Color::RGBA(0x8F, 0x91, 0x85, 0xFF);
-    static ref COLOR_PX_ON: Color = Color::RGBA(0x11, 0x13, 0x2B, 0xFF);
-    static ref RECT_SCREEN_TARGET: Rect = Rect::new(40, 40, 1470, 735);
-    static ref RECT_SCREEN: Rect = Rect::new(20, 20, 1510, 775);
-    static ref RECT_LOG: Rect = Rect::new(20, 815, 700, 317);
-    static ref RECT_INSTRUCTIONS: Rect = Rect::new(1550, 20, 478, 1112);
-    static ref RECT_REGISTERS: Rect = Rect::new(740, 815, 790, 317);
 }
 
 pub type TextureCache = Cache<String, Texture>;
@@ -47,11 +37,12 @@ macro_rules! text {
     ($context:ident { $($style:expr => $x:expr, $y:expr => $text:expr)* } ) => ({
         let mut canvas = $context.canvas.borrow_mut();
         $({
-            let key = format!("{}|{}", $text, $style);
+            let color = $style.color(&$context.theme.borrow());
+            let size = $context.layout.get().font_size(FONT_SIZE);
+            let key = format!("{}|{}|{:02X}{:02X}{:02X}|{}", $text, $style, color.r, color.g, color.b, size);
             let text: String = if $text == "" { " ".to_owned() } else { $text.to_owned() };
             let texture = $context.cache.get(&key).unwrap_or_else(|| {
-                let color = $style.color();
-                let surface = $context.font.render(&text).blended(color).unwrap();
+                let surface = $context.font.borrow().render(&text).blended(color).unwrap();
                 let creator = canvas.texture_creator();
                 let texture = creator.create_texture_from_surface(&surface).unwrap();
                 $context.cache.put(key.clone(), texture);
@@ -76,14 +67,27 @@ pub enum Style {
     Default,
     Address,
     Instruction,
+    Error,
+    Warn,
 }
 
 impl Style {
-    fn color(&self) -> Color {
+    fn color(&self, theme: &Theme) -> Color {
         match self {
-            Style::Default => *COLOR_DEFAULT,
-            Style::Address => *COLOR_BLUE,
-            Style::Instruction => *COLOR_MAGENTA,
+            Style::Default => theme.text,
+            Style::Address => theme.address,
+            Style::Instruction => theme.instruction,
+            Style::Error => theme.error,
+            Style::Warn => theme.warn,
+        }
+    }
+
+    fn level(level: Level) -> Style {
+        match level {
+            Level::Error => Style::Error,
+            Level::Warn => Style::Warn,
+            Level::Info => Style::Default,
+            Level::Debug | Level::Trace => Style::Instruction,
         }
     }
 }
@@ -97,30 +101,44 @@ impl fmt::Display for Style {
 struct Context<'a> {
     cache: &'a TextureCache,
     canvas: Rc<RefCell<Canvas<Window>>>,
-    log: &'static Logger,
-    font: Font<'a, 'static>,
+    font: RefCell<Font<'a, 'static>>,
+    layout: Cell<Layout>,
+    theme: RefCell<Theme>,
+    filter: Cell<Filter>,
 }
 
 trait Component {
-    fn rect(&self) -> Rect;
+    fn rect(&self, layout: &Layout) -> Rect;
+
+    fn debugger(&self) -> bool {
+        true
+    }
 
     fn update(&mut self, context: ContextRef, state: &UpdateState);
     fn render(&mut self, context: ContextRef, state: &UpdateState);
 }
 
-pub struct Screen {}
+pub struct Screen {
+    filter: FrameFilter,
+}
 
 impl Screen {
     #[inline(always)]
     fn new() -> Screen {
-        Screen {}
+        Screen {
+            filter: FrameFilter::new(Filter::None),
+        }
     }
 }
 
 impl Component for Screen {
     #[inline(always)]
-    fn rect(&self) -> Rect {
-        *RECT_SCREEN
+    fn rect(&self, layout: &Layout) -> Rect {
+        layout.screen
+    }
+
+    fn debugger(&self) -> bool {
+        false
     }
 
     fn update(&mut self, _ctx: ContextRef, _state: &UpdateState) {}
@@ -133,39 +151,47 @@ impl Component for Screen {
             .unwrap_or_else(|| {
                 let texture = canvas
                     .texture_creator()
-                    .create_texture_streaming(PixelFormatEnum::RGB24, 64, 32)
+                    .create_texture_streaming(
+                        PixelFormatEnum::RGB24,
+                        Chip8State::WIDTH as u32,
+                        Chip8State::HEIGHT as u32,
+                    )
                     .unwrap();
                 context.cache.put("screen".to_owned(), texture);
                 context.cache.get_mut(&"screen".to_owned()).unwrap()
             });
 
+        self.filter.set_filter(context.filter.get());
+        let intensity = self.filter.apply(state.cpu.video());
+
         screen
             .with_lock(None, |buffer: &mut [u8], _: usize| {
-                let video = state.cpu.video();
-                for byte_offset in 0..video.len() {
-                    let byte = video[byte_offset];
-
-                    for bit_offset in 0..8 {
-                        let i = (byte_offset * 8 * 3) + (bit_offset * 3);
-                        let color = match byte & (1 << (7 - bit_offset)) {
-                            0 => *COLOR_PX_OFF,
-                            _ => *COLOR_PX_ON,
-                        };
-
-                        buffer[i] = color.r;
-                        buffer[i + 1] = color.g;
-                        buffer[i + 2] = color.b;
-                    }
+                let theme = context.theme.borrow();
+                let (off, on) = (theme.pixel(0), theme.pixel(1));
+
+                for (px, value) in intensity.iter().enumerate() {
+                    let i = px * 3;
+                    let color = mix(off, on, *value);
+
+                    buffer[i] = color.r;
+                    buffer[i + 1] = color.g;
+                    buffer[i + 2] = color.b;
                 }
             })
             .unwrap();
 
         canvas
-            .copy(&screen, None, Some(*RECT_SCREEN_TARGET))
+            .copy(screen, None, Some(context.layout.get().screen_target))
             .unwrap();
     }
 }
 
+#[inline(always)]
+fn mix(from: Color, to: Color, t: f32) -> Color {
+    let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    Color::RGB(channel(from.r, to.r), channel(from.g, to.g), channel(from.b, to.b))
+}
+
 pub struct Instructions {
     offset: usize,
     instructions: [u16; N_INSTRUCTIONS],
@@ -185,14 +211,18 @@ impl Instructions {
 
 impl Component for Instructions {
     #[inline(always)]
-    fn rect(&self) -> Rect {
-        *RECT_INSTRUCTIONS
+    fn rect(&self, layout: &Layout) -> Rect {
+        layout.instructions
     }
 
     fn update(&mut self, _ctx: ContextRef, state: &UpdateState) {
         let cpu = &state.cpu;
-        if cpu.pc() < self.offset + 4 || cpu.pc() > self.offset + N_INSTRUCTIONS * 2 - 4 {
-            self.offset = cpu.pc() - 4;
+        if let Some(ref fault) = state.run.fault {
+            // centre the faulting instruction
+            self.offset = fault.pc.saturating_sub(N_INSTRUCTIONS / 2 * 2);
+        } else if cpu.pc() < self.offset + 4 || cpu.pc() > self.offset + N_INSTRUCTIONS * 2 - 4
+        {
+            self.offset = cpu.pc().saturating_sub(4);
         }
         for i in 0..N_INSTRUCTIONS {
             let address = self.offset + i * 2;
@@ -204,32 +234,40 @@ impl Component for Instructions {
         }
     }
 
-    fn render(&mut self, context: ContextRef, _state: &UpdateState) {
-        let x = self.rect().left() + 20;
-        let mut y = self.rect().top() + 20;
+    fn render(&mut self, context: ContextRef, state: &UpdateState) {
+        let layout = context.layout.get();
+        let rect = self.rect(&layout);
+        let x = rect.left() + layout.px(20);
+        let mut y = rect.top() + layout.px(20);
 
         for i in 0..N_INSTRUCTIONS {
             let address = self.offset + i * 2;
             let inst = self.instructions[i];
 
             if self.highlighted == address {
-                let width = self.rect().width() - 20;
-                let rect = Rect::new(x - 10, y - 3, width, LINE_HEIGHT as u32);
+                let width = rect.width() - layout.px(20) as u32;
+                let line = layout.px(LINE_HEIGHT) as u32;
+                let rect = Rect::new(x - layout.px(10), y - layout.px(3), width, line);
                 let mut canvas = context.canvas.borrow_mut();
-                canvas.set_draw_color(*COLOR_HIGHLIGHT);
+                canvas.set_draw_color(context.theme.borrow().highlight);
                 canvas.fill_rect(rect).unwrap();
             }
 
             let (op, params) = OpCode::disassemble(inst);
 
+            let style = if state.debugger.is_breakpoint(address) {
+                Style::Error
+            } else {
+                Style::Address
+            };
             text!(context {
-                Style::Address     => x,       y => format!("{:04X}", address)
-                Style::Instruction => x + 85,  y => format!("{:04X}", inst)
-                Style::Default     => x + 170, y => op
-                Style::Default     => x + 280, y => params
+                style              => x,                   y => format!("{:04X}", address)
+                Style::Instruction => x + layout.px(85),  y => format!("{:04X}", inst)
+                Style::Default     => x + layout.px(170), y => op
+                Style::Default     => x + layout.px(280), y => params
             });
 
-            y += LINE_HEIGHT;
+            y += layout.px(LINE_HEIGHT);
         }
     }
 }
@@ -241,7 +279,8 @@ pub struct Registers {
     pub i: usize,
     pub dt: u8,
     pub st: u8,
-    pub hz: u32,
+    pub rate: String,
+    pub speed: String,
     pub fps: i32,
 }
 
@@ -255,7 +294,8 @@ impl Registers {
             i: 0,
             dt: 0,
             st: 0,
-            hz: 0,
+            rate: String::new(),
+            speed: String::new(),
             fps: 0,
         }
     }
@@ -263,8 +303,8 @@ impl Registers {
 
 impl Component for Registers {
     #[inline(always)]
-    fn rect(&self) -> Rect {
-        *RECT_REGISTERS
+    fn rect(&self, layout: &Layout) -> Rect {
+        layout.registers
     }
 
     fn update(&mut self, _ctx: ContextRef, state: &UpdateState) {
@@ -274,98 +314,376 @@ impl Component for Registers {
         self.dt = state.cpu.dt();
         self.st = state.cpu.st();
         self.fps = state.run.fps;
-        self.hz = state.run.hz;
+        self.rate = state.run.speed.rate();
+        self.speed = state.run.speed.to_string();
         self.v.clone_from_slice(&state.cpu.registers());
     }
 
     fn render(&mut self, context: ContextRef, _state: &UpdateState) {
-        let rect = self.rect();
-        let mut x = rect.left() + 20;
-        let separator = Rect::new(rect.left() + 20, rect.top() + 110, rect.width() - 40, 5);
+        let layout = context.layout.get();
+        let px = |n| layout.px(n);
+        let rect = self.rect(&layout);
+        let mut x = rect.left() + px(20);
+        let separator = Rect::new(
+            rect.left() + px(20),
+            rect.top() + px(110),
+            rect.width() - px(40) as u32,
+            px(5).max(1) as u32,
+        );
 
         {
             let mut canvas = context.canvas.borrow_mut();
-            canvas.set_draw_color(*COLOR_BG);
+            canvas.set_draw_color(context.theme.borrow().background);
             canvas.fill_rect(separator).unwrap();
         }
 
         for col in 0..4 {
-            let mut y = rect.top() + 135;
+            let mut y = rect.top() + px(135);
             for row in 0..4 {
                 let i = col * 4 + row;
                 let v = self.v[i];
                 text!(context {
-                    Style::Default     => x,       y => format!("V{:X}", i)
-                    Style::Address     => x + 60,  y => format!("{:02X}", v)
-                    Style::Instruction => x + 100, y => format!("({})", v)
+                    Style::Default     => x,           y => format!("V{:X}", i)
+                    Style::Address     => x + px(60),  y => format!("{:02X}", v)
+                    Style::Instruction => x + px(100), y => format!("({})", v)
                 });
-                y += LINE_HEIGHT;
+                y += px(LINE_HEIGHT);
             }
 
-            x += 200;
+            x += px(200);
         }
 
-        let x = rect.left() + 20;
-        let y = rect.top() + 10;
+        let x = rect.left() + px(20);
+        let y = rect.top() + px(10);
+        let y2 = y + px(40);
 
         text!(context {
-            Style::Default => x,       y      => "PC"
-            Style::Address => x + 60,  y      => format!("{:04X}", self.pc)
-            Style::Default => x + 200, y      => "ST"
-            Style::Address => x + 260, y      => format!("{:02X}", self.st)
-            Style::Default => x + 400, y      => "DT"
-            Style::Address => x + 460, y      => format!("{:02X}", self.dt)
-            Style::Default => x + 600, y      => "SP"
-            Style::Address => x + 660, y      => format!("{:02X}", self.sp)
-            Style::Default => x,       y + 40 => "I"
-            Style::Address => x + 60,  y + 40 => format!("{:04X }", self.i)
-            Style::Default => x + 200, y + 40 => "HZ"
-            Style::Address => x + 260, y + 40 => format!("{:04}", self.hz)
-            Style::Default => x + 400, y + 40 => "FPS"
-            Style::Address => x + 460, y + 40 => format!("{:02}", self.fps)
+            Style::Default => x,           y  => "PC"
+            Style::Address => x + px(60),  y  => format!("{:04X}", self.pc)
+            Style::Default => x + px(200), y  => "ST"
+            Style::Address => x + px(260), y  => format!("{:02X}", self.st)
+            Style::Default => x + px(400), y  => "DT"
+            Style::Address => x + px(460), y  => format!("{:02X}", self.dt)
+            Style::Default => x + px(600), y  => "SP"
+            Style::Address => x + px(660), y  => format!("{:02X}", self.sp)
+            Style::Default => x,           y2 => "I"
+            Style::Address => x + px(60),  y2 => format!("{:04X }", self.i)
+            Style::Default => x + px(200), y2 => "IPF"
+            Style::Address => x + px(260), y2 => self.rate.clone()
+            Style::Default => x + px(400), y2 => "FPS"
+            Style::Address => x + px(460), y2 => format!("{:02}", self.fps)
+            Style::Default => x + px(600), y2 => "SPD"
+            Style::Address => x + px(660), y2 => self.speed.clone()
         });
     }
 }
 
-pub struct Log {
-    messages: VecDeque<String>,
-}
+pub struct Log {}
 
 impl Log {
     #[inline(always)]
     fn new() -> Log {
-        Log {
-            messages: VecDeque::new(),
-        }
+        Log {}
     }
 }
 
 impl Component for Log {
     #[inline(always)]
-    fn rect(&self) -> Rect {
-        *RECT_LOG
+    fn rect(&self, layout: &Layout) -> Rect {
+        layout.log
     }
 
-    fn update(&mut self, ctx: ContextRef, _state: &UpdateState) {
-        if ctx.log.unread() > 0 {
-            let read = ctx.log.read();
-            self.messages.extend(read);
-            while self.messages.len() > N_MESSAGES {
-                self.messages.pop_front();
+    fn update(&mut self, _ctx: ContextRef, _state: &UpdateState) {}
+
+    fn render(&mut self, context: ContextRef, state: &UpdateState) {
+        let layout = context.layout.get();
+        let rect = self.rect(&layout);
+        let x = rect.left() + layout.px(20);
+        let (log, console) = (state.log, state.console);
+        let status = console.active() || log.status();
+        let rows = if status { ROWS - 1 } else { ROWS };
+
+        let mut y = rect.top() + layout.px(10);
+        for entry in log.visible(rows) {
+            text!(context {
+                Style::level(entry.level) => x, y => entry.message.to_owned()
+            });
+            y += layout.px(LINE_HEIGHT);
+        }
+
+        if status {
+            let y = rect.top() + layout.px(10) + layout.px(LINE_HEIGHT) * rows as i32;
+            let line = if console.active() {
+                format!("> {}_", console.input())
+            } else {
+                let cursor = if log.searching() { "_" } else { "" };
+                format!("/{}{}", log.search(), cursor)
+            };
+            let scrolled = match log.scrolled() {
+                0 => String::new(),
+                n => format!("+{}", n),
+            };
+            text!(context {
+                Style::Address     => x,                               y => line
+                Style::Instruction => rect.right() - layout.px(120), y => scrolled
+            });
+        }
+    }
+}
+
+pub struct Keymap {}
+
+impl Keymap {
+    #[inline(always)]
+    fn new() -> Keymap {
+        Keymap {}
+    }
+}
+
+impl Component for Keymap {
+    #[inline(always)]
+    fn rect(&self, layout: &Layout) -> Rect {
+        layout.screen
+    }
+
+    fn debugger(&self) -> bool {
+        false
+    }
+
+    fn update(&mut self, _ctx: ContextRef, _state: &UpdateState) {}
+
+    fn render(&mut self, context: ContextRef, state: &UpdateState) {
+        let layout = context.layout.get();
+        let px = |n| layout.px(n);
+        let rect = self.rect(&layout);
+        let names = |inputs: &[Input]| {
+            let names: Vec<String> = inputs.iter().map(|input| input.name()).collect();
+            if names.is_empty() {
+                "-".to_owned()
+            } else {
+                names.join("/")
+            }
+        };
+
+        {
+            let mut canvas = context.canvas.borrow_mut();
+            canvas.set_draw_color(context.theme.borrow().panel);
+            canvas.fill_rect(rect).unwrap();
+        }
+
+        let mut y = rect.top() + px(20);
+        for row in KEYPAD.iter() {
+            let mut x = rect.left() + px(20);
+            for key in row.iter() {
+                text!(context {
+                    Style::Address => x,          y => format!("{:X}", key)
+                    Style::Default => x + px(40), y => names(state.bindings.keys(*key))
+                });
+                x += px(220);
             }
+            y += px(LINE_HEIGHT) * 2;
+        }
+
+        let x = rect.left() + px(920);
+        let mut y = rect.top() + px(20);
+        for (hotkey, inputs) in state.bindings.hotkeys() {
+            text!(context {
+                Style::Instruction => x,           y => hotkey.name()
+                Style::Default     => x + px(220), y => names(inputs)
+            });
+            y += px(LINE_HEIGHT);
         }
     }
+}
 
-    fn render(&mut self, context: ContextRef, _state: &UpdateState) {
-        let font = Style::Default;
+pub struct RomBrowser {}
+
+impl RomBrowser {
+    #[inline(always)]
+    fn new() -> RomBrowser {
+        RomBrowser {}
+    }
+}
+
+impl Component for RomBrowser {
+    #[inline(always)]
+    fn rect(&self, layout: &Layout) -> Rect {
+        layout.screen
+    }
+
+    fn debugger(&self) -> bool {
+        false
+    }
+
+    fn update(&mut self, _ctx: ContextRef, _state: &UpdateState) {}
+
+    fn render(&mut self, context: ContextRef, state: &UpdateState) {
+        let browser = match state.browser {
+            Some(browser) => browser,
+            None => return,
+        };
+
+        let layout = context.layout.get();
+        let px = |n| layout.px(n);
+        let rect = self.rect(&layout);
+        let line = px(LINE_HEIGHT);
+        let x = rect.left() + px(20);
+        let top = rect.top() + px(20);
+        let footer = rect.bottom() - px(20) - line * 2;
+        let rows = ((footer - top - line) / line).max(1) as usize;
+        let entries = browser.entries();
+        let offset = (browser.selected() + 1).saturating_sub(rows);
+
+        {
+            let mut canvas = context.canvas.borrow_mut();
+            canvas.set_draw_color(context.theme.borrow().panel);
+            canvas.fill_rect(rect).unwrap();
+        }
+
+        let title = match browser.view() {
+            View::Files => browser.dir().display().to_string(),
+            View::Recent => "Recent files".to_owned(),
+        };
+        let filter = if browser.all() { "*" } else { "*.ch8" };
+
+        text!(context {
+            Style::Address     => x,                             top => title
+            Style::Instruction => rect.right() - px(20) - px(100), top => filter
+        });
+
+        let mut y = top + line;
+        for (i, entry) in entries.iter().enumerate().skip(offset).take(rows) {
+            if i == browser.selected() {
+                let width = rect.width() - px(20) as u32;
+                let rect = Rect::new(x - px(10), y - px(3), width, line as u32);
+                let mut canvas = context.canvas.borrow_mut();
+                canvas.set_draw_color(context.theme.borrow().highlight);
+                canvas.fill_rect(rect).unwrap();
+            }
+
+            let name = match (browser.view(), entry) {
+                (View::Recent, Entry::File { path, .. }) => path.display().to_string(),
+                _ => entry.name(),
+            };
+            let size = match entry {
+                Entry::File { size, .. } => format!("{}", size),
+                _ => String::new(),
+            };
+
+            text!(context {
+                Style::Default => x,                             y => name
+                Style::Address => rect.right() - px(20) - px(100), y => size
+            });
+            y += line;
+        }
 
-        let mut y = self.rect().top() + 10;
-        for message in &self.messages {
+        if entries.is_empty() {
             text!(context {
-                font => self.rect().left() + 20, y => message.to_owned()
+                Style::Default => x, y => "No ROMs found"
             });
-            y += LINE_HEIGHT;
         }
+
+        let hint = "Enter open  Backspace up  R recent  A all files  Esc close";
+        text!(context {
+            Style::Instruction => x, footer        => browser.preview()
+            Style::Default     => x, footer + line => hint
+        });
+    }
+}
+
+pub struct CheatList {}
+
+impl CheatList {
+    #[inline(always)]
+    fn new() -> CheatList {
+        CheatList {}
+    }
+}
+
+impl Component for CheatList {
+    #[inline(always)]
+    fn rect(&self, layout: &Layout) -> Rect {
+        layout.screen
+    }
+
+    fn debugger(&self) -> bool {
+        false
+    }
+
+    fn update(&mut self, _ctx: ContextRef, _state: &UpdateState) {}
+
+    fn render(&mut self, context: ContextRef, state: &UpdateState) {
+        let selected = match state.cheat_menu {
+            Some(selected) => selected,
+            None => return,
+        };
+
+        let layout = context.layout.get();
+        let px = |n| layout.px(n);
+        let rect = self.rect(&layout);
+        let line = px(LINE_HEIGHT);
+        let x = rect.left() + px(20);
+        let top = rect.top() + px(20);
+        let footer = rect.bottom() - px(20) - line * 2;
+        let rows = ((footer - top - line) / line).max(1) as usize;
+        let cheats = state.cheats.cheats();
+        let offset = (selected + 1).saturating_sub(rows);
+
+        {
+            let mut canvas = context.canvas.borrow_mut();
+            canvas.set_draw_color(context.theme.borrow().panel);
+            canvas.fill_rect(rect).unwrap();
+        }
+
+        let search = match state.cheats.search() {
+            Some(search) => format!("{} candidates", search.candidates().len()),
+            None => String::new(),
+        };
+        text!(context {
+            Style::Address     => x,                             top => "Cheats"
+            Style::Instruction => rect.right() - px(20) - px(300), top => search
+        });
+
+        let mut y = top + line;
+        for (i, cheat) in cheats.iter().enumerate().skip(offset).take(rows) {
+            if i == selected {
+                let width = rect.width() - px(20) as u32;
+                let rect = Rect::new(x - px(10), y - px(3), width, line as u32);
+                let mut canvas = context.canvas.borrow_mut();
+                canvas.set_draw_color(context.theme.borrow().highlight);
+                canvas.fill_rect(rect).unwrap();
+            }
+
+            let (style, on) = if cheat.enabled {
+                (Style::Default, "[x]")
+            } else {
+                (Style::Instruction, "[ ]")
+            };
+            text!(context {
+                style => x, y => format!("{} {}", on, cheat.name)
+            });
+            y += line;
+        }
+
+        if cheats.is_empty() {
+            let file = state
+                .cheats
+                .file()
+                .map_or_else(String::new, |path| path.display().to_string());
+            text!(context {
+                Style::Default     => x, y        => "No cheats, add them to"
+                Style::Instruction => x, y + line => file
+            });
+        }
+
+        let codes = cheats
+            .get(selected)
+            .map_or_else(String::new, |cheat| cheat.to_string());
+        let hint = "Enter toggle  Esc close";
+        text!(context {
+            Style::Instruction => x, footer        => codes
+            Style::Default     => x, footer + line => hint
+        });
     }
 }
 
@@ -373,8 +691,13 @@ struct Panel(Box<Component>);
 
 impl Component for Panel {
     #[inline(always)]
-    fn rect(&self) -> Rect {
-        self.0.rect()
+    fn rect(&self, layout: &Layout) -> Rect {
+        self.0.rect(layout)
+    }
+
+    #[inline(always)]
+    fn debugger(&self) -> bool {
+        self.0.debugger()
     }
 
     #[inline(always)]
@@ -385,8 +708,8 @@ impl Component for Panel {
     fn render(&mut self, context: ContextRef, state: &UpdateState) {
         {
             let mut canvas = context.canvas.borrow_mut();
-            canvas.set_draw_color(*COLOR_PANEL);
-            canvas.fill_rect(self.0.rect()).unwrap();
+            canvas.set_draw_color(context.theme.borrow().panel);
+            canvas.fill_rect(self.0.rect(&context.layout.get())).unwrap();
         }
 
         self.0.render(context, state);
@@ -395,7 +718,14 @@ impl Component for Panel {
 
 pub struct Display<'a> {
     context: ContextRef<'a>,
+    ttf: &'a Sdl2TtfContext,
+    font: PathBuf,
     panels: Vec<Panel>,
+    themes: Themes,
+    keymap: Option<Keymap>,
+    browser: RomBrowser,
+    cheats: CheatList,
+    vsync: bool,
     frame: u128,
 }
 
@@ -403,14 +733,15 @@ impl<'a> Display<'a> {
     pub fn new(
         sdl_context: &'a Sdl,
         ttf_context: &'a Sdl2TtfContext,
-        log: &'static Logger,
         cache: &'a TextureCache,
+        config: &Config,
     ) -> Display<'a> {
         let window = sdl_context
             .video()
             .unwrap()
-            .window("CHIP-8", WINDOW_WIDTH, WINDOW_HEIGHT)
+            .window("CHIP-8", config.width, config.height)
             .allow_highdpi()
+            .resizable()
             .build()
             .unwrap();
 
@@ -428,30 +759,185 @@ impl<'a> Display<'a> {
             panel!(Log::new()),
         ];
 
+        let vsync_flag = SDL_RendererFlags::SDL_RENDERER_PRESENTVSYNC as u32;
+        let vsync = canvas.info().flags & vsync_flag == vsync_flag;
+        let (width, height) = canvas.output_size().unwrap();
+        let layout = Layout::new(width, height, Mode::Debugger, Scaling::Integer);
+        let font_size = layout.font_size(FONT_SIZE);
+        let font = config
+            .font
+            .clone()
+            .unwrap_or_else(|| FONT_PATH.to_path_buf());
+
+        let mut themes = Themes::load();
+        if let Some(ref name) = config.theme {
+            if !themes.select(name) {
+                warn!("Unknown theme {}", name);
+            }
+        }
+
+        let filter = config
+            .filter
+            .as_ref()
+            .and_then(|filter| filter.parse().map_err(|err| warn!("{}", err)).ok())
+            .unwrap_or(Filter::None);
+
         let context = Rc::new(Context {
             cache,
-            log,
             canvas: Rc::new(RefCell::new(canvas)),
-            font: ttf_context.load_font(*FONT_PATH, FONT_SIZE).unwrap(),
+            font: RefCell::new(ttf_context.load_font(&font, font_size).unwrap()),
+            layout: Cell::new(layout),
+            theme: RefCell::new(themes.current().clone()),
+            filter: Cell::new(filter),
         });
 
-        Display {
+        let display = Display {
             context,
+            ttf: ttf_context,
+            font,
             panels,
+            themes,
+            keymap: None,
+            browser: RomBrowser::new(),
+            cheats: CheatList::new(),
+            vsync,
             frame: 0,
+        };
+        // only wanted while typing a search
+        display.text_input(false);
+        display
+    }
+
+    pub fn next_theme(&mut self) {
+        let theme = self.themes.cycle().clone();
+        info!("Theme: {}", theme.name);
+        *self.context.theme.borrow_mut() = theme;
+    }
+
+    pub fn select_theme(&mut self, name: &str) -> bool {
+        let found = self.themes.select(name);
+        if found {
+            *self.context.theme.borrow_mut() = self.themes.current().clone();
+        }
+        found
+    }
+
+    pub fn theme_name(&self) -> &str {
+        &self.themes.current().name
+    }
+
+    pub fn next_filter(&mut self) {
+        let filter = self.context.filter.get().cycle();
+        info!("Filter: {}", filter);
+        self.context.filter.set(filter);
+    }
+
+    pub fn filter(&self) -> Filter {
+        self.context.filter.get()
+    }
+
+    pub fn window_size(&self) -> (u32, u32) {
+        self.context.canvas.borrow().window().size()
+    }
+
+    // whether a point in window coordinates is over the log panel
+    pub fn over_log(&self, x: i32, y: i32) -> bool {
+        let canvas = self.context.canvas.borrow();
+        let (width, _) = canvas.window().size();
+        let (output, _) = canvas.output_size().unwrap();
+        let scale = output as f32 / width.max(1) as f32;
+        let layout = self.context.layout.get();
+        layout.mode == Mode::Debugger
+            && layout
+                .log
+                .contains_point(((x as f32 * scale) as i32, (y as f32 * scale) as i32))
+    }
+
+    pub fn text_input(&self, on: bool) {
+        let text_input = self.context.canvas.borrow().window().subsystem().text_input();
+        if on {
+            text_input.start();
+        } else {
+            text_input.stop();
+        }
+    }
+
+    pub fn toggle_keymap(&mut self) {
+        self.keymap = match self.keymap {
+            Some(_) => None,
+            None => Some(Keymap::new()),
+        };
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        let mut canvas = self.context.canvas.borrow_mut();
+        let window = canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+
+        if let Err(err) = window.set_fullscreen(fullscreen) {
+            error!("Error toggling fullscreen: {}", err);
         }
     }
 
+    pub fn toggle_mode(&mut self) {
+        let layout = self.context.layout.get();
+        let mode = match layout.mode {
+            Mode::Debugger => Mode::Game,
+            Mode::Game => Mode::Debugger,
+        };
+        info!("Layout: {:?}", mode);
+        self.relayout(layout.width, layout.height, mode, layout.scaling);
+    }
+
+    pub fn toggle_scaling(&mut self) {
+        let layout = self.context.layout.get();
+        let scaling = match layout.scaling {
+            Scaling::Integer => Scaling::Aspect,
+            Scaling::Aspect => Scaling::Integer,
+        };
+        info!("Scaling: {:?}", scaling);
+        self.relayout(layout.width, layout.height, layout.mode, scaling);
+    }
+
+    fn relayout(&mut self, width: u32, height: u32, mode: Mode, scaling: Scaling) {
+        let old = self.context.layout.get();
+        let layout = Layout::new(width, height, mode, scaling);
+        let size = layout.font_size(FONT_SIZE);
+
+        if size != old.font_size(FONT_SIZE) {
+            match self.ttf.load_font(&self.font, size) {
+                Ok(font) => *self.context.font.borrow_mut() = font,
+                Err(err) => error!("Error loading font: {}", err),
+            }
+        }
+
+        self.context.layout.set(layout);
+    }
+
     pub fn update(&mut self, state: &UpdateState) {
+        let (width, height) = self.context.canvas.borrow().output_size().unwrap();
+        let layout = self.context.layout.get();
+        if (width, height) != (layout.width, layout.height) {
+            self.relayout(width, height, layout.mode, layout.scaling);
+        }
+
         let context = &self.context;
+        let game = context.layout.get().mode == Mode::Game;
 
         {
             let mut canvas = context.canvas.borrow_mut();
-            canvas.set_draw_color(*COLOR_BG);
+            canvas.set_draw_color(context.theme.borrow().background);
             canvas.clear();
         }
 
         for p in &mut self.panels {
+            if game && p.debugger() {
+                continue;
+            }
+
             // TODO less awful
             if self.frame % 7 < 2 {
                 p.update(context.clone(), state);
@@ -459,11 +945,23 @@ impl<'a> Display<'a> {
             p.render(context.clone(), state);
         }
 
+        if let Some(ref mut keymap) = self.keymap {
+            keymap.render(context.clone(), state);
+        }
+
+        self.browser.render(context.clone(), state);
+        self.cheats.render(context.clone(), state);
+
         let mut canvas = self.context.canvas.borrow_mut();
         canvas.present();
         self.frame += 1;
     }
 
+    // whether presenting blocks until the next vertical blank
+    pub fn vsync(&self) -> bool {
+        self.vsync
+    }
+
     pub fn focused(&self) -> bool {
         let canvas = self.context.canvas.borrow();
         let flags = canvas.window().window_flags() as u32;
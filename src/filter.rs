@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+const FRAME_MS: f32 = 1000.0 / 60.0;
+const MAX_FRAMES: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    None,
+    Or,
+    Blend { frames: usize },
+    Decay { half_life: f32 },
+}
+
+impl Filter {
+    pub fn presets() -> Vec<Filter> {
+        vec![
+            Filter::None,
+            Filter::Or,
+            Filter::Blend { frames: 3 },
+            Filter::Decay { half_life: 40.0 },
+        ]
+    }
+
+    pub fn cycle(self) -> Filter {
+        let presets = Self::presets();
+        let i = presets
+            .iter()
+            .position(|f| *f == self)
+            .map_or(0, |i| (i + 1) % presets.len());
+        presets[i]
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::None
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Filter::None => write!(f, "none"),
+            Filter::Or => write!(f, "or"),
+            Filter::Blend { frames } => write!(f, "blend:{}", frames),
+            Filter::Decay { half_life } => write!(f, "decay:{}", half_life),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let param = parts.next();
+        let invalid = || format!("invalid filter {}", s);
+
+        match (name, param) {
+            ("none", None) => Ok(Filter::None),
+            ("or", None) => Ok(Filter::Or),
+            ("blend", None) => Ok(Filter::Blend { frames: 3 }),
+            ("blend", Some(n)) => match n.parse::<usize>() {
+                Ok(frames) if frames > 0 && frames <= MAX_FRAMES => Ok(Filter::Blend { frames }),
+                _ => Err(invalid()),
+            },
+            ("decay", None) => Ok(Filter::Decay { half_life: 40.0 }),
+            ("decay", Some(ms)) => match ms.parse::<f32>() {
+                Ok(half_life) if half_life > 0.0 => Ok(Filter::Decay { half_life }),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+pub struct FrameFilter {
+    filter: Filter,
+    history: VecDeque<Vec<u8>>,
+    intensity: Vec<f32>,
+}
+
+impl FrameFilter {
+    pub fn new(filter: Filter) -> FrameFilter {
+        FrameFilter {
+            filter,
+            history: VecDeque::new(),
+            intensity: Vec::new(),
+        }
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        if filter != self.filter {
+            self.filter = filter;
+            self.history.clear();
+            self.intensity.clear();
+        }
+    }
+
+    // returns a 0.0 - 1.0 intensity per pixel for the packed 1bpp video buffer
+    pub fn apply(&mut self, video: &[u8]) -> &[f32] {
+        let n_pixels = video.len() * 8;
+        if self.intensity.len() != n_pixels {
+            self.intensity = vec![0.0; n_pixels];
+            self.history.clear();
+        }
+
+        match self.filter {
+            Filter::None => self.lit(video, 1),
+            Filter::Or => self.lit(video, 2),
+            Filter::Blend { frames } => self.blend(video, frames),
+            Filter::Decay { half_life } => self.decay(video, half_life),
+        }
+
+        &self.intensity
+    }
+
+    fn push(&mut self, video: &[u8], frames: usize) {
+        self.history.push_front(video.to_vec());
+        self.history.truncate(frames.max(1));
+    }
+
+    fn lit(&mut self, video: &[u8], frames: usize) {
+        self.push(video, frames);
+        for (px, value) in self.intensity.iter_mut().enumerate() {
+            let on = self.history.iter().any(|frame| bit(frame, px));
+            *value = if on { 1.0 } else { 0.0 };
+        }
+    }
+
+    fn blend(&mut self, video: &[u8], frames: usize) {
+        self.push(video, frames);
+        let n = self.history.len() as f32;
+        for (px, value) in self.intensity.iter_mut().enumerate() {
+            let on = self.history.iter().filter(|frame| bit(frame, px)).count();
+            *value = on as f32 / n;
+        }
+    }
+
+    fn decay(&mut self, video: &[u8], half_life: f32) {
+        let factor = 0.5f32.powf(FRAME_MS / half_life);
+        for (px, value) in self.intensity.iter_mut().enumerate() {
+            *value = if bit(video, px) { 1.0 } else { *value * factor };
+        }
+    }
+}
+
+#[inline(always)]
+fn bit(video: &[u8], px: usize) -> bool {
+    video[px / 8] & (0x80 >> (px % 8)) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_display() {
+        for filter in Filter::presets() {
+            assert_eq!(filter.to_string().parse::<Filter>(), Ok(filter));
+        }
+        assert_eq!("blend:5".parse(), Ok(Filter::Blend { frames: 5 }));
+        assert!("blend:0".parse::<Filter>().is_err());
+        assert!("decay:-1".parse::<Filter>().is_err());
+        assert!("bogus".parse::<Filter>().is_err());
+    }
+
+    #[test]
+    fn or_last_two() {
+        let mut filter = FrameFilter::new(Filter::Or);
+        assert_eq!(filter.apply(&[0x80])[0], 1.0);
+        assert_eq!(filter.apply(&[0x00])[0], 1.0);
+        assert_eq!(filter.apply(&[0x00])[0], 0.0);
+    }
+
+    #[test]
+    fn blend_frames() {
+        let mut filter = FrameFilter::new(Filter::Blend { frames: 4 });
+        filter.apply(&[0x80]);
+        filter.apply(&[0x80]);
+        filter.apply(&[0x00]);
+        let intensity = filter.apply(&[0x00]);
+        assert_eq!(intensity[0], 0.5);
+        assert_eq!(intensity[1], 0.0);
+    }
+
+    #[test]
+    fn decay_half_life() {
+        let mut filter = FrameFilter::new(Filter::Decay {
+            half_life: FRAME_MS,
+        });
+        assert_eq!(filter.apply(&[0x80])[0], 1.0);
+        assert!((filter.apply(&[0x00])[0] - 0.5).abs() < 1e-6);
+        assert!((filter.apply(&[0x00])[0] - 0.25).abs() < 1e-6);
+        assert_eq!(filter.apply(&[0x80])[0], 1.0);
+    }
+
+    #[test]
+    fn cycle_presets() {
+        let mut filter = Filter::None;
+        for _ in 0..Filter::presets().len() {
+            filter = filter.cycle();
+        }
+        assert_eq!(filter, Filter::None);
+    }
+}
@@ -0,0 +1,206 @@
+use audio::{self, Audio};
+use config::{Config, RomStore};
+use console::{self, Command, Host};
+use control::{Event, Server};
+use cpu::{Chip8, Chip8Error, Chip8State, Fault, Quirks};
+use logger::Logger;
+use rom;
+use romdb::RomDb;
+use script::Script;
+use speed::{Speed, Timing, FRAME_HZ};
+use std::collections::HashMap;
+use std::fs;
+use std::io::BufRead;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+// frames `continue` runs before giving up, ten minutes of emulated time
+const MAX_FRAMES: u32 = FRAME_HZ * 60 * 10;
+// how often control requests are checked for
+const POLL_MS: u64 = 5;
+
+// runs console commands read line by line without a window; audio only
+// goes anywhere when the config records it to a file
+pub struct Headless {
+    cpu: Chip8,
+    speed: Speed,
+    audio: Box<dyn Audio>,
+    config: Config,
+    roms: RomStore,
+    romdb: RomDb,
+    states: HashMap<u8, Chip8State>,
+    log: &'static Logger,
+    control: Option<Server>,
+    quit: bool,
+}
+
+impl Headless {
+    pub fn new(log: &'static Logger) -> Headless {
+        Headless {
+            roms: RomStore::load(),
+            romdb: RomDb::load(),
+            ..Headless::with_config(log, Config::load())
+        }
+    }
+
+    // leaves the user's files alone, for tests
+    pub fn with_config(log: &'static Logger, config: Config) -> Headless {
+        log.configure(&config.log);
+        let mut cpu = Chip8::new();
+        cpu.set_quirks(config.default_quirks());
+
+        Headless {
+            cpu,
+            speed: Speed::new(config.ipf, config.timing),
+            audio: audio::open(None, &config.audio),
+            roms: RomStore::default(),
+            romdb: RomDb::builtin(),
+            config,
+            states: HashMap::new(),
+            log,
+            control: None,
+            quit: false,
+        }
+    }
+
+    pub fn run<R: BufRead>(&mut self, input: R) {
+        for line in input.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    error!("Error reading commands: {}", err);
+                    break;
+                }
+            };
+
+            // blank lines and comments let command files be annotated
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match line.parse::<Command>() {
+                Ok(command) => console::execute(self, command),
+                Err(err) => warn!("{}", err),
+            }
+            if self.quit {
+                break;
+            }
+        }
+    }
+
+    // loads a rom the way the VM does, with its cheats, speed and quirks
+    fn load_rom(&mut self, bytes: &[u8]) -> Result<usize, Chip8Error> {
+        Chip8::check_rom(bytes)?;
+        self.cpu.hard_reset();
+        let n = self.cpu.load_rom(bytes)?;
+
+        let hash = rom::hash(bytes);
+        self.cpu.cheats_mut().load(&hash);
+        let profile = self.romdb.profile(&hash, &self.roms, &self.config);
+        self.speed.set_ipf(profile.ipf);
+        self.cpu.set_quirks(profile.quirks);
+        Ok(n)
+    }
+
+    // answers control requests until told to quit
+    pub fn serve(&mut self, server: Server) {
+        self.control = Some(server);
+        while !self.quit {
+            let calls = match self.control {
+                Some(ref server) => server.calls(),
+                None => return,
+            };
+            for call in calls {
+                call.answer(self);
+            }
+            thread::sleep(Duration::from_millis(POLL_MS));
+        }
+    }
+
+    fn publish(&self, event: Event) {
+        if let Some(ref server) = self.control {
+            server.publish(&event);
+        }
+    }
+
+    // runs a script against the loaded rom, returning false if it failed
+    pub fn run_script(&mut self, script: &mut Script) -> bool {
+        script.run(&mut self.cpu, &mut self.speed)
+    }
+}
+
+impl Host for Headless {
+    fn cpu(&mut self) -> &mut Chip8 {
+        &mut self.cpu
+    }
+
+    fn speed(&mut self) -> &mut Speed {
+        &mut self.speed
+    }
+
+    fn states(&mut self) -> &mut HashMap<u8, Chip8State> {
+        &mut self.states
+    }
+
+    fn logger(&self) -> &Logger {
+        self.log
+    }
+
+    fn load(&mut self, path: &Path) {
+        let result = fs::read(path)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| self.load_rom(&bytes).map_err(|err| err.to_string()));
+        match result {
+            Ok(n) => info!("Loaded {} ({} bytes)", path.display(), n),
+            Err(err) => error!("Error loading {}: {}", path.display(), err),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.cpu.soft_reset();
+        info!("Reloaded");
+    }
+
+    // frames only run on request, so resuming runs until something stops them
+    fn pause(&mut self, paused: bool) {
+        if !paused {
+            self.run_frames(MAX_FRAMES);
+        }
+    }
+
+    fn run_frames(&mut self, n: u32) {
+        for frame in 0..n {
+            let result = match self.speed.timing {
+                Timing::Ipf => self.cpu.run_frame(self.speed.ipf),
+                Timing::Vip => self.cpu.run_vip_frame(),
+            };
+            if let Err(fault) = result {
+                self.fault(fault);
+                return;
+            }
+            self.audio.frame(self.cpu.sound());
+            if let Some(stop) = self.cpu.take_stop() {
+                info!("{} after {} frames", stop, frame);
+                self.publish(Event::Stop(stop));
+                return;
+            }
+        }
+        info!("Ran {} frames, PC {:04X}", n, self.cpu.state().pc());
+    }
+
+    fn set_quirks(&mut self, quirks: Quirks) {
+        self.cpu.set_quirks(quirks);
+    }
+
+    fn fault(&mut self, fault: Fault) {
+        error!("CPU Error: {}", fault);
+        self.publish(Event::Fault(fault.to_string()));
+        console::registers(self.cpu.state());
+    }
+
+    fn quit(&mut self) {
+        self.quit = true;
+    }
+}
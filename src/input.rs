@@ -0,0 +1,543 @@
+use config;
+use sdl2::controller::Button;
+use sdl2::keyboard::Keycode;
+use std::collections::HashMap;
+
+const KEYS_FILE: &str = "keys.toml";
+const N_KEYS: usize = 16;
+const PAD_PREFIX: &str = "pad:";
+
+const BUTTONS: [(Button, &str); 15] = [
+    (Button::A, "a"),
+    (Button::B, "b"),
+    (Button::X, "x"),
+    (Button::Y, "y"),
+    (Button::Back, "back"),
+    (Button::Guide, "guide"),
+    (Button::Start, "start"),
+    (Button::LeftStick, "leftstick"),
+    (Button::RightStick, "rightstick"),
+    (Button::LeftShoulder, "leftshoulder"),
+    (Button::RightShoulder, "rightshoulder"),
+    (Button::DPadUp, "dpup"),
+    (Button::DPadDown, "dpdown"),
+    (Button::DPadLeft, "dpleft"),
+    (Button::DPadRight, "dpright"),
+];
+
+// keypad layout as printed on the COSMAC VIP, used for the overlay
+pub const KEYPAD: [[usize; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Hotkey {
+    Load,
+    Reload,
+    Restart,
+    Pause,
+    Step,
+    Slower,
+    Faster,
+    Theme,
+    Filter,
+    Scaling,
+    Layout,
+    Fullscreen,
+    Keymap,
+    Watch,
+    FastForward,
+    SlowMotion,
+    Timing,
+    Mute,
+    Waveform,
+    VolumeDown,
+    VolumeUp,
+    PitchDown,
+    PitchUp,
+    ScrollUp,
+    ScrollDown,
+    Search,
+    SaveLog,
+    Console,
+    Cheats,
+}
+
+impl Hotkey {
+    const ALL: [Hotkey; 29] = [
+        Hotkey::Load,
+        Hotkey::Reload,
+        Hotkey::Restart,
+        Hotkey::Pause,
+        Hotkey::Step,
+        Hotkey::Slower,
+        Hotkey::Faster,
+        Hotkey::Theme,
+        Hotkey::Filter,
+        Hotkey::Scaling,
+        Hotkey::Layout,
+        Hotkey::Fullscreen,
+        Hotkey::Keymap,
+        Hotkey::Watch,
+        Hotkey::FastForward,
+        Hotkey::SlowMotion,
+        Hotkey::Timing,
+        Hotkey::Mute,
+        Hotkey::Waveform,
+        Hotkey::VolumeDown,
+        Hotkey::VolumeUp,
+        Hotkey::PitchDown,
+        Hotkey::PitchUp,
+        Hotkey::ScrollUp,
+        Hotkey::ScrollDown,
+        Hotkey::Search,
+        Hotkey::SaveLog,
+        Hotkey::Console,
+        Hotkey::Cheats,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Hotkey::Load => "load",
+            Hotkey::Reload => "reload",
+            Hotkey::Restart => "restart",
+            Hotkey::Pause => "pause",
+            Hotkey::Step => "step",
+            Hotkey::Slower => "slower",
+            Hotkey::Faster => "faster",
+            Hotkey::Theme => "theme",
+            Hotkey::Filter => "filter",
+            Hotkey::Scaling => "scaling",
+            Hotkey::Layout => "layout",
+            Hotkey::Fullscreen => "fullscreen",
+            Hotkey::Keymap => "keymap",
+            Hotkey::Watch => "watch",
+            Hotkey::FastForward => "fastforward",
+            Hotkey::SlowMotion => "slowmotion",
+            Hotkey::Timing => "timing",
+            Hotkey::Mute => "mute",
+            Hotkey::Waveform => "waveform",
+            Hotkey::VolumeDown => "volumedown",
+            Hotkey::VolumeUp => "volumeup",
+            Hotkey::PitchDown => "pitchdown",
+            Hotkey::PitchUp => "pitchup",
+            Hotkey::ScrollUp => "scrollup",
+            Hotkey::ScrollDown => "scrolldown",
+            Hotkey::Search => "search",
+            Hotkey::SaveLog => "savelog",
+            Hotkey::Console => "console",
+            Hotkey::Cheats => "cheats",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Hotkey> {
+        Self::ALL.iter().cloned().find(|h| h.name() == name)
+    }
+
+    fn defaults() -> Vec<(Hotkey, Vec<Input>)> {
+        use self::Input::*;
+
+        vec![
+            (Hotkey::Load, vec![Key(Keycode::F1)]),
+            (Hotkey::Reload, vec![Key(Keycode::F2), Pad(Button::Back)]),
+            (Hotkey::Restart, vec![Key(Keycode::F3)]),
+            (Hotkey::Keymap, vec![Key(Keycode::F4)]),
+            (Hotkey::Pause, vec![Key(Keycode::F5), Pad(Button::Start)]),
+            (Hotkey::Step, vec![Key(Keycode::F6)]),
+            (Hotkey::Theme, vec![Key(Keycode::F7)]),
+            (Hotkey::Filter, vec![Key(Keycode::F8)]),
+            (Hotkey::Scaling, vec![Key(Keycode::F9)]),
+            (Hotkey::Layout, vec![Key(Keycode::F10)]),
+            (Hotkey::Fullscreen, vec![Key(Keycode::F11)]),
+            (Hotkey::Watch, vec![Key(Keycode::F12)]),
+            (Hotkey::Slower, vec![Key(Keycode::LeftBracket)]),
+            (Hotkey::Faster, vec![Key(Keycode::RightBracket)]),
+            (Hotkey::FastForward, vec![Key(Keycode::Tab), Pad(Button::RightStick)]),
+            (Hotkey::SlowMotion, vec![Key(Keycode::Backslash)]),
+            (Hotkey::Timing, vec![Key(Keycode::Semicolon)]),
+            (Hotkey::Mute, vec![Key(Keycode::M)]),
+            (Hotkey::Waveform, vec![Key(Keycode::N)]),
+            (Hotkey::VolumeDown, vec![Key(Keycode::Minus)]),
+            (Hotkey::VolumeUp, vec![Key(Keycode::Equals)]),
+            (Hotkey::PitchDown, vec![Key(Keycode::Comma)]),
+            (Hotkey::PitchUp, vec![Key(Keycode::Period)]),
+            (Hotkey::ScrollUp, vec![Key(Keycode::PageUp)]),
+            (Hotkey::ScrollDown, vec![Key(Keycode::PageDown)]),
+            (Hotkey::Search, vec![Key(Keycode::Slash)]),
+            (Hotkey::SaveLog, vec![Key(Keycode::Quote)]),
+            (Hotkey::Console, vec![Key(Keycode::Backquote)]),
+            (Hotkey::Cheats, vec![Key(Keycode::Insert)]),
+        ]
+    }
+}
+
+// a host input, either a keyboard key or a game controller button
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Input {
+    Key(Keycode),
+    Pad(Button),
+}
+
+impl Input {
+    pub fn name(self) -> String {
+        match self {
+            Input::Key(code) => code.name(),
+            Input::Pad(button) => format!("{}{}", PAD_PREFIX, button_name(button)),
+        }
+    }
+
+    fn parse(name: &str, parse_key: fn(&str) -> Option<Keycode>) -> Option<Input> {
+        match name.strip_prefix(PAD_PREFIX) {
+            Some(button) => parse_button(button).map(Input::Pad),
+            None => parse_key(name).map(Input::Key),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Key(usize),
+    Hotkey(Hotkey),
+}
+
+type Keypad = Vec<Vec<Input>>;
+
+#[derive(Debug, Default, Deserialize)]
+struct KeysConfig {
+    #[serde(default)]
+    keypad: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    hotkeys: HashMap<String, Vec<String>>,
+    // keyed by the ROM's SHA-1, like the other per-ROM settings
+    #[serde(default)]
+    rom: HashMap<String, RomKeysConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RomKeysConfig {
+    #[serde(default)]
+    keypad: HashMap<String, Vec<String>>,
+}
+
+pub struct Bindings {
+    keypad: Keypad,
+    hotkeys: Vec<(Hotkey, Vec<Input>)>,
+    roms: HashMap<String, Keypad>,
+    active: Keypad,
+    actions: HashMap<Input, Action>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use self::Input::{Key, Pad};
+
+        let mut keypad = vec![Vec::new(); N_KEYS];
+        let defaults = [
+            (0x1, Key(Keycode::Num1)),
+            (0x2, Key(Keycode::Num2)),
+            (0x3, Key(Keycode::Num3)),
+            (0xC, Key(Keycode::Num4)),
+            (0x4, Key(Keycode::Q)),
+            (0x5, Key(Keycode::W)),
+            (0x6, Key(Keycode::E)),
+            (0xD, Key(Keycode::R)),
+            (0x7, Key(Keycode::A)),
+            (0x8, Key(Keycode::S)),
+            (0x9, Key(Keycode::D)),
+            (0xE, Key(Keycode::F)),
+            (0xA, Key(Keycode::Z)),
+            (0x0, Key(Keycode::X)),
+            (0xB, Key(Keycode::C)),
+            (0xF, Key(Keycode::V)),
+            // fallback for the common 2/4/6/8 + 5 layout of 4-direction games
+            (0x2, Pad(Button::DPadUp)),
+            (0x8, Pad(Button::DPadDown)),
+            (0x4, Pad(Button::DPadLeft)),
+            (0x6, Pad(Button::DPadRight)),
+            (0x5, Pad(Button::A)),
+            (0x0, Pad(Button::B)),
+            (0x7, Pad(Button::X)),
+            (0x9, Pad(Button::Y)),
+            (0x1, Pad(Button::LeftShoulder)),
+            (0x3, Pad(Button::RightShoulder)),
+        ];
+        for (key, input) in defaults.iter() {
+            keypad[*key].push(*input);
+        }
+
+        let mut bindings = Bindings {
+            active: keypad.clone(),
+            keypad,
+            hotkeys: Hotkey::defaults(),
+            roms: HashMap::new(),
+            actions: HashMap::new(),
+        };
+        bindings.rebuild();
+        bindings
+    }
+}
+
+impl Bindings {
+    pub fn new() -> Bindings {
+        Self::default()
+    }
+
+    pub fn load() -> Bindings {
+        let mut bindings = Bindings::new();
+        if let Some(config) = config::load::<KeysConfig>(KEYS_FILE) {
+            bindings.configure(&config, parse_keycode);
+        }
+        bindings
+    }
+
+    fn configure(&mut self, config: &KeysConfig, parse: fn(&str) -> Option<Keycode>) {
+        apply_keypad(&mut self.keypad, &config.keypad, parse);
+
+        for (name, names) in &config.hotkeys {
+            match Hotkey::from_name(name) {
+                Some(hotkey) => {
+                    let inputs = parse_inputs(names, parse);
+                    self.hotkeys.retain(|(h, _)| *h != hotkey);
+                    self.hotkeys.push((hotkey, inputs));
+                }
+                None => warn!("Unknown hotkey {}", name),
+            }
+        }
+
+        for (rom, overrides) in &config.rom {
+            let mut keypad = self.keypad.clone();
+            apply_keypad(&mut keypad, &overrides.keypad, parse);
+            self.roms.insert(rom.to_lowercase(), keypad);
+        }
+
+        self.active = self.keypad.clone();
+        self.rebuild();
+    }
+
+    pub fn select_rom(&mut self, hash: Option<&str>) {
+        let overrides = hash.and_then(|hash| self.roms.get(&hash.to_lowercase()));
+        self.active = overrides.unwrap_or(&self.keypad).clone();
+        self.rebuild();
+    }
+
+    pub fn override_keys(&mut self, keys: &HashMap<String, Vec<String>>) {
+        if !keys.is_empty() {
+            apply_keypad(&mut self.active, keys, parse_keycode);
+            self.rebuild();
+        }
+    }
+
+    fn rebuild(&mut self) {
+        self.actions.clear();
+
+        // hotkeys take precedence if a host key is bound to both
+        for (key, inputs) in self.active.iter().enumerate() {
+            for input in inputs {
+                self.actions.insert(*input, Action::Key(key));
+            }
+        }
+
+        for (hotkey, inputs) in &self.hotkeys {
+            for input in inputs {
+                self.actions.insert(*input, Action::Hotkey(*hotkey));
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub fn action(&self, input: Input) -> Option<Action> {
+        self.actions.get(&input).cloned()
+    }
+
+    pub fn keys(&self, key: usize) -> &[Input] {
+        &self.active[key]
+    }
+
+    pub fn hotkeys(&self) -> &[(Hotkey, Vec<Input>)] {
+        &self.hotkeys
+    }
+}
+
+fn apply_keypad(
+    keypad: &mut Keypad,
+    config: &HashMap<String, Vec<String>>,
+    parse: fn(&str) -> Option<Keycode>,
+) {
+    for (name, names) in config {
+        match parse_key(name) {
+            Some(key) => {
+                let inputs = parse_inputs(names, parse);
+                for other in keypad.iter_mut() {
+                    other.retain(|input| !inputs.contains(input));
+                }
+                keypad[key] = inputs;
+            }
+            None => warn!("Unknown CHIP-8 key {}", name),
+        }
+    }
+}
+
+fn parse_key(name: &str) -> Option<usize> {
+    match usize::from_str_radix(name.trim(), 16) {
+        Ok(key) if key < N_KEYS => Some(key),
+        _ => None,
+    }
+}
+
+fn parse_inputs(names: &[String], parse: fn(&str) -> Option<Keycode>) -> Vec<Input> {
+    names
+        .iter()
+        .filter_map(|name| {
+            let input = Input::parse(name, parse);
+            if input.is_none() {
+                warn!("Unknown key name {}", name);
+            }
+            input
+        })
+        .collect()
+}
+
+fn parse_keycode(name: &str) -> Option<Keycode> {
+    Keycode::from_name(name)
+}
+
+fn parse_button(name: &str) -> Option<Button> {
+    BUTTONS
+        .iter()
+        .find(|(_, n)| n.eq_ignore_ascii_case(name))
+        .map(|(button, _)| *button)
+}
+
+fn button_name(button: Button) -> &'static str {
+    BUTTONS
+        .iter()
+        .find(|(b, _)| *b == button)
+        .map_or("?", |(_, name)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use browser::Nav;
+
+    fn parse_test(name: &str) -> Option<Keycode> {
+        match name {
+            "Up" => Some(Keycode::Up),
+            "Down" => Some(Keycode::Down),
+            "W" => Some(Keycode::W),
+            "Space" => Some(Keycode::Space),
+            "Kp7" => Some(Keycode::Kp7),
+            _ => None,
+        }
+    }
+
+    fn key(code: Keycode) -> Input {
+        Input::Key(code)
+    }
+
+    #[test]
+    fn defaults() {
+        let bindings = Bindings::new();
+        assert_eq!(bindings.action(key(Keycode::Q)), Some(Action::Key(0x4)));
+        assert_eq!(bindings.action(key(Keycode::V)), Some(Action::Key(0xF)));
+        assert_eq!(
+            bindings.action(key(Keycode::F5)),
+            Some(Action::Hotkey(Hotkey::Pause))
+        );
+        assert_eq!(bindings.action(key(Keycode::Up)), None);
+        assert_eq!(
+            bindings.action(Input::Pad(Button::DPadLeft)),
+            Some(Action::Key(0x4))
+        );
+        assert_eq!(
+            bindings.action(Input::Pad(Button::Start)),
+            Some(Action::Hotkey(Hotkey::Pause))
+        );
+    }
+
+    #[test]
+    fn defaults_distinct() {
+        let bindings = Bindings::new();
+        let mut inputs: Vec<Input> = bindings.keypad.iter().flatten().cloned().collect();
+        // the browser pages with the same keys that scroll the log behind it
+        let paging = [Hotkey::ScrollUp, Hotkey::ScrollDown];
+        for (hotkey, hotkey_inputs) in bindings.hotkeys() {
+            for input in hotkey_inputs {
+                if let (Input::Key(code), false) = (input, paging.contains(hotkey)) {
+                    assert_eq!(Nav::from_key(*code), None, "{:?} is a browser key", code);
+                }
+            }
+            inputs.extend(hotkey_inputs);
+        }
+
+        let n = inputs.len();
+        inputs.sort_by_key(|input| format!("{:?}", input));
+        inputs.dedup();
+        assert_eq!(inputs.len(), n);
+    }
+
+    #[test]
+    fn button_names() {
+        for (button, _) in BUTTONS.iter() {
+            assert_eq!(parse_button(button_name(*button)), Some(*button));
+        }
+        assert_eq!(
+            Input::parse("pad:DPUp", parse_test),
+            Some(Input::Pad(Button::DPadUp))
+        );
+        assert_eq!(Input::parse("pad:bogus", parse_test), None);
+    }
+
+    #[test]
+    fn configured_keys() {
+        let config: KeysConfig = toml::from_str(
+            r#"
+            [keypad]
+            1 = ["Kp7", "W"]
+            6 = ["pad:a"]
+
+            [hotkeys]
+            pause = ["Space"]
+
+            [rom.7EC9F7A8BB1DC3D5F5C66EB0E5C7A4F3A5C1B7D2]
+            keypad = { 1 = ["Up", "pad:dpup"], 4 = ["Down", "pad:dpdown"] }
+            "#,
+        )
+        .unwrap();
+
+        let mut bindings = Bindings::new();
+        bindings.configure(&config, parse_test);
+
+        assert_eq!(bindings.action(key(Keycode::Kp7)), Some(Action::Key(0x1)));
+        assert_eq!(bindings.action(key(Keycode::W)), Some(Action::Key(0x1)));
+        assert_eq!(bindings.keys(0x5), &[] as &[Input]);
+        assert_eq!(
+            bindings.action(Input::Pad(Button::A)),
+            Some(Action::Key(0x6))
+        );
+        assert_eq!(bindings.action(key(Keycode::F5)), None);
+        assert_eq!(
+            bindings.action(key(Keycode::Space)),
+            Some(Action::Hotkey(Hotkey::Pause))
+        );
+
+        bindings.select_rom(Some("7ec9f7a8bb1dc3d5f5c66eb0e5c7a4f3a5c1b7d2"));
+        assert_eq!(bindings.action(key(Keycode::Up)), Some(Action::Key(0x1)));
+        assert_eq!(bindings.action(key(Keycode::Down)), Some(Action::Key(0x4)));
+        assert_eq!(bindings.action(key(Keycode::Kp7)), None);
+        assert_eq!(bindings.action(key(Keycode::Q)), None);
+        assert_eq!(bindings.action(key(Keycode::S)), Some(Action::Key(0x8)));
+        assert_eq!(
+            bindings.action(Input::Pad(Button::DPadUp)),
+            Some(Action::Key(0x1))
+        );
+        assert_eq!(bindings.action(Input::Pad(Button::DPadLeft)), None);
+
+        bindings.select_rom(Some("0123456789abcdef0123456789abcdef01234567"));
+        assert_eq!(bindings.action(key(Keycode::Up)), None);
+        assert_eq!(bindings.action(key(Keycode::Kp7)), Some(Action::Key(0x1)));
+    }
+}
@@ -0,0 +1,155 @@
+use sdl2::rect::Rect;
+
+// the debugger layout is designed at this size and scaled to fit the drawable
+const BASE_WIDTH: u32 = 2048;
+const BASE_HEIGHT: u32 = 1152;
+const SCREEN_WIDTH: u32 = 64;
+const SCREEN_HEIGHT: u32 = 32;
+const MIN_SCALE: f32 = 0.25;
+
+lazy_static! {
+    static ref BASE_SCREEN_TARGET: Rect = Rect::new(40, 40, 1470, 735);
+    static ref BASE_SCREEN: Rect = Rect::new(20, 20, 1510, 775);
+    static ref BASE_LOG: Rect = Rect::new(20, 815, 700, 317);
+    static ref BASE_INSTRUCTIONS: Rect = Rect::new(1550, 20, 478, 1112);
+    static ref BASE_REGISTERS: Rect = Rect::new(740, 815, 790, 317);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Debugger,
+    Game,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scaling {
+    Integer,
+    Aspect,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    pub width: u32,
+    pub height: u32,
+    pub mode: Mode,
+    pub scaling: Scaling,
+    pub scale: f32,
+    pub screen: Rect,
+    pub screen_target: Rect,
+    pub log: Rect,
+    pub instructions: Rect,
+    pub registers: Rect,
+}
+
+impl Layout {
+    pub fn new(width: u32, height: u32, mode: Mode, scaling: Scaling) -> Layout {
+        let (width, height) = (width.max(1), height.max(1));
+        let scale = (width as f32 / BASE_WIDTH as f32)
+            .min(height as f32 / BASE_HEIGHT as f32)
+            .max(MIN_SCALE);
+        let x = (width as i32 - (BASE_WIDTH as f32 * scale) as i32).max(0) / 2;
+        let y = (height as i32 - (BASE_HEIGHT as f32 * scale) as i32).max(0) / 2;
+        let place = |rect: Rect| {
+            Rect::new(
+                x + (rect.x() as f32 * scale) as i32,
+                y + (rect.y() as f32 * scale) as i32,
+                (rect.width() as f32 * scale) as u32,
+                (rect.height() as f32 * scale) as u32,
+            )
+        };
+
+        let mut layout = Layout {
+            width,
+            height,
+            mode,
+            scaling,
+            scale,
+            screen: place(*BASE_SCREEN),
+            screen_target: place(*BASE_SCREEN_TARGET),
+            log: place(*BASE_LOG),
+            instructions: place(*BASE_INSTRUCTIONS),
+            registers: place(*BASE_REGISTERS),
+        };
+
+        if mode == Mode::Game {
+            layout.screen = Rect::new(0, 0, width, height);
+            layout.screen_target = fit(layout.screen, scaling);
+        }
+
+        layout
+    }
+
+    #[inline(always)]
+    pub fn px(&self, n: i32) -> i32 {
+        (n as f32 * self.scale).round() as i32
+    }
+
+    pub fn font_size(&self, base: u16) -> u16 {
+        (f32::from(base) * self.scale).round().max(6.0) as u16
+    }
+}
+
+fn fit(area: Rect, scaling: Scaling) -> Rect {
+    let (width, height) = match scaling {
+        Scaling::Integer => {
+            let factor = (area.width() / SCREEN_WIDTH)
+                .min(area.height() / SCREEN_HEIGHT)
+                .max(1);
+            (SCREEN_WIDTH * factor, SCREEN_HEIGHT * factor)
+        }
+        Scaling::Aspect => {
+            let width = area.width().min(area.height() * SCREEN_WIDTH / SCREEN_HEIGHT);
+            (width, width * SCREEN_HEIGHT / SCREEN_WIDTH)
+        }
+    };
+
+    Rect::new(
+        area.x() + (area.width() as i32 - width as i32) / 2,
+        area.y() + (area.height() as i32 - height as i32) / 2,
+        width,
+        height,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debugger_scales_to_drawable() {
+        let layout = Layout::new(2048, 1152, Mode::Debugger, Scaling::Aspect);
+        assert_eq!(layout.scale, 1.0);
+        assert_eq!(layout.instructions, *BASE_INSTRUCTIONS);
+
+        let layout = Layout::new(1024, 576, Mode::Debugger, Scaling::Aspect);
+        assert_eq!(layout.scale, 0.5);
+        assert_eq!(layout.instructions, Rect::new(775, 10, 239, 556));
+        assert!(layout.instructions.right() <= 1024);
+        assert!(layout.log.bottom() <= 576);
+        assert_eq!(layout.px(43), 22);
+        assert_eq!(layout.font_size(28), 14);
+    }
+
+    #[test]
+    fn debugger_centers_wide_drawable() {
+        let layout = Layout::new(3000, 1152, Mode::Debugger, Scaling::Aspect);
+        assert_eq!(layout.scale, 1.0);
+        assert_eq!(layout.screen.x(), 476 + 20);
+    }
+
+    #[test]
+    fn game_integer_scaling() {
+        let layout = Layout::new(1000, 600, Mode::Game, Scaling::Integer);
+        assert_eq!(layout.screen, Rect::new(0, 0, 1000, 600));
+        assert_eq!(layout.screen_target, Rect::new(20, 60, 960, 480));
+    }
+
+    #[test]
+    fn game_aspect_scaling() {
+        let layout = Layout::new(1000, 600, Mode::Game, Scaling::Aspect);
+        assert_eq!(layout.screen_target, Rect::new(0, 50, 1000, 500));
+
+        let layout = Layout::new(1600, 400, Mode::Game, Scaling::Aspect);
+        assert_eq!(layout.screen_target, Rect::new(400, 0, 800, 400));
+    }
+}
@@ -1,17 +1,49 @@
 // #![warn(clippy)]
 pub mod audio;
+pub mod browser;
+pub mod cheat;
+pub mod config;
+pub mod console;
+pub mod control;
+pub mod debugger;
 pub mod display;
+pub mod filter;
+pub mod headless;
+pub mod input;
+pub mod layout;
 pub mod logger;
+pub mod logview;
+pub mod netplay;
+pub mod opcode;
 pub mod rom;
+pub mod romdb;
+pub mod scheduler;
+pub mod screenshot;
+pub mod script;
+pub mod speed;
+pub mod theme;
 pub mod util;
 pub mod vm;
+pub mod watch;
 pub mod cpu;
 
 
+extern crate rand;
 extern crate sdl2;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
 extern crate log;
-extern crate nfd;
 extern crate sdl2_sys;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate toml;
+extern crate dirs;
+extern crate sha1;
+extern crate rhai;
+extern crate png;
+#[cfg(test)]
+extern crate proptest;
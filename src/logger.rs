@@ -1,55 +1,461 @@
-use log::{Level, Metadata, Record};
+use log::{Level, LevelFilter, Metadata, Record};
 use std::collections::VecDeque;
-use std::iter::FromIterator;
+use std::env;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// overrides the configured filter, e.g. CHIP8_LOG="info,cpu=debug"
+const LOG_ENV: &str = "CHIP8_LOG";
+pub const N_HISTORY: usize = 1000;
+const N_BACKUPS: usize = 3;
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
 
 lazy_static! {
-    static ref LOGGER: Logger = Logger::new();
-    static ref BUFFER: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+    static ref LOGGER: Logger = Logger::new(N_HISTORY);
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    // a default level followed by per-subsystem levels, e.g. "info,cpu=debug"
+    pub filter: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    // size in bytes at which the log file is rotated
+    pub max_size: u64,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            filter: "info".to_owned(),
+            file: None,
+            max_size: MAX_FILE_SIZE,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter {
+            default: LevelFilter::Info,
+            targets: Vec::new(),
+        }
+    }
+}
+
+impl Filter {
+    pub fn parse(spec: &str) -> Result<Filter, String> {
+        let mut filter = Filter::default();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let mut split = part.splitn(2, '=');
+            let (target, level) = match (split.next(), split.next()) {
+                (Some(target), Some(level)) => (Some(target.trim()), level.trim()),
+                (Some(level), None) => (None, level),
+                _ => unreachable!(),
+            };
+
+            let level = level
+                .parse::<LevelFilter>()
+                .map_err(|_| format!("unknown log level {}", level))?;
+            match target {
+                Some(target) => filter.targets.push((target.to_lowercase(), level)),
+                None => filter.default = level,
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn level(&self, target: &str) -> LevelFilter {
+        self.get(target).unwrap_or(self.default)
+    }
+
+    pub fn set(&mut self, target: &str, level: LevelFilter) {
+        self.targets.retain(|(t, _)| t != target);
+        self.targets.push((target.to_owned(), level));
+    }
+
+    // drops a target's own level so it falls back to the default
+    pub fn unset(&mut self, target: &str) {
+        self.targets.retain(|(t, _)| t != target);
+    }
+
+    fn get(&self, target: &str) -> Option<LevelFilter> {
+        self.targets
+            .iter()
+            .find(|(t, _)| t == target)
+            .map(|(_, level)| *level)
+    }
+
+    fn max(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    // increases by one per message, so readers can tell what they've seen
+    pub seq: u64,
+    // time since the logger started
+    pub time: Duration,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>9.3} {:<5} {}: {}",
+            self.time.as_secs_f64(),
+            self.level,
+            self.target,
+            self.message
+        )
+    }
+}
+
+// appends to a file, moving it aside to .1, .2, ... once it grows too large
+struct FileSink {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+}
+
+impl FileSink {
+    fn open(path: &Path, max_size: u64) -> io::Result<FileSink> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileSink {
+            path: path.to_path_buf(),
+            size: file.metadata()?.len(),
+            file,
+            max_size,
+        })
+    }
+
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 + 1 > self.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for i in (1..N_BACKUPS).rev() {
+            let from = backup(&self.path, i);
+            if from.exists() {
+                fs::rename(from, backup(&self.path, i + 1))?;
+            }
+        }
+        fs::rename(&self.path, backup(&self.path, 1))?;
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn backup(path: &Path, i: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", i));
+    PathBuf::from(name)
+}
+
+struct State {
+    filter: Filter,
+    // levels replaced by override_level, to put back on restore_level
+    saved: Vec<(String, Option<LevelFilter>)>,
+    history: VecDeque<Entry>,
+    capacity: usize,
+    next: u64,
+    dropped: u64,
+    file: Option<FileSink>,
 }
 
 pub struct Logger {
-    buffer: &'static Mutex<VecDeque<String>>,
+    start: Instant,
+    state: Mutex<State>,
 }
 
 impl Logger {
-    pub fn new() -> Logger {
-        Logger { buffer: &*BUFFER }
+    pub fn new(capacity: usize) -> Logger {
+        Logger {
+            start: Instant::now(),
+            state: Mutex::new(State {
+                filter: Filter::default(),
+                saved: Vec::new(),
+                history: VecDeque::with_capacity(capacity),
+                capacity,
+                next: 0,
+                dropped: 0,
+                file: None,
+            }),
+        }
     }
 
-    pub fn unread(&self) -> usize {
-        self.buffer.lock().unwrap().len()
+    pub fn configure(&self, config: &LogConfig) {
+        let spec = env::var(LOG_ENV).unwrap_or_else(|_| config.filter.clone());
+        let filter = Filter::parse(&spec).unwrap_or_else(|err| {
+            eprintln!("Error parsing log filter {}: {}", spec, err);
+            Filter::default()
+        });
+        let file = config.file.as_ref().and_then(|path| {
+            FileSink::open(path, config.max_size)
+                .map_err(|err| eprintln!("Error opening {}: {}", path.display(), err))
+                .ok()
+        });
+
+        log::set_max_level(filter.max());
+        let mut state = self.state.lock().unwrap();
+        state.filter = filter;
+        state.saved.clear();
+        state.file = file;
     }
 
-    pub fn read(&self) -> Vec<String> {
-        let mut buffer = self.buffer.lock().unwrap();
-        let output = buffer.split_off(0);
-        Vec::from_iter(output.into_iter())
+    // overrides the level for one subsystem until restored or reconfigured
+    pub fn override_level(&self, target: &str, level: LevelFilter) {
+        let mut state = self.state.lock().unwrap();
+        if !state.saved.iter().any(|(t, _)| t == target) {
+            let previous = state.filter.get(target);
+            state.saved.push((target.to_owned(), previous));
+        }
+        state.filter.set(target, level);
+        log::set_max_level(state.filter.max());
+    }
+
+    pub fn restore_level(&self, target: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(i) = state.saved.iter().position(|(t, _)| t == target) {
+            match state.saved.remove(i).1 {
+                Some(level) => state.filter.set(target, level),
+                None => state.filter.unset(target),
+            }
+            log::set_max_level(state.filter.max());
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn level(&self, target: &str) -> LevelFilter {
+        self.state.lock().unwrap().filter.level(target)
+    }
+
+    // entries from `seq` onwards that are still retained
+    pub fn since(&self, seq: u64) -> Vec<Entry> {
+        let state = self.state.lock().unwrap();
+        let skip = seq.saturating_sub(state.next - state.history.len() as u64);
+        state.history.iter().skip(skip as usize).cloned().collect()
+    }
+
+    pub fn history(&self) -> Vec<Entry> {
+        self.since(0)
+    }
+
+    // entries evicted from the history to make room for newer ones
+    pub fn dropped(&self) -> u64 {
+        self.state.lock().unwrap().dropped
+    }
+
+    // writes the retained history to a file, returning the number of entries
+    pub fn export(&self, path: &Path) -> io::Result<usize> {
+        let (history, dropped) = (self.history(), self.dropped());
+        let mut file = File::create(path)?;
+        if dropped > 0 {
+            writeln!(file, "({} earlier messages dropped)", dropped)?;
+        }
+        for entry in &history {
+            writeln!(file, "{}", entry)?;
+        }
+        Ok(history.len())
     }
 }
 
+// the subsystem a record came from, i.e. its module without the crate prefix
+fn subsystem(target: &str) -> &str {
+    target.rsplit("::").next().unwrap_or(target)
+}
+
 impl log::Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= Level::Debug
+        let state = self.state.lock().unwrap();
+        metadata.level() <= state.filter.level(subsystem(metadata.target()))
     }
 
     fn log(&self, record: &Record) {
-        if self.enabled(record.metadata()) {
-            println!("{} - {}", record.level(), record.args());
-            if record.level() >= Level::Info {
-                let mut buffer = self.buffer.lock().unwrap();
-                let message = format!("{}", record.args());
-                buffer.push_back(message);
+        let target = subsystem(record.target());
+        let mut state = self.state.lock().unwrap();
+        if record.level() > state.filter.level(target) {
+            return;
+        }
+
+        let entry = Entry {
+            seq: state.next,
+            time: self.start.elapsed(),
+            level: record.level(),
+            target: target.to_owned(),
+            message: record.args().to_string(),
+        };
+        let line = entry.to_string();
+        println!("{}", line);
+
+        let mut failed = false;
+        if let Some(ref mut file) = state.file {
+            if let Err(err) = file.write(&line) {
+                eprintln!("Error writing log file: {}", err);
+                failed = true;
             }
         }
+        if failed {
+            state.file = None;
+        }
+
+        if state.history.len() == state.capacity {
+            state.history.pop_front();
+            state.dropped += 1;
+        }
+        state.history.push_back(entry);
+        state.next += 1;
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        if let Some(ref mut file) = self.state.lock().unwrap().file {
+            let _ = file.file.flush();
+        }
+    }
 }
 
+// installs the global logger; later calls return the same logger
 pub fn init<'a>() -> &'a Logger {
     let logger = &*LOGGER;
-    log::set_logger(logger).unwrap();
-    log::set_max_level(log::LevelFilter::Debug);
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
     logger
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Log;
+
+    fn log(logger: &Logger, level: Level, target: &str, message: &str) {
+        logger.log(
+            &Record::builder()
+                .level(level)
+                .target(target)
+                .args(format_args!("{}", message))
+                .build(),
+        );
+    }
+
+    #[test]
+    fn parse_filter() {
+        let filter = Filter::parse("warn, cpu=debug,Audio=off").unwrap();
+        assert_eq!(filter.level("vm"), LevelFilter::Warn);
+        assert_eq!(filter.level("cpu"), LevelFilter::Debug);
+        assert_eq!(filter.level("audio"), LevelFilter::Off);
+        assert_eq!(filter.max(), LevelFilter::Debug);
+
+        let mut filter = filter;
+        filter.set("cpu", LevelFilter::Trace);
+        assert_eq!(filter.level("cpu"), LevelFilter::Trace);
+        assert_eq!(filter.max(), LevelFilter::Trace);
+
+        assert_eq!(Filter::parse("").unwrap(), Filter::default());
+        assert!(Filter::parse("cpu=loud").is_err());
+    }
+
+    #[test]
+    fn bounded_history() {
+        let logger = Logger::new(3);
+        {
+            let mut state = logger.state.lock().unwrap();
+            state.filter = Filter::parse("info,cpu=debug").unwrap();
+        }
+
+        log(&logger, Level::Debug, "chip8::vm", "hidden");
+        log(&logger, Level::Debug, "chip8::cpu", "shown");
+        for i in 0..3 {
+            log(&logger, Level::Warn, "chip8::display", &i.to_string());
+        }
+
+        let history = logger.history();
+        assert_eq!(logger.dropped(), 1);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].seq, 1);
+        assert_eq!(history[0].target, "display");
+        assert_eq!(history[0].level, Level::Warn);
+
+        let recent = logger.since(3);
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].message, "2");
+        assert!(logger.since(4).is_empty());
+    }
+
+    #[test]
+    fn restore_level() {
+        let logger = Logger::new(1);
+        logger.state.lock().unwrap().filter = Filter::parse("warn,vm=debug").unwrap();
+
+        logger.override_level("cpu", LevelFilter::Trace);
+        logger.override_level("cpu", LevelFilter::Trace);
+        logger.override_level("vm", LevelFilter::Trace);
+        assert_eq!(logger.level("cpu"), LevelFilter::Trace);
+
+        logger.restore_level("cpu");
+        logger.restore_level("vm");
+        assert_eq!(logger.level("cpu"), LevelFilter::Warn);
+        assert_eq!(logger.level("vm"), LevelFilter::Debug);
+        assert_eq!(logger.state.lock().unwrap().filter.get("cpu"), None);
+    }
+
+    #[test]
+    fn rotate_file() {
+        let path = env::temp_dir().join(format!("chip8-log-{}.log", std::process::id()));
+        let mut sink = FileSink::open(&path, 10).unwrap();
+        for line in &["first", "second", "third"] {
+            sink.write(line).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "third\n");
+        assert_eq!(fs::read_to_string(backup(&path, 1)).unwrap(), "second\n");
+        assert_eq!(fs::read_to_string(backup(&path, 2)).unwrap(), "first\n");
+
+        for i in 1..=2 {
+            fs::remove_file(backup(&path, i)).unwrap();
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn export_history() {
+        let logger = Logger::new(2);
+        for message in &["one", "two", "three"] {
+            log(&logger, Level::Error, "chip8::vm", message);
+        }
+
+        let path = env::temp_dir().join(format!("chip8-export-{}.log", std::process::id()));
+        assert_eq!(logger.export(&path).unwrap(), 2);
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines[0], "(1 earlier messages dropped)");
+        assert!(lines[1].ends_with("ERROR vm: two"));
+        assert!(lines[2].ends_with("ERROR vm: three"));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,282 @@
+use logger::{Entry, Logger, N_HISTORY};
+use sdl2::keyboard::Keycode;
+use std::collections::VecDeque;
+
+// lines the log panel has room for, one of which shows the search
+pub const ROWS: usize = 7;
+pub const WHEEL_LINES: i32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchKey {
+    Done,
+    Cancel,
+    Backspace,
+}
+
+impl SearchKey {
+    pub fn from_key(code: Keycode) -> Option<SearchKey> {
+        match code {
+            Keycode::Return | Keycode::KpEnter => Some(SearchKey::Done),
+            Keycode::Escape => Some(SearchKey::Cancel),
+            Keycode::Backspace => Some(SearchKey::Backspace),
+            _ => None,
+        }
+    }
+}
+
+// scrollback over the logger's history, optionally filtered by a search
+#[derive(Default)]
+pub struct LogView {
+    entries: VecDeque<Entry>,
+    next: u64,
+    // entries trimmed from the front, so positions in `matched` stay valid
+    dropped: usize,
+    // positions of the entries matching the search, oldest first
+    matched: VecDeque<usize>,
+    // matching lines scrolled back from the newest
+    scroll: usize,
+    search: String,
+    // the search lowercased once for matching
+    query: String,
+    searching: bool,
+}
+
+impl LogView {
+    pub fn new() -> LogView {
+        Self::default()
+    }
+
+    pub fn update(&mut self, logger: &Logger) {
+        for entry in logger.since(self.next) {
+            self.next = entry.seq + 1;
+            if self.matches(&entry) {
+                // keep the same lines in view while scrolled back
+                if self.scroll > 0 {
+                    self.scroll += 1;
+                }
+                self.matched.push_back(self.dropped + self.entries.len());
+            }
+            self.entries.push_back(entry);
+        }
+
+        while self.entries.len() > N_HISTORY {
+            self.entries.pop_front();
+            if self.matched.front() == Some(&self.dropped) {
+                self.matched.pop_front();
+            }
+            self.dropped += 1;
+        }
+        self.scroll = self.scroll.min(self.max_scroll());
+    }
+
+    fn matches(&self, entry: &Entry) -> bool {
+        self.query.is_empty() || entry.to_string().to_lowercase().contains(&self.query)
+    }
+
+    // rematches the history after the search changes
+    fn refilter(&mut self) {
+        self.query = self.search.to_lowercase();
+        let matched = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| self.matches(entry))
+            .map(|(i, _)| self.dropped + i)
+            .collect();
+        self.matched = matched;
+        self.scroll = 0;
+    }
+
+    // whether the bottom row is needed to show the search or scroll position
+    pub fn status(&self) -> bool {
+        self.searching || !self.search.is_empty() || self.scroll > 0
+    }
+
+    fn max_scroll(&self) -> usize {
+        self.matched.len().saturating_sub(ROWS - 1)
+    }
+
+    // the last `rows` lines in view, oldest first
+    pub fn visible(&self, rows: usize) -> Vec<&Entry> {
+        let end = self.matched.len() - self.scroll.min(self.matched.len());
+        let start = end.saturating_sub(rows);
+        (start..end)
+            .map(|i| &self.entries[self.matched[i] - self.dropped])
+            .collect()
+    }
+
+    pub fn scrolled(&self) -> usize {
+        self.scroll
+    }
+
+    // positive lines scroll back towards older messages
+    pub fn scroll(&mut self, lines: i32) {
+        let scroll = (self.scroll as i64 + i64::from(lines)).max(0) as usize;
+        self.scroll = scroll.min(self.max_scroll());
+    }
+
+    pub fn page_up(&mut self) {
+        self.scroll(ROWS as i32 - 1);
+    }
+
+    pub fn page_down(&mut self) {
+        self.scroll(1 - ROWS as i32);
+    }
+
+    pub fn search(&self) -> &str {
+        &self.search
+    }
+
+    pub fn searching(&self) -> bool {
+        self.searching
+    }
+
+    pub fn start_search(&mut self) {
+        self.searching = true;
+    }
+
+    pub fn type_text(&mut self, text: &str) {
+        self.search.push_str(text);
+        self.refilter();
+    }
+
+    pub fn search_key(&mut self, key: SearchKey) {
+        match key {
+            SearchKey::Done => {
+                self.searching = false;
+                self.scroll = 0;
+            }
+            SearchKey::Cancel => {
+                self.search.clear();
+                self.searching = false;
+                self.refilter();
+            }
+            SearchKey::Backspace => {
+                self.search.pop();
+                self.refilter();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::{Level, Log, Record};
+
+    fn logger(messages: &[(Level, &str)]) -> Logger {
+        let logger = Logger::new(N_HISTORY);
+        for (level, message) in messages {
+            logger.log(
+                &Record::builder()
+                    .level(*level)
+                    .target("chip8::vm")
+                    .args(format_args!("{}", message))
+                    .build(),
+            );
+        }
+        logger
+    }
+
+    fn messages(view: &LogView) -> Vec<String> {
+        let rows = if view.status() { ROWS - 1 } else { ROWS };
+        view.visible(rows).iter().map(|e| e.message.clone()).collect()
+    }
+
+    #[test]
+    fn scrollback() {
+        let numbers: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let logger = logger(
+            &numbers
+                .iter()
+                .map(|n| (Level::Info, n.as_str()))
+                .collect::<Vec<_>>(),
+        );
+        let mut view = LogView::new();
+        view.update(&logger);
+        assert_eq!(messages(&view), numbers[13..].to_vec());
+
+        view.scroll(2);
+        assert!(view.status());
+        assert_eq!(messages(&view), numbers[12..18].to_vec());
+
+        logger.log(
+            &Record::builder()
+                .level(Level::Info)
+                .args(format_args!("20"))
+                .build(),
+        );
+        view.update(&logger);
+        assert_eq!(view.scrolled(), 3);
+        assert_eq!(messages(&view), numbers[12..18].to_vec());
+
+        view.scroll(100);
+        assert_eq!(messages(&view), numbers[..6].to_vec());
+        view.page_down();
+        view.scroll(-100);
+        assert_eq!(view.scrolled(), 0);
+        assert_eq!(messages(&view).last().unwrap(), "20");
+    }
+
+    #[test]
+    fn search_filter() {
+        let logger = logger(&[
+            (Level::Info, "Loaded PONG"),
+            (Level::Error, "CPU Error: unknown instruction"),
+            (Level::Warn, "Unknown theme"),
+            (Level::Info, "Paused"),
+        ]);
+        let mut view = LogView::new();
+        view.update(&logger);
+
+        view.start_search();
+        view.type_text("UNKNOWN");
+        assert!(view.searching());
+        assert_eq!(
+            messages(&view),
+            vec!["CPU Error: unknown instruction", "Unknown theme"]
+        );
+
+        view.search_key(SearchKey::Done);
+        assert!(!view.searching());
+        view.type_text("x");
+        view.search_key(SearchKey::Backspace);
+        assert_eq!(view.search(), "UNKNOWN");
+
+        view.search_key(SearchKey::Cancel);
+        assert_eq!(view.search(), "");
+        assert_eq!(messages(&view).len(), 4);
+
+        // levels are searchable too
+        view.type_text("warn");
+        assert_eq!(messages(&view), vec!["Unknown theme"]);
+    }
+
+    #[test]
+    fn trimmed_history() {
+        let logger = logger(&[(Level::Warn, "first"), (Level::Info, "second")]);
+        let mut view = LogView::new();
+        view.type_text("first");
+        view.update(&logger);
+        assert_eq!(messages(&view), vec!["first"]);
+
+        // matches leave with the history they point into
+        let info = |message: &str| {
+            logger.log(
+                &Record::builder()
+                    .level(Level::Info)
+                    .args(format_args!("{}", message))
+                    .build(),
+            )
+        };
+        for _ in 0..N_HISTORY {
+            info("filler");
+        }
+        view.update(&logger);
+        assert!(messages(&view).is_empty());
+
+        info("first again");
+        view.update(&logger);
+        assert_eq!(messages(&view), vec!["first again"]);
+    }
+}
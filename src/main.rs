@@ -1,31 +1,163 @@
 // #![warn(clippy)]
+mod browser;
+mod cheat;
+mod config;
+mod console;
+mod control;
 mod cpu;
+mod debugger;
 mod display;
+mod filter;
+mod headless;
+mod input;
+mod layout;
 mod logger;
+mod logview;
+mod netplay;
+mod opcode;
 mod rom;
+mod romdb;
+mod scheduler;
+mod screenshot;
+mod script;
+mod speed;
+mod theme;
 mod util;
 mod vm;
+mod watch;
 mod audio;
 
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
 extern crate log;
-extern crate nfd;
 extern crate rand;
 extern crate sdl2;
 extern crate sdl2_sys;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate toml;
+extern crate dirs;
+extern crate sha1;
+extern crate rhai;
+extern crate png;
+#[cfg(test)]
+extern crate proptest;
 
+use console::Host;
+use control::Server;
+use headless::Headless;
+use netplay::Role;
+use script::Script;
+use std::env;
+use std::io;
+use std::path::Path;
+use std::process;
 use vm::{VMArgs, VM};
 
+// options followed by a value
+const OPTIONS: [&str; 5] = ["--script", "--host", "--join", "--keys", "--control"];
+
+// the value following an option, e.g. --script bot.rhai
+fn option<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let i = args.iter().position(|arg| arg == name)?;
+    args.get(i + 1).map(String::as_str)
+}
+
+// e.g. chip8 PONG --host 8642 --keys 14, then chip8 --join 192.168.1.2:8642
+fn netplay_role(args: &[String]) -> Result<Option<Role>, String> {
+    if let Some(address) = option(args, "--join") {
+        let address = if address.contains(':') {
+            address.to_owned()
+        } else {
+            format!("{}:{}", address, netplay::DEFAULT_PORT)
+        };
+        return Ok(Some(Role::Join(address)));
+    }
+
+    let port = match option(args, "--host") {
+        Some(port) => port.parse().map_err(|_| format!("bad port {}", port))?,
+        None => return Ok(None),
+    };
+    let keys = match option(args, "--keys") {
+        Some(keys) => netplay::parse_keys(keys)?,
+        None => netplay::default_keys(),
+    };
+    Ok(Some(Role::Host { port, keys }))
+}
+
 fn main() {
+    let log = logger::init();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let script_path = option(&args, "--script");
+    let rom = (0..args.len())
+        .find(|&i| !args[i].starts_with("--") && (i == 0 || !OPTIONS.contains(&&*args[i - 1])))
+        .map(|i| Path::new(&args[i]));
+    let script = script_path.map(|path| {
+        Script::load(Path::new(path)).unwrap_or_else(|err| {
+            error!("Error loading {}: {}", path, err);
+            process::exit(1);
+        })
+    });
+
+    // a local json-rpc server for editors and test runners, e.g. --control 8643
+    let control = option(&args, "--control").map(|port| {
+        port.parse().unwrap_or_else(|_| {
+            error!("Bad control port {}, using {}", port, control::DEFAULT_PORT);
+            control::DEFAULT_PORT
+        })
+    });
+
+    if args.iter().any(|arg| arg == "--headless") {
+        let mut headless = Headless::new(log);
+        if let Some(rom) = rom {
+            headless.load(rom);
+        }
+        // scripts run to completion, failing on an error, e.g. chip8 --headless PONG --script test.rhai
+        if let Some(mut script) = script {
+            if !headless.run_script(&mut script) {
+                process::exit(1);
+            }
+        }
+        match control {
+            // requests are answered until one says to quit, e.g. chip8 --headless PONG --control 8643
+            Some(port) => match Server::start(port) {
+                Some(server) => headless.serve(server),
+                None => process::exit(1),
+            },
+            // console commands from stdin, e.g. chip8 --headless PONG < script.txt
+            None if script_path.is_none() => {
+                let stdin = io::stdin();
+                headless.run(stdin.lock());
+            }
+            None => (),
+        }
+        return;
+    }
+
+    let netplay = netplay_role(&args).unwrap_or_else(|err| {
+        error!("Netplay: {}", err);
+        process::exit(1);
+    });
+
     let context = &sdl2::init().unwrap();
+    let audio = context
+        .audio()
+        .map_err(|err| warn!("Audio unavailable: {}", err))
+        .ok();
     let args = VMArgs {
         sdl: context,
         ttf: &sdl2::ttf::init().unwrap(),
-        audio: &context.audio().unwrap(),
-        log: &logger::init(),
+        audio: audio.as_ref(),
+        log,
         cache: &display::TextureCache::new(),
+        rom,
+        script,
+        netplay,
+        control,
     };
 
     VM::new(args).start();
@@ -0,0 +1,644 @@
+use cpu::{Chip8, Chip8State, Quirks};
+use input::KEYPAD;
+use sha1::Sha1;
+use speed::{Speed, Timing};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_PORT: u16 = 8642;
+// frames between pressing a key and it taking effect, hiding the round trip
+const INPUT_DELAY: u32 = 2;
+// frames between comparisons of the machine state
+const HASH_INTERVAL: u32 = 60;
+const N_HASHES: usize = 8;
+const TIMEOUT_SECS: u64 = 30;
+const MAX_MESSAGE: usize = 64 * 1024;
+// bumped whenever the messages change
+const VERSION: u8 = 1;
+
+const SYNC: u8 = 1;
+const INPUT: u8 = 2;
+const HASH: u8 = 3;
+
+#[derive(Debug)]
+pub enum NetError {
+    Io(io::Error),
+    Closed,
+    Desync(u32),
+    Protocol(String),
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetError::Io(err) => write!(f, "{}", err),
+            NetError::Closed => write!(f, "peer disconnected"),
+            NetError::Desync(frame) => write!(f, "desync detected at frame {}", frame),
+            NetError::Protocol(message) => write!(f, "protocol error: {}", message),
+        }
+    }
+}
+
+impl From<io::Error> for NetError {
+    fn from(err: io::Error) -> NetError {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset => NetError::Closed,
+            _ => NetError::Io(err),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Role {
+    Host { port: u16, keys: u16 },
+    Join(String),
+}
+
+// keypad keys as a bit per key, written as hex digits, e.g. "14" for 1 and 4
+pub fn parse_keys(s: &str) -> Result<u16, String> {
+    s.chars().try_fold(0, |keys, c| match c.to_digit(16) {
+        Some(k) => Ok(keys | 1 << k),
+        None => Err(format!("bad key {}", c)),
+    })
+}
+
+pub fn describe_keys(keys: u16) -> String {
+    (0..16)
+        .filter(|k| keys & 1 << k != 0)
+        .map(|k| format!("{:X}", k))
+        .collect()
+}
+
+// the left half of the keypad, e.g. 1 and 4 for the left paddle in PONG
+pub fn default_keys() -> u16 {
+    KEYPAD
+        .iter()
+        .flat_map(|row| row[..2].iter())
+        .fold(0, |keys, k| keys | 1 << k)
+}
+
+fn state_hash(state: &Chip8State) -> u64 {
+    let digest = Sha1::from(state.to_bytes()).digest().bytes();
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+fn quirks_bits(quirks: Quirks) -> u8 {
+    [
+        quirks.shift,
+        quirks.load_store,
+        quirks.jump,
+        quirks.vf_reset,
+        quirks.display_wait,
+        quirks.wrap,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |bits, (i, on)| bits | (*on as u8) << i)
+}
+
+fn quirks_from_bits(bits: u8) -> Quirks {
+    let on = |i: u8| bits & 1 << i != 0;
+    Quirks {
+        shift: on(0),
+        load_store: on(1),
+        jump: on(2),
+        vf_reset: on(3),
+        display_wait: on(4),
+        wrap: on(5),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Message {
+    // everything the joining peer needs to start identical to the host
+    Sync {
+        keys: u16,
+        seed: u64,
+        ipf: u32,
+        timing: Timing,
+        quirks: Quirks,
+        state: Vec<u8>,
+    },
+    // a peer's keys for a frame
+    Input {
+        frame: u32,
+        keys: u16,
+    },
+    Hash {
+        frame: u32,
+        hash: u64,
+    },
+}
+
+impl Message {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![0; 5];
+        match self {
+            Message::Sync {
+                keys,
+                seed,
+                ipf,
+                timing,
+                quirks,
+                state,
+            } => {
+                bytes[0] = SYNC;
+                bytes.push(VERSION);
+                bytes.extend_from_slice(&keys.to_be_bytes());
+                bytes.extend_from_slice(&seed.to_be_bytes());
+                bytes.extend_from_slice(&ipf.to_be_bytes());
+                bytes.push(if *timing == Timing::Vip { 1 } else { 0 });
+                bytes.push(quirks_bits(*quirks));
+                bytes.extend_from_slice(state);
+            }
+            Message::Input { frame, keys } => {
+                bytes[0] = INPUT;
+                bytes.extend_from_slice(&frame.to_be_bytes());
+                bytes.extend_from_slice(&keys.to_be_bytes());
+            }
+            Message::Hash { frame, hash } => {
+                bytes[0] = HASH;
+                bytes.extend_from_slice(&frame.to_be_bytes());
+                bytes.extend_from_slice(&hash.to_be_bytes());
+            }
+        }
+        let len = (bytes.len() - 5) as u32;
+        bytes[1..5].copy_from_slice(&len.to_be_bytes());
+        bytes
+    }
+
+    fn decode(tag: u8, payload: &[u8]) -> Result<Message, NetError> {
+        let mut reader = payload;
+        let message = match tag {
+            SYNC => {
+                let version = take::<1>(&mut reader)?[0];
+                if version != VERSION {
+                    return Err(NetError::Protocol(format!("version {}", version)));
+                }
+                Message::Sync {
+                    keys: u16::from_be_bytes(take(&mut reader)?),
+                    seed: u64::from_be_bytes(take(&mut reader)?),
+                    ipf: u32::from_be_bytes(take(&mut reader)?),
+                    timing: match take::<1>(&mut reader)?[0] {
+                        0 => Timing::Ipf,
+                        _ => Timing::Vip,
+                    },
+                    quirks: quirks_from_bits(take::<1>(&mut reader)?[0]),
+                    state: reader.to_vec(),
+                }
+            }
+            INPUT => Message::Input {
+                frame: u32::from_be_bytes(take(&mut reader)?),
+                keys: u16::from_be_bytes(take(&mut reader)?),
+            },
+            HASH => Message::Hash {
+                frame: u32::from_be_bytes(take(&mut reader)?),
+                hash: u64::from_be_bytes(take(&mut reader)?),
+            },
+            _ => return Err(NetError::Protocol(format!("unknown message {}", tag))),
+        };
+        Ok(message)
+    }
+}
+
+fn take<const N: usize>(reader: &mut &[u8]) -> Result<[u8; N], NetError> {
+    let mut bytes = [0; N];
+    reader
+        .read_exact(&mut bytes)
+        .map_err(|_| NetError::Protocol("short message".to_owned()))?;
+    Ok(bytes)
+}
+
+// a session being set up on its own thread, so the window keeps running
+// while it waits for the peer
+pub struct Connecting {
+    peer: Receiver<Result<Peer, NetError>>,
+}
+
+enum Peer {
+    // a peer joined this host and is still to be sent the machine state
+    Joined(Netplay, SocketAddr),
+    // the host accepted this peer and sent its machine state
+    Hosted(Netplay, String, Message),
+}
+
+impl Connecting {
+    pub fn start(role: &Role) -> Result<Connecting, NetError> {
+        match role {
+            Role::Host { port, keys } => {
+                let listener = TcpListener::bind(("0.0.0.0", *port))?;
+                info!("Waiting for a peer on port {}", port);
+                Ok(Connecting::accept(listener, *keys))
+            }
+            Role::Join(address) => {
+                info!("Connecting to {}", address);
+                Ok(Connecting::dial(address.clone()))
+            }
+        }
+    }
+
+    // waits for a peer, which gets the keys not in `keys`
+    pub fn accept(listener: TcpListener, keys: u16) -> Connecting {
+        Connecting::spawn(move || {
+            let (stream, address) = listener.accept()?;
+            Ok(Peer::Joined(Netplay::new(stream, keys)?, address))
+        })
+    }
+
+    pub fn dial(address: String) -> Connecting {
+        Connecting::spawn(move || {
+            let mut netplay = Netplay::new(TcpStream::connect(&address)?, 0)?;
+            let sync = receive(&mut netplay.stream)?;
+            Ok(Peer::Hosted(netplay, address, sync))
+        })
+    }
+
+    fn spawn<F>(f: F) -> Connecting
+    where
+        F: FnOnce() -> Result<Peer, NetError> + Send + 'static,
+    {
+        let (sender, peer) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(f());
+        });
+        Connecting { peer }
+    }
+
+    // starts the session once the peer is there, or returns None while waiting
+    pub fn poll(&self, cpu: &mut Chip8, speed: &mut Speed) -> Option<Result<Netplay, NetError>> {
+        let peer = match self.peer.try_recv() {
+            Ok(peer) => peer,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err(NetError::Closed),
+        };
+        Some(peer.and_then(|peer| match peer {
+            Peer::Joined(netplay, address) => netplay.host(address, cpu, speed),
+            Peer::Hosted(netplay, address, sync) => netplay.join(&address, sync, cpu, speed),
+        }))
+    }
+}
+
+// a lockstep session with one peer: both run the same frames on the same
+// inputs, each supplying the keys it owns
+pub struct Netplay {
+    stream: TcpStream,
+    // messages from the peer, read on their own thread
+    messages: Option<Receiver<Result<Message, NetError>>>,
+    keys: u16,
+    // the local keys held down, including ones the peer owns
+    pressed: u16,
+    frame: u32,
+    // whether this frame's input has gone to the peer
+    sent: bool,
+    // local inputs sent but not yet applied, oldest first
+    pending: VecDeque<u16>,
+    // recent local state hashes by frame
+    hashes: VecDeque<(u32, u64)>,
+}
+
+impl Netplay {
+    fn new(stream: TcpStream, keys: u16) -> Result<Netplay, NetError> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(Duration::from_secs(TIMEOUT_SECS)))?;
+        Ok(Netplay {
+            stream,
+            messages: None,
+            keys,
+            pressed: 0,
+            frame: 0,
+            sent: false,
+            pending: (0..INPUT_DELAY).map(|_| 0).collect(),
+            hashes: VecDeque::new(),
+        })
+    }
+
+    // sends the machine state to a peer that joined
+    fn host(
+        mut self,
+        address: SocketAddr,
+        cpu: &mut Chip8,
+        speed: &Speed,
+    ) -> Result<Netplay, NetError> {
+        // reloading clears any partial frame so both sides start the same
+        let state = cpu.save_state();
+        cpu.load_state(state.clone());
+        let seed = rand::random();
+        cpu.seed_random(seed);
+
+        self.send(&Message::Sync {
+            keys: !self.keys,
+            seed,
+            ipf: speed.ipf,
+            timing: speed.timing,
+            quirks: cpu.quirks(),
+            state: state.to_bytes(),
+        })?;
+        info!(
+            "Peer connected from {}, keys {}",
+            address,
+            describe_keys(self.keys)
+        );
+        self.listen()
+    }
+
+    // takes on the machine state the host sent
+    fn join(
+        mut self,
+        address: &str,
+        sync: Message,
+        cpu: &mut Chip8,
+        speed: &mut Speed,
+    ) -> Result<Netplay, NetError> {
+        match sync {
+            Message::Sync {
+                keys,
+                seed,
+                ipf,
+                timing,
+                quirks,
+                state,
+            } => {
+                let state = Chip8State::from_bytes(&state)
+                    .ok_or_else(|| NetError::Protocol("bad save state".to_owned()))?;
+                cpu.load_state(state);
+                cpu.set_quirks(quirks);
+                cpu.seed_random(seed);
+                speed.set_ipf(ipf);
+                speed.timing = timing;
+                self.keys = keys;
+            }
+            _ => return Err(NetError::Protocol("expected a save state".to_owned())),
+        }
+        info!(
+            "Connected to {}, keys {}",
+            address,
+            describe_keys(self.keys)
+        );
+        self.listen()
+    }
+
+    // hands reading over to a thread so frames never block on the peer
+    fn listen(mut self) -> Result<Netplay, NetError> {
+        let mut reader = self.stream.try_clone()?;
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || loop {
+            let message = receive(&mut reader);
+            let failed = message.is_err();
+            if sender.send(message).is_err() || failed {
+                break;
+            }
+        });
+        self.messages = Some(messages);
+        Ok(self)
+    }
+
+    pub fn press(&mut self, key: usize) {
+        self.pressed |= 1 << key;
+    }
+
+    pub fn release(&mut self, key: usize) {
+        self.pressed &= !(1 << key);
+    }
+
+    // sends this peer's keys and sets the keypad for the next frame, returning
+    // false if the peer's keys haven't arrived yet and the frame has to wait
+    pub fn before_frame(&mut self, cpu: &mut Chip8) -> Result<bool, NetError> {
+        if !self.sent {
+            let local = self.pressed & self.keys;
+            self.send(&Message::Input {
+                frame: self.frame + INPUT_DELAY,
+                keys: local,
+            })?;
+            self.pending.push_back(local);
+            self.sent = true;
+        }
+
+        let theirs = if self.frame < INPUT_DELAY {
+            0
+        } else {
+            match self.receive_input()? {
+                Some(keys) => keys,
+                None => return Ok(false),
+            }
+        };
+        let mine = self.pending.pop_front().unwrap_or(0);
+        let keys = mine | theirs & !self.keys;
+        for k in 0..16 {
+            if keys & 1 << k != 0 {
+                cpu.press_key(k);
+            } else {
+                cpu.release_key(k);
+            }
+        }
+        self.sent = false;
+        Ok(true)
+    }
+
+    // sends a hash of the machine state every so often for the peer to check
+    pub fn after_frame(&mut self, cpu: &Chip8) -> Result<(), NetError> {
+        if self.frame.is_multiple_of(HASH_INTERVAL) {
+            let hash = state_hash(cpu.state());
+            self.hashes.push_back((self.frame, hash));
+            if self.hashes.len() > N_HASHES {
+                self.hashes.pop_front();
+            }
+            self.send(&Message::Hash {
+                frame: self.frame,
+                hash,
+            })?;
+        }
+        self.frame += 1;
+        Ok(())
+    }
+
+    fn receive_input(&mut self) -> Result<Option<u16>, NetError> {
+        let messages = match self.messages {
+            Some(ref messages) => messages,
+            None => return Err(NetError::Closed),
+        };
+        loop {
+            let message = match messages.try_recv() {
+                Ok(message) => message?,
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => return Err(NetError::Closed),
+            };
+            match message {
+                Message::Input { frame, keys } if frame == self.frame => return Ok(Some(keys)),
+                Message::Input { frame, .. } => {
+                    let message = format!("input for frame {} at frame {}", frame, self.frame);
+                    return Err(NetError::Protocol(message));
+                }
+                Message::Hash { frame, hash } => {
+                    let local = self.hashes.iter().find(|(f, _)| *f == frame);
+                    if let Some((_, local)) = local {
+                        if *local != hash {
+                            return Err(NetError::Desync(frame));
+                        }
+                    }
+                }
+                Message::Sync { .. } => {
+                    return Err(NetError::Protocol("unexpected save state".to_owned()))
+                }
+            }
+        }
+    }
+
+    fn send(&mut self, message: &Message) -> Result<(), NetError> {
+        self.stream.write_all(&message.encode())?;
+        Ok(())
+    }
+}
+
+// closing the socket also ends the reading thread
+impl Drop for Netplay {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn receive<R: Read>(reader: &mut R) -> Result<Message, NetError> {
+    let mut header = [0; 5];
+    reader.read_exact(&mut header)?;
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if len > MAX_MESSAGE {
+        return Err(NetError::Protocol(format!("message of {} bytes", len)));
+    }
+
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+    Message::decode(header[0], &payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // V0 = random, draw, then loop
+    const ROM: [u8; 6] = [0xC0, 0xFF, 0xD0, 0x15, 0x12, 0x00];
+
+    fn wait(connecting: &Connecting, cpu: &mut Chip8, speed: &mut Speed) -> Option<Netplay> {
+        connecting.poll(cpu, speed).map(Result::unwrap)
+    }
+
+    fn connect() -> ((Chip8, Netplay), (Chip8, Netplay)) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let hosting = Connecting::accept(listener, default_keys());
+        let joining = Connecting::dial(address);
+
+        let mut host_cpu = Chip8::new();
+        host_cpu.load_rom(&ROM).unwrap();
+        let mut host_speed = Speed::new(10, Timing::Ipf);
+        let mut guest_cpu = Chip8::new();
+        let mut guest_speed = Speed::new(1, Timing::Vip);
+
+        // the guest only hears from the host once the host has its peer
+        let (mut host, mut guest) = (None, None);
+        while host.is_none() || guest.is_none() {
+            if host.is_none() {
+                host = wait(&hosting, &mut host_cpu, &mut host_speed);
+            }
+            if guest.is_none() {
+                guest = wait(&joining, &mut guest_cpu, &mut guest_speed);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!((guest_speed.ipf, guest_speed.timing), (10, Timing::Ipf));
+        ((host_cpu, host.unwrap()), (guest_cpu, guest.unwrap()))
+    }
+
+    fn run(
+        mut cpu: Chip8,
+        mut netplay: Netplay,
+        frames: u32,
+        press: usize,
+    ) -> thread::JoinHandle<Result<(Chip8, Netplay), NetError>> {
+        thread::spawn(move || {
+            netplay.press(press);
+            for _ in 0..frames {
+                while !netplay.before_frame(&mut cpu)? {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                cpu.run_frame(10).unwrap();
+                netplay.after_frame(&cpu)?;
+            }
+            // the session is returned so it outlives the peer's last reads
+            Ok((cpu, netplay))
+        })
+    }
+
+    #[test]
+    fn parse_key_sets() {
+        assert_eq!(parse_keys("14"), Ok(0b10010));
+        assert_eq!(describe_keys(parse_keys("c1D").unwrap()), "1CD");
+        assert!(parse_keys("1G").is_err());
+        assert_eq!(describe_keys(default_keys()), "0124578A");
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = vec![
+            Message::Sync {
+                keys: 0xF00F,
+                seed: 42,
+                ipf: 15,
+                timing: Timing::Vip,
+                quirks: Quirks::chip8(),
+                state: vec![1, 2, 3],
+            },
+            Message::Input {
+                frame: 7,
+                keys: 0x12,
+            },
+            Message::Hash {
+                frame: 60,
+                hash: 0xDEAD_BEEF,
+            },
+        ];
+        for message in messages {
+            let bytes = message.encode();
+            assert_eq!(Message::decode(bytes[0], &bytes[5..]).unwrap(), message);
+        }
+        assert!(Message::decode(INPUT, &[0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn lockstep_frames() {
+        let ((host_cpu, host), (guest_cpu, guest)) = connect();
+        assert_eq!(guest.keys, !default_keys());
+
+        // each peer holds one key of its own and one the other owns
+        let host = run(host_cpu, host, 130, 0x1);
+        let guest = run(guest_cpu, guest, 130, 0xC);
+        let (host_cpu, _host) = host.join().unwrap().unwrap();
+        let (guest_cpu, _guest) = guest.join().unwrap().unwrap();
+
+        assert_eq!(host_cpu.state().to_bytes(), guest_cpu.state().to_bytes());
+        for cpu in &[host_cpu, guest_cpu] {
+            let keys = cpu.state().keys();
+            assert!(keys[0x1] && keys[0xC]);
+            assert_eq!(keys.iter().filter(|k| **k).count(), 2);
+        }
+    }
+
+    #[test]
+    fn detects_desync() {
+        let ((host_cpu, host), (mut guest_cpu, guest)) = connect();
+        guest_cpu.poke(0x300, &[0xFF]).unwrap();
+
+        let host = run(host_cpu, host, 10, 0x1);
+        let guest = run(guest_cpu, guest, 10, 0xC);
+        let results = [host.join().unwrap(), guest.join().unwrap()];
+        assert!(results
+            .iter()
+            .any(|result| matches!(result, Err(NetError::Desync(0)))));
+    }
+}
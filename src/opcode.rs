@@ -0,0 +1,641 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+const N_REGISTERS: usize = 16;
+const MEMORY_SIZE: usize = 4096;
+
+const MNEMONICS: [&str; 23] = [
+    "CLS", "RET", "JUMP", "CALL", "SE", "SNE", "LOAD", "ADD", "OR", "AND", "XOR", "SUB", "SHR",
+    "SUBN", "SHL", "RND", "DRAW", "SKP", "SKNP", "FONT", "BCD", "SAV", "RST",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpCode {
+    ClearScreen,
+    Return,
+    Jump { address: usize },
+    Call { address: usize },
+    SkipByteEqual { x: usize, byte: u8 },
+    SkipByteNotEqual { x: usize, byte: u8 },
+    SkipEqual { x: usize, y: usize },
+    SkipNotEqual { x: usize, y: usize },
+    LoadByte { x: usize, byte: u8 },
+    AddByte { x: usize, byte: u8 },
+    Load { x: usize, y: usize },
+    Or { x: usize, y: usize },
+    And { x: usize, y: usize },
+    Xor { x: usize, y: usize },
+    Add { x: usize, y: usize },
+    Sub { x: usize, y: usize },
+    ShiftRight { x: usize, y: usize },
+    SubReverse { x: usize, y: usize },
+    ShiftLeft { x: usize, y: usize },
+    LoadAddress { address: usize },
+    JumpOffset { address: usize },
+    Random { x: usize, byte: u8 },
+    Draw { x: usize, y: usize, n: u8 },
+    SkipKeyPressed { x: usize },
+    SkipNotPressed { x: usize },
+    LoadFromDelayTimer { x: usize },
+    WaitKey { x: usize },
+    LoadDelayTimer { x: usize },
+    LoadSoundTimer { x: usize },
+    AddAddress { x: usize },
+    LoadFont { x: usize },
+    BCD { x: usize },
+    Save { x: usize },
+    Restore { x: usize },
+    Unknown { instruction: u16 },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OpCodeError {
+    RegisterOutOfRange(usize),
+    AddressOutOfRange(usize),
+    ByteOutOfRange(usize),
+    NibbleOutOfRange(usize),
+    UnknownMnemonic(String),
+    InvalidOperands(String),
+}
+
+impl fmt::Display for OpCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OpCodeError::RegisterOutOfRange(x) => write!(f, "register V{:X} out of range", x),
+            OpCodeError::AddressOutOfRange(address) => {
+                write!(f, "address #{:X} out of range", address)
+            }
+            OpCodeError::ByteOutOfRange(byte) => write!(f, "byte #{:X} out of range", byte),
+            OpCodeError::NibbleOutOfRange(n) => write!(f, "sprite height {} out of range", n),
+            OpCodeError::UnknownMnemonic(op) => write!(f, "unknown mnemonic {}", op),
+            OpCodeError::InvalidOperands(inst) => write!(f, "invalid operands in {}", inst),
+        }
+    }
+}
+
+impl Error for OpCodeError {}
+
+// state an instruction reads or writes, apart from the PC
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    V(usize),
+    I,
+    DT,
+    ST,
+    SP,
+}
+
+#[allow(dead_code)]
+impl OpCode {
+    pub fn decode(instruction: u16) -> OpCode {
+        let address = (instruction & 0xFFF) as usize;
+        let byte = (instruction & 0xFF) as u8;
+        let x = (instruction >> 8 & 0xF) as usize;
+        let y = (instruction >> 4 & 0xF) as usize;
+        let n = (instruction & 0xF) as u8;
+
+        match instruction {
+            0x00E0 => OpCode::ClearScreen,
+            0x00EE => OpCode::Return,
+            i if i & 0xF000 == 0x1000 => OpCode::Jump { address },
+            i if i & 0xF000 == 0x2000 => OpCode::Call { address },
+            i if i & 0xF000 == 0x3000 => OpCode::SkipByteEqual { x, byte },
+            i if i & 0xF000 == 0x4000 => OpCode::SkipByteNotEqual { x, byte },
+            i if i & 0xF00F == 0x5000 => OpCode::SkipEqual { x, y },
+            i if i & 0xF000 == 0x6000 => OpCode::LoadByte { x, byte },
+            i if i & 0xF000 == 0x7000 => OpCode::AddByte { x, byte },
+            i if i & 0xF00F == 0x8000 => OpCode::Load { x, y },
+            i if i & 0xF00F == 0x8001 => OpCode::Or { x, y },
+            i if i & 0xF00F == 0x8002 => OpCode::And { x, y },
+            i if i & 0xF00F == 0x8003 => OpCode::Xor { x, y },
+            i if i & 0xF00F == 0x8004 => OpCode::Add { x, y },
+            i if i & 0xF00F == 0x8005 => OpCode::Sub { x, y },
+            i if i & 0xF00F == 0x8006 => OpCode::ShiftRight { x, y },
+            i if i & 0xF00F == 0x8007 => OpCode::SubReverse { x, y },
+            i if i & 0xF00F == 0x800E => OpCode::ShiftLeft { x, y },
+            i if i & 0xF00F == 0x9000 => OpCode::SkipNotEqual { x, y },
+            i if i & 0xF000 == 0xA000 => OpCode::LoadAddress { address },
+            i if i & 0xF000 == 0xB000 => OpCode::JumpOffset { address },
+            i if i & 0xF000 == 0xC000 => OpCode::Random { x, byte },
+            i if i & 0xF000 == 0xD000 => OpCode::Draw { x, y, n },
+            i if i & 0xF0FF == 0xE09E => OpCode::SkipKeyPressed { x },
+            i if i & 0xF0FF == 0xE0A1 => OpCode::SkipNotPressed { x },
+            i if i & 0xF0FF == 0xF007 => OpCode::LoadFromDelayTimer { x },
+            i if i & 0xF0FF == 0xF00A => OpCode::WaitKey { x },
+            i if i & 0xF0FF == 0xF015 => OpCode::LoadDelayTimer { x },
+            i if i & 0xF0FF == 0xF018 => OpCode::LoadSoundTimer { x },
+            i if i & 0xF0FF == 0xF01E => OpCode::AddAddress { x },
+            i if i & 0xF0FF == 0xF029 => OpCode::LoadFont { x },
+            i if i & 0xF0FF == 0xF033 => OpCode::BCD { x },
+            i if i & 0xF0FF == 0xF055 => OpCode::Save { x },
+            i if i & 0xF0FF == 0xF065 => OpCode::Restore { x },
+            _ => OpCode::Unknown { instruction },
+        }
+    }
+
+    pub fn encode(self) -> Result<u16, OpCodeError> {
+        use self::OpCode::*;
+
+        self.validate()?;
+        let instruction = match self {
+            ClearScreen => 0x00E0,
+            Return => 0x00EE,
+            Jump { address } => 0x1000 | address as u16,
+            Call { address } => 0x2000 | address as u16,
+            SkipByteEqual { x, byte } => 0x3000 | encode_xb(x, byte),
+            SkipByteNotEqual { x, byte } => 0x4000 | encode_xb(x, byte),
+            SkipEqual { x, y } => 0x5000 | encode_xy(x, y),
+            LoadByte { x, byte } => 0x6000 | encode_xb(x, byte),
+            AddByte { x, byte } => 0x7000 | encode_xb(x, byte),
+            Load { x, y } => 0x8000 | encode_xy(x, y),
+            Or { x, y } => 0x8001 | encode_xy(x, y),
+            And { x, y } => 0x8002 | encode_xy(x, y),
+            Xor { x, y } => 0x8003 | encode_xy(x, y),
+            Add { x, y } => 0x8004 | encode_xy(x, y),
+            Sub { x, y } => 0x8005 | encode_xy(x, y),
+            ShiftRight { x, y } => 0x8006 | encode_xy(x, y),
+            SubReverse { x, y } => 0x8007 | encode_xy(x, y),
+            ShiftLeft { x, y } => 0x800E | encode_xy(x, y),
+            SkipNotEqual { x, y } => 0x9000 | encode_xy(x, y),
+            LoadAddress { address } => 0xA000 | address as u16,
+            JumpOffset { address } => 0xB000 | address as u16,
+            Random { x, byte } => 0xC000 | encode_xb(x, byte),
+            Draw { x, y, n } => 0xD000 | encode_xy(x, y) | u16::from(n),
+            SkipKeyPressed { x } => 0xE09E | encode_x(x),
+            SkipNotPressed { x } => 0xE0A1 | encode_x(x),
+            LoadFromDelayTimer { x } => 0xF007 | encode_x(x),
+            WaitKey { x } => 0xF00A | encode_x(x),
+            LoadDelayTimer { x } => 0xF015 | encode_x(x),
+            LoadSoundTimer { x } => 0xF018 | encode_x(x),
+            AddAddress { x } => 0xF01E | encode_x(x),
+            LoadFont { x } => 0xF029 | encode_x(x),
+            BCD { x } => 0xF033 | encode_x(x),
+            Save { x } => 0xF055 | encode_x(x),
+            Restore { x } => 0xF065 | encode_x(x),
+            Unknown { instruction } => instruction,
+        };
+
+        Ok(instruction)
+    }
+
+    // checks operands fit their fields: registers and sprite heights < 16,
+    // addresses < 4096
+    pub fn validate(self) -> Result<(), OpCodeError> {
+        use self::OpCode::*;
+
+        match self {
+            ClearScreen | Return | Unknown { .. } => Ok(()),
+            Jump { address } | Call { address } | LoadAddress { address } => check_address(address),
+            JumpOffset { address } => check_address(address),
+            SkipEqual { x, y } | SkipNotEqual { x, y } | Load { x, y } | Or { x, y } => {
+                check_registers(x, y)
+            }
+            And { x, y } | Xor { x, y } | Add { x, y } | Sub { x, y } => check_registers(x, y),
+            ShiftRight { x, y } | SubReverse { x, y } | ShiftLeft { x, y } => check_registers(x, y),
+            Draw { x, y, n } => {
+                check_registers(x, y)?;
+                if n as usize >= N_REGISTERS {
+                    return Err(OpCodeError::NibbleOutOfRange(n as usize));
+                }
+                Ok(())
+            }
+            SkipByteEqual { x, .. } | SkipByteNotEqual { x, .. } | LoadByte { x, .. } => {
+                check_register(x)
+            }
+            AddByte { x, .. } | Random { x, .. } => check_register(x),
+            SkipKeyPressed { x } | SkipNotPressed { x } | LoadFromDelayTimer { x } => {
+                check_register(x)
+            }
+            WaitKey { x } | LoadDelayTimer { x } | LoadSoundTimer { x } | AddAddress { x } => {
+                check_register(x)
+            }
+            LoadFont { x } | BCD { x } | Save { x } | Restore { x } => check_register(x),
+        }
+    }
+
+    pub fn disassemble(instruction: u16) -> (String, String) {
+        let (op, params) = Self::decode(instruction).mnemonic();
+        (op.to_owned(), params)
+    }
+
+    pub fn mnemonic(self) -> (&'static str, String) {
+        use self::OpCode::*;
+
+        match self {
+            ClearScreen => ("CLS", String::new()),
+            Return => ("RET", String::new()),
+            Jump { address } => ("JUMP", format!("#{:04X}", address)),
+            Call { address } => ("CALL", format!("#{:04X}", address)),
+            SkipByteEqual { x, byte } => ("SE", format!("V{:X}, {:02X}", x, byte)),
+            SkipByteNotEqual { x, byte } => ("SNE", format!("V{:X}, {:02X}", x, byte)),
+            SkipEqual { x, y } => ("SE", format!("V{:X}, V{:X}", x, y)),
+            LoadByte { x, byte } => ("LOAD", format!("V{:X}, {:02X}", x, byte)),
+            AddByte { x, byte } => ("ADD", format!("V{:X}, {:02X}", x, byte)),
+            Load { x, y } => ("LOAD", format!("V{:X}, V{:X}", x, y)),
+            Or { x, y } => ("OR", format!("V{:X}, V{:X}", x, y)),
+            And { x, y } => ("AND", format!("V{:X}, V{:X}", x, y)),
+            Xor { x, y } => ("XOR", format!("V{:X}, V{:X}", x, y)),
+            Add { x, y } => ("ADD", format!("V{:X}, V{:X}", x, y)),
+            Sub { x, y } => ("SUB", format!("V{:X}, V{:X}", x, y)),
+            ShiftRight { x, y } => ("SHR", format!("V{:X}, V{:X}", x, y)),
+            SubReverse { x, y } => ("SUBN", format!("V{:X}, V{:X}", x, y)),
+            ShiftLeft { x, y } => ("SHL", format!("V{:X}, V{:X}", x, y)),
+            SkipNotEqual { x, y } => ("SNE", format!("V{:X}, V{:X}", x, y)),
+            LoadAddress { address } => ("LOAD", format!("I, #{:04X}", address)),
+            JumpOffset { address } => ("JUMP", format!("V0, #{:04X}", address)),
+            Random { x, byte } => ("RND", format!("V{:X}, #{:02X}", x, byte)),
+            Draw { x, y, n } => ("DRAW", format!("V{:X}, V{:X}, {:X}", x, y, n)),
+            SkipKeyPressed { x } => ("SKP", format!("V{:X}", x)),
+            SkipNotPressed { x } => ("SKNP", format!("V{:X}", x)),
+            LoadFromDelayTimer { x } => ("LOAD", format!("V{:X}, DT", x)),
+            WaitKey { x } => ("LOAD", format!("V{:X}, K", x)),
+            LoadDelayTimer { x } => ("LOAD", format!("DT, V{:X}", x)),
+            LoadSoundTimer { x } => ("LOAD", format!("ST, V{:X}", x)),
+            AddAddress { x } => ("ADD", format!("I, V{:X}", x)),
+            LoadFont { x } => ("FONT", format!("I, V{:X}", x)),
+            BCD { x } => ("BCD", format!("I, V{:X}", x)),
+            Save { x } => ("SAV", format!("[I], V{:X}", x)),
+            Restore { x } => ("RST", format!("V{:X}, [I]", x)),
+            Unknown { instruction } => ("DW", format!("#{:04X}", instruction)),
+        }
+    }
+
+    // registers read, covering every quirk setting
+    pub fn reads(self) -> Vec<Register> {
+        use self::OpCode::*;
+        use self::Register::*;
+
+        match self {
+            Return | Call { .. } => vec![SP],
+            SkipByteEqual { x, .. } | SkipByteNotEqual { x, .. } | AddByte { x, .. } => vec![V(x)],
+            SkipKeyPressed { x } | SkipNotPressed { x } | LoadFont { x } => vec![V(x)],
+            LoadDelayTimer { x } | LoadSoundTimer { x } => vec![V(x)],
+            SkipEqual { x, y } | SkipNotEqual { x, y } | Or { x, y } | And { x, y } => {
+                vec![V(x), V(y)]
+            }
+            Xor { x, y } | Add { x, y } | Sub { x, y } | SubReverse { x, y } => vec![V(x), V(y)],
+            ShiftRight { x, y } | ShiftLeft { x, y } => vec![V(x), V(y)],
+            Load { y, .. } => vec![V(y)],
+            JumpOffset { address } => match address >> 8 {
+                0 => vec![V(0)],
+                x => vec![V(0), V(x)],
+            },
+            Draw { x, y, .. } => vec![V(x), V(y), I],
+            LoadFromDelayTimer { .. } => vec![DT],
+            AddAddress { x } | BCD { x } => vec![I, V(x)],
+            Save { x } => {
+                let mut regs = vec![I];
+                regs.extend((0..=x).map(V));
+                regs
+            }
+            Restore { .. } => vec![I],
+            _ => vec![],
+        }
+    }
+
+    // registers written, covering every quirk setting
+    pub fn writes(self) -> Vec<Register> {
+        use self::OpCode::*;
+        use self::Register::*;
+
+        match self {
+            Return | Call { .. } => vec![SP],
+            LoadByte { x, .. } | AddByte { x, .. } | Load { x, .. } | Random { x, .. } => {
+                vec![V(x)]
+            }
+            LoadFromDelayTimer { x } | WaitKey { x } => vec![V(x)],
+            Or { x, .. } | And { x, .. } | Xor { x, .. } | Add { x, .. } => vec![V(x), V(0xF)],
+            Sub { x, .. } | SubReverse { x, .. } => vec![V(x), V(0xF)],
+            ShiftRight { x, .. } | ShiftLeft { x, .. } => vec![V(x), V(0xF)],
+            LoadAddress { .. } | AddAddress { .. } | LoadFont { .. } | Save { .. } => vec![I],
+            Draw { .. } => vec![V(0xF)],
+            LoadDelayTimer { .. } => vec![DT],
+            LoadSoundTimer { .. } => vec![ST],
+            Restore { x } => {
+                let mut regs: Vec<Register> = (0..=x).map(V).collect();
+                regs.push(I);
+                regs
+            }
+            _ => vec![],
+        }
+    }
+
+    pub fn reads_memory(self) -> bool {
+        matches!(self, OpCode::Draw { .. } | OpCode::Restore { .. })
+    }
+
+    pub fn writes_memory(self) -> bool {
+        matches!(self, OpCode::BCD { .. } | OpCode::Save { .. })
+    }
+
+    // instructions that may move the PC other than to the next instruction
+    pub fn is_branch(self) -> bool {
+        use self::OpCode::*;
+
+        matches!(
+            self,
+            Jump { .. }
+                | Call { .. }
+                | Return
+                | JumpOffset { .. }
+                | SkipByteEqual { .. }
+                | SkipByteNotEqual { .. }
+                | SkipEqual { .. }
+                | SkipNotEqual { .. }
+                | SkipKeyPressed { .. }
+                | SkipNotPressed { .. }
+        )
+    }
+
+    // approximate COSMAC VIP machine cycles taken by the original interpreter
+    pub fn cycles(self) -> u32 {
+        use self::OpCode::*;
+
+        match self {
+            ClearScreen => 24,
+            Return | Jump { .. } | Call { .. } | JumpOffset { .. } => 23,
+            SkipByteEqual { .. } | SkipByteNotEqual { .. } | LoadAddress { .. } => 12,
+            SkipEqual { .. } | SkipNotEqual { .. } => 16,
+            SkipKeyPressed { .. } | SkipNotPressed { .. } => 16,
+            LoadByte { .. } => 6,
+            AddByte { .. } => 10,
+            Load { .. } | Or { .. } | And { .. } | Xor { .. } => 44,
+            Add { .. } | Sub { .. } | SubReverse { .. } => 44,
+            ShiftRight { .. } | ShiftLeft { .. } => 44,
+            Random { .. } => 36,
+            Draw { n, .. } => 68 + 24 * u32::from(n),
+            LoadFromDelayTimer { .. } | LoadDelayTimer { .. } | LoadSoundTimer { .. } => 10,
+            WaitKey { .. } => 10,
+            AddAddress { .. } => 19,
+            LoadFont { .. } => 20,
+            BCD { x } => 84 + 16 * x as u32,
+            Save { x } | Restore { x } => 14 + 14 * x as u32,
+            Unknown { .. } => 23,
+        }
+    }
+}
+
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (op, params) = self.mnemonic();
+        if params.is_empty() {
+            write!(f, "{}", op)
+        } else {
+            write!(f, "{} {}", op, params)
+        }
+    }
+}
+
+// parses the disassembler's syntax, e.g. "LOAD V1, 2A" or "DRAW V0, V1, 5";
+// numbers are hex with an optional leading '#'
+impl FromStr for OpCode {
+    type Err = OpCodeError;
+
+    fn from_str(s: &str) -> Result<OpCode, OpCodeError> {
+        use self::OpCode::*;
+        use self::Operand::*;
+
+        let s = s.trim();
+        let invalid = || OpCodeError::InvalidOperands(s.to_owned());
+        let (op, rest) = match s.find(char::is_whitespace) {
+            Some(i) => (&s[..i], &s[i..]),
+            None => (s, ""),
+        };
+
+        let mut operands = Vec::new();
+        for operand in rest.split(',').map(str::trim) {
+            operands.push(Operand::parse(operand).ok_or_else(invalid)?);
+        }
+        if operands == [Empty] {
+            operands.clear();
+        }
+
+        let op = op.to_uppercase();
+        let opcode = match (op.as_str(), &operands[..]) {
+            ("CLS", []) => ClearScreen,
+            ("RET", []) => Return,
+            ("JUMP", [Number(a)]) => Jump { address: *a },
+            ("JUMP", [V(0), Number(a)]) => JumpOffset { address: *a },
+            ("CALL", [Number(a)]) => Call { address: *a },
+            ("SE", [V(x), V(y)]) => SkipEqual { x: *x, y: *y },
+            ("SE", [V(x), Number(b)]) => SkipByteEqual {
+                x: *x,
+                byte: to_byte(*b)?,
+            },
+            ("SNE", [V(x), V(y)]) => SkipNotEqual { x: *x, y: *y },
+            ("SNE", [V(x), Number(b)]) => SkipByteNotEqual {
+                x: *x,
+                byte: to_byte(*b)?,
+            },
+            ("LOAD", [V(x), V(y)]) => Load { x: *x, y: *y },
+            ("LOAD", [V(x), Number(b)]) => LoadByte {
+                x: *x,
+                byte: to_byte(*b)?,
+            },
+            ("LOAD", [I, Number(a)]) => LoadAddress { address: *a },
+            ("LOAD", [V(x), DT]) => LoadFromDelayTimer { x: *x },
+            ("LOAD", [V(x), K]) => WaitKey { x: *x },
+            ("LOAD", [DT, V(x)]) => LoadDelayTimer { x: *x },
+            ("LOAD", [ST, V(x)]) => LoadSoundTimer { x: *x },
+            ("ADD", [V(x), V(y)]) => Add { x: *x, y: *y },
+            ("ADD", [V(x), Number(b)]) => AddByte {
+                x: *x,
+                byte: to_byte(*b)?,
+            },
+            ("ADD", [I, V(x)]) => AddAddress { x: *x },
+            ("OR", [V(x), V(y)]) => Or { x: *x, y: *y },
+            ("AND", [V(x), V(y)]) => And { x: *x, y: *y },
+            ("XOR", [V(x), V(y)]) => Xor { x: *x, y: *y },
+            ("SUB", [V(x), V(y)]) => Sub { x: *x, y: *y },
+            ("SHR", [V(x), V(y)]) => ShiftRight { x: *x, y: *y },
+            ("SUBN", [V(x), V(y)]) => SubReverse { x: *x, y: *y },
+            ("SHL", [V(x), V(y)]) => ShiftLeft { x: *x, y: *y },
+            ("RND", [V(x), Number(b)]) => Random {
+                x: *x,
+                byte: to_byte(*b)?,
+            },
+            ("DRAW", [V(x), V(y), Number(n)]) if *n <= 0xFF => Draw {
+                x: *x,
+                y: *y,
+                n: *n as u8,
+            },
+            ("DRAW", [V(_), V(_), Number(n)]) => return Err(OpCodeError::NibbleOutOfRange(*n)),
+            ("SKP", [V(x)]) => SkipKeyPressed { x: *x },
+            ("SKNP", [V(x)]) => SkipNotPressed { x: *x },
+            ("FONT", [I, V(x)]) => LoadFont { x: *x },
+            ("BCD", [I, V(x)]) => BCD { x: *x },
+            ("SAV", [IndirectI, V(x)]) => Save { x: *x },
+            ("RST", [V(x), IndirectI]) => Restore { x: *x },
+            ("DW", [Number(word)]) if *word <= 0xFFFF => return Ok(OpCode::decode(*word as u16)),
+            ("DW", [Number(word)]) => return Err(OpCodeError::AddressOutOfRange(*word)),
+            (op, _) if op == "DW" || MNEMONICS.contains(&op) => return Err(invalid()),
+            (op, _) => return Err(OpCodeError::UnknownMnemonic(op.to_owned())),
+        };
+
+        opcode.validate()?;
+        Ok(opcode)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand {
+    Empty,
+    V(usize),
+    I,
+    IndirectI,
+    DT,
+    ST,
+    K,
+    Number(usize),
+}
+
+impl Operand {
+    fn parse(s: &str) -> Option<Operand> {
+        let s = s.to_uppercase();
+        let operand = match s.as_str() {
+            "" => Operand::Empty,
+            "I" => Operand::I,
+            "[I]" => Operand::IndirectI,
+            "DT" => Operand::DT,
+            "ST" => Operand::ST,
+            "K" => Operand::K,
+            s if s.starts_with('V') => Operand::V(usize::from_str_radix(&s[1..], 16).ok()?),
+            s => Operand::Number(usize::from_str_radix(s.trim_start_matches('#'), 16).ok()?),
+        };
+        Some(operand)
+    }
+}
+
+#[inline(always)]
+fn encode_x(x: usize) -> u16 {
+    (x as u16) << 8
+}
+
+#[inline(always)]
+fn encode_xy(x: usize, y: usize) -> u16 {
+    (x as u16) << 8 | (y as u16) << 4
+}
+
+#[inline(always)]
+fn encode_xb(x: usize, byte: u8) -> u16 {
+    (x as u16) << 8 | u16::from(byte)
+}
+
+fn check_register(x: usize) -> Result<(), OpCodeError> {
+    if x < N_REGISTERS {
+        Ok(())
+    } else {
+        Err(OpCodeError::RegisterOutOfRange(x))
+    }
+}
+
+fn check_registers(x: usize, y: usize) -> Result<(), OpCodeError> {
+    check_register(x)?;
+    check_register(y)
+}
+
+fn check_address(address: usize) -> Result<(), OpCodeError> {
+    if address < MEMORY_SIZE {
+        Ok(())
+    } else {
+        Err(OpCodeError::AddressOutOfRange(address))
+    }
+}
+
+fn to_byte(n: usize) -> Result<u8, OpCodeError> {
+    if n <= 0xFF {
+        Ok(n as u8)
+    } else {
+        Err(OpCodeError::ByteOutOfRange(n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn parse() {
+        let program = [
+            "LOAD V1, 2A",
+            "jump v0, #300",
+            "DRAW VA, VB, F",
+            "sav [i], v3",
+            "CLS",
+        ];
+        let opcodes: Vec<OpCode> = program.iter().map(|s| s.parse().unwrap()).collect();
+        assert_eq!(
+            opcodes,
+            [
+                OpCode::LoadByte { x: 1, byte: 0x2A },
+                OpCode::JumpOffset { address: 0x300 },
+                OpCode::Draw {
+                    x: 10,
+                    y: 11,
+                    n: 15
+                },
+                OpCode::Save { x: 3 },
+                OpCode::ClearScreen,
+            ]
+        );
+
+        let words: Vec<u16> = opcodes.iter().map(|op| op.encode().unwrap()).collect();
+        assert_eq!(words, [0x612A, 0xB300, 0xDABF, 0xF355, 0x00E0]);
+        assert_eq!(opcodes[2].to_string(), "DRAW VA, VB, F");
+    }
+
+    #[test]
+    fn invalid_operands() {
+        let parse = |s: &str| s.parse::<OpCode>().unwrap_err();
+        assert_eq!(parse("LOAD V10, 00"), OpCodeError::RegisterOutOfRange(16));
+        assert_eq!(parse("JUMP #1000"), OpCodeError::AddressOutOfRange(0x1000));
+        assert_eq!(parse("ADD V0, 100"), OpCodeError::ByteOutOfRange(0x100));
+        assert_eq!(parse("DRAW V0, V1, 10"), OpCodeError::NibbleOutOfRange(16));
+        assert_eq!(
+            parse("FOO V0"),
+            OpCodeError::UnknownMnemonic("FOO".to_owned())
+        );
+        assert_eq!(
+            parse("CLS V0"),
+            OpCodeError::InvalidOperands("CLS V0".to_owned())
+        );
+        assert_eq!(
+            parse("SKP V0,"),
+            OpCodeError::InvalidOperands("SKP V0,".to_owned())
+        );
+
+        let op = OpCode::Draw { x: 0, y: 1, n: 16 };
+        assert_eq!(op.encode(), Err(OpCodeError::NibbleOutOfRange(16)));
+        let op = OpCode::Call { address: 0x1000 };
+        assert_eq!(op.encode(), Err(OpCodeError::AddressOutOfRange(0x1000)));
+    }
+
+    #[test]
+    fn metadata() {
+        let op = OpCode::Add { x: 1, y: 2 };
+        assert_eq!(op.reads(), [Register::V(1), Register::V(2)]);
+        assert_eq!(op.writes(), [Register::V(1), Register::V(0xF)]);
+        assert!(!op.is_branch());
+
+        let op = OpCode::Save { x: 1 };
+        assert_eq!(op.reads(), [Register::I, Register::V(0), Register::V(1)]);
+        assert!(op.writes_memory() && !op.reads_memory());
+
+        let op = OpCode::JumpOffset { address: 0x312 };
+        assert_eq!(op.reads(), [Register::V(0), Register::V(3)]);
+        assert!(op.is_branch());
+        assert!(OpCode::SkipKeyPressed { x: 0 }.is_branch());
+    }
+
+    proptest! {
+        #[test]
+        fn decode_encode_round_trip(instruction in any::<u16>()) {
+            let opcode = OpCode::decode(instruction);
+            prop_assert_eq!(opcode.encode(), Ok(instruction));
+            prop_assert_eq!(opcode.to_string().parse(), Ok(opcode));
+        }
+    }
+}
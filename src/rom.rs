@@ -1,3 +1,5 @@
+use sha1::Sha1;
+
 #[allow(dead_code)]
 pub static ROM: &'static [u8] = &[
     // 0x0000
@@ -33,3 +35,7 @@ pub static BOOT: &'static [u8] = &[
     0xF8, 0xCC, 0xCC, 0xF8, 0xC0, 0xC0, 0xC0, 0x00, 0x00, 0x00, 0xF0, 0x00, 0x00, 0x00, 0x78, 0xCC,
     0xCC, 0x78, 0xCC, 0xCC, 0x78,
 ];
+
+pub fn hash(bytes: &[u8]) -> String {
+    Sha1::from(bytes).digest().to_string()
+}
@@ -0,0 +1,218 @@
+use config::{self, Config, QuirksConfig, RomSettings, RomStore};
+use cpu::Quirks;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use toml;
+
+const ROMDB_FILE: &str = "romdb.toml";
+const BUILTIN: &str = include_str!("../resources/romdb.toml");
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Platform {
+    #[default]
+    #[serde(rename = "chip-8", alias = "chip8")]
+    Chip8,
+    #[serde(rename = "schip", alias = "superchip")]
+    Schip,
+    #[serde(rename = "xo-chip", alias = "xochip")]
+    XoChip,
+}
+
+impl Platform {
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::chip8(),
+            Platform::Schip => Quirks::schip(),
+            Platform::XoChip => Quirks::xochip(),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "CHIP-8"),
+            Platform::Schip => write!(f, "SCHIP"),
+            Platform::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RomInfo {
+    pub title: String,
+    pub author: Option<String>,
+    pub platform: Platform,
+    // instructions per 60 Hz frame, as in the community database
+    pub tickrate: Option<u32>,
+    pub quirks: Option<QuirksConfig>,
+    pub keys: BTreeMap<String, String>,
+}
+
+impl RomInfo {
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+            .as_ref()
+            .and_then(|quirks| quirks.quirks())
+            .unwrap_or_else(|| self.platform.quirks())
+    }
+
+    pub fn describe_keys(&self) -> Option<String> {
+        if self.keys.is_empty() {
+            return None;
+        }
+
+        let keys: Vec<String> = self
+            .keys
+            .iter()
+            .map(|(key, action)| format!("{} {}", key, action))
+            .collect();
+        Some(keys.join(", "))
+    }
+}
+
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} [{}]", self.title, self.platform)?;
+        if let Some(ref author) = self.author {
+            write!(f, " by {}", author)?;
+        }
+        Ok(())
+    }
+}
+
+// ROM metadata keyed by the SHA-1 of the ROM image
+#[derive(Debug, Default)]
+pub struct RomDb {
+    roms: HashMap<String, RomInfo>,
+}
+
+impl RomDb {
+    pub fn load() -> RomDb {
+        let mut db = RomDb::builtin();
+        if let Some(roms) = config::load::<HashMap<String, RomInfo>>(ROMDB_FILE) {
+            db.extend(roms);
+        }
+        db
+    }
+
+    pub fn builtin() -> RomDb {
+        match toml::from_str(BUILTIN) {
+            Ok(roms) => RomDb { roms },
+            Err(err) => {
+                error!("Error parsing built-in ROM database: {}", err);
+                RomDb::default()
+            }
+        }
+    }
+
+    fn extend(&mut self, roms: HashMap<String, RomInfo>) {
+        for (hash, info) in roms {
+            self.roms.insert(hash.to_lowercase(), info);
+        }
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&RomInfo> {
+        self.roms.get(hash)
+    }
+
+    // saved settings win over the database, which wins over the config
+    pub fn profile(&self, hash: &str, roms: &RomStore, config: &Config) -> Profile {
+        let settings = roms.get(hash).cloned().unwrap_or_default();
+        let info = self.get(hash);
+        if let Some(info) = info {
+            info!("Loaded {}", info);
+            if let Some(keys) = info.describe_keys() {
+                info!("Keys: {}", keys);
+            }
+        }
+
+        let quirks = settings
+            .quirks
+            .as_ref()
+            .and_then(|quirks| quirks.quirks())
+            .or_else(|| info.map(|info| info.quirks()))
+            .unwrap_or_else(|| config.default_quirks());
+        let ipf = settings
+            .ipf
+            .or_else(|| info.and_then(|info| info.tickrate))
+            .unwrap_or(config.ipf);
+        Profile {
+            settings,
+            quirks,
+            ipf,
+        }
+    }
+}
+
+// how a rom should run once its settings and database entry are applied
+pub struct Profile {
+    pub settings: RomSettings,
+    pub quirks: Quirks,
+    pub ipf: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rom;
+    use std::fs;
+
+    #[test]
+    fn builtin_matches_roms() {
+        let db = RomDb::builtin();
+        assert!(!db.roms.is_empty());
+
+        let bytes = fs::read("roms/pong.ch8").unwrap();
+        let info = db.get(&rom::hash(&bytes)).unwrap();
+        assert_eq!(info.title, "Pong");
+        assert_eq!(info.platform, Platform::Chip8);
+        assert_eq!(info.tickrate, Some(7));
+        assert_eq!(info.quirks(), Quirks::chip8());
+        assert_eq!(
+            info.describe_keys(),
+            Some("1 left up, 4 left down, C right up, D right down".to_owned())
+        );
+    }
+
+    #[test]
+    fn user_entries_override() {
+        let roms: HashMap<String, RomInfo> = toml::from_str(
+            r#"
+            [B232EF880BD6060FB45FA6EFFED7EDF0AE95670E]
+            title = "My Pong"
+            platform = "xochip"
+            quirks = "schip"
+            "#,
+        )
+        .unwrap();
+
+        let mut db = RomDb::builtin();
+        let n = db.roms.len();
+        db.extend(roms);
+
+        let info = db.get("b232ef880bd6060fb45fa6effed7edf0ae95670e").unwrap();
+        assert_eq!(db.roms.len(), n);
+        assert_eq!(info.to_string(), "My Pong [XO-CHIP]");
+        assert_eq!(info.quirks(), Quirks::schip());
+        assert_eq!(info.tickrate, None);
+    }
+
+    #[test]
+    fn profile_falls_back() {
+        let db = RomDb::builtin();
+        let config = Config {
+            ipf: 20,
+            quirks: Some(QuirksConfig::Preset("schip".to_owned())),
+            ..Config::default()
+        };
+
+        let bytes = fs::read("roms/pong.ch8").unwrap();
+        let profile = db.profile(&rom::hash(&bytes), &RomStore::default(), &config);
+        assert_eq!((profile.ipf, profile.quirks), (7, Quirks::chip8()));
+
+        let profile = db.profile("unknown", &RomStore::default(), &config);
+        assert_eq!((profile.ipf, profile.quirks), (20, Quirks::schip()));
+    }
+}
@@ -0,0 +1,88 @@
+use std::time::{Duration, Instant};
+
+// frames run per update before the backlog is dropped, e.g. after a stall
+const MAX_CATCH_UP: u32 = 4;
+
+// converts monotonic wall-clock time into whole emulation frames, carrying
+// the fractional remainder over so the long-run rate is exact
+pub struct Scheduler {
+    last: Instant,
+    accumulator: Duration,
+}
+
+impl Scheduler {
+    pub fn new(now: Instant) -> Scheduler {
+        Scheduler {
+            last: now,
+            accumulator: Duration::from_secs(0),
+        }
+    }
+
+    // forgets elapsed time, used when resuming so paused time isn't caught up
+    pub fn reset(&mut self, now: Instant) {
+        self.last = now;
+        self.accumulator = Duration::from_secs(0);
+    }
+
+    pub fn advance(&mut self, now: Instant, frame: Duration) -> u32 {
+        self.accumulator += now.saturating_duration_since(self.last);
+        self.last = now;
+
+        let frames = (self.accumulator.as_nanos() / frame.as_nanos().max(1)) as u32;
+        if frames > MAX_CATCH_UP {
+            self.accumulator = Duration::from_secs(0);
+            return MAX_CATCH_UP;
+        }
+
+        self.accumulator -= frame * frames;
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulates_fractional_frames() {
+        let start = Instant::now();
+        let frame = Duration::from_nanos(16_666_667);
+        let mut scheduler = Scheduler::new(start);
+
+        let mut frames = 0;
+        for ms in 1..=1000 {
+            frames += scheduler.advance(start + Duration::from_millis(ms), frame);
+        }
+        assert_eq!(frames, 59);
+
+        frames += scheduler.advance(start + Duration::from_millis(1001), frame);
+        assert_eq!(frames, 60);
+    }
+
+    #[test]
+    fn limits_catch_up() {
+        let start = Instant::now();
+        let frame = Duration::from_millis(10);
+        let mut scheduler = Scheduler::new(start);
+
+        assert_eq!(
+            scheduler.advance(start + Duration::from_millis(25), frame),
+            2
+        );
+        assert_eq!(
+            scheduler.advance(start + Duration::from_secs(2), frame),
+            MAX_CATCH_UP
+        );
+        assert_eq!(
+            scheduler.advance(start + Duration::from_millis(2015), frame),
+            1
+        );
+        assert_eq!(
+            scheduler.advance(start + Duration::from_millis(2019), frame),
+            0
+        );
+
+        scheduler.reset(start + Duration::from_secs(10));
+        assert_eq!(scheduler.advance(start + Duration::from_secs(10), frame), 0);
+    }
+}
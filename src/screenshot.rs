@@ -0,0 +1,74 @@
+use cpu::Chip8State;
+use png::{BitDepth, ColorType, Encoder};
+use std::fs::File;
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::path::Path;
+
+// a 2048x1024 image at most
+pub const MAX_SCALE: u32 = 32;
+
+#[inline(always)]
+pub fn pixel(video: &[u8], x: u32, y: u32) -> bool {
+    let byte = video[y as usize * Chip8State::WIDTH / 8 + x as usize / 8];
+    byte & (0x80 >> (x % 8)) != 0
+}
+
+// writes the 1-bit framebuffer as a grayscale png, each pixel `scale` wide
+pub fn encode<W: Write>(writer: W, video: &[u8], scale: u32) -> io::Result<()> {
+    if !(1..=MAX_SCALE).contains(&scale) {
+        let message = format!("scale must be 1 to {}", MAX_SCALE);
+        return Err(io::Error::new(ErrorKind::InvalidInput, message));
+    }
+    let width = Chip8State::WIDTH as u32 * scale;
+    let height = Chip8State::HEIGHT as u32 * scale;
+    let mut encoder = Encoder::new(writer, width, height);
+    encoder.set_color(ColorType::Grayscale);
+    encoder.set_depth(BitDepth::Eight);
+
+    let mut data = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let on = pixel(video, x / scale, y / scale);
+            data.push(if on { 0xFF } else { 0 });
+        }
+    }
+
+    let mut writer = encoder.write_header().map_err(to_io)?;
+    writer.write_image_data(&data).map_err(to_io)
+}
+
+pub fn save(path: &Path, video: &[u8], scale: u32) -> io::Result<()> {
+    encode(BufWriter::new(File::create(path)?), video, scale)
+}
+
+fn to_io(err: png::EncodingError) -> io::Error {
+    io::Error::other(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_scaled() {
+        let mut video = [0u8; 256];
+        video[0] = 0x80;
+        video[255] = 0x01;
+
+        let mut bytes = Vec::new();
+        encode(&mut bytes, &video, 2).unwrap();
+
+        let decoder = png::Decoder::new(&bytes[..]);
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((info.width, info.height), (128, 64));
+
+        let at = |x: usize, y: usize| data[y * 128 + x];
+        assert_eq!((at(0, 0), at(1, 1), at(2, 0)), (0xFF, 0xFF, 0));
+        assert_eq!((at(127, 63), at(125, 63)), (0xFF, 0));
+
+        assert!(encode(&mut bytes, &video, 0).is_err());
+        assert!(encode(&mut bytes, &video, MAX_SCALE + 1).is_err());
+    }
+}
@@ -0,0 +1,387 @@
+use cpu::{Chip8, Fault, Quirks};
+use debugger::{parse_address, Location, Stop};
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, INT};
+use screenshot;
+use speed::{Speed, Timing, IPF_MIN};
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::mem;
+use std::path::Path;
+use std::rc::Rc;
+
+// called before each frame the host runs, and when the debugger stops
+const ON_FRAME: &str = "on_frame";
+const ON_BREAK: &str = "on_break";
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+// what script functions act on, the host's cpu is swapped in for each call
+struct Machine {
+    cpu: Chip8,
+    speed: Speed,
+    frame: INT,
+    // a pause or resume requested of the host
+    paused: Option<bool>,
+}
+
+impl Machine {
+    fn run_frame(&mut self) -> Result<Option<Stop>, Fault> {
+        match self.speed.timing {
+            Timing::Ipf => self.cpu.run_frame(self.speed.ipf)?,
+            Timing::Vip => self.cpu.run_vip_frame()?,
+        }
+        self.frame += 1;
+        Ok(self.cpu.take_stop())
+    }
+}
+
+// a rhai script driving the emulator, e.g. a bot, a test scenario or a trainer
+pub struct Script {
+    name: String,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    machine: Rc<RefCell<Machine>>,
+    // bound to `this` in hooks, which can't see the script's variables
+    state: Dynamic,
+    // set once the script has thrown an error, after which hooks are skipped
+    failed: bool,
+}
+
+fn location(s: &str) -> ScriptResult<Location> {
+    s.parse::<Location>().map_err(|err| err.into())
+}
+
+fn address(value: INT) -> ScriptResult<usize> {
+    if value < 0 {
+        return Err(format!("bad address {}", value).into());
+    }
+    Ok(value as usize)
+}
+
+fn key(value: INT) -> ScriptResult<usize> {
+    match value {
+        0..=0xF => Ok(value as usize),
+        _ => Err(format!("bad key {}", value).into()),
+    }
+}
+
+fn save(machine: &Rc<RefCell<Machine>>, path: &str, scale: INT) -> ScriptResult<()> {
+    let machine = machine.borrow();
+    screenshot::save(
+        Path::new(path),
+        machine.cpu.state().video(),
+        u32::try_from(scale).unwrap_or(0),
+    )
+    .map_err(|err| format!("Error saving {}: {}", path, err).into())
+}
+
+fn engine(machine: &Rc<RefCell<Machine>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.on_print(|s| info!("{}", s));
+    engine.on_debug(|s, _, _| debug!("{}", s));
+
+    let m = machine.clone();
+    engine.register_fn("get", move |name: &str| -> ScriptResult<INT> {
+        let location = location(name)?;
+        let value = location.read(m.borrow().cpu.state());
+        value
+            .map(INT::from)
+            .ok_or_else(|| format!("{} out of range", location).into())
+    });
+    let m = machine.clone();
+    engine.register_fn("set", move |name: &str, value: INT| -> ScriptResult<()> {
+        let location = location(name)?;
+        m.borrow_mut()
+            .cpu
+            .set(location, value as u16)
+            .map_err(|err| format!("{}: {}", location, err).into())
+    });
+    let m = machine.clone();
+    engine.register_fn("peek", move |addr: INT| -> ScriptResult<INT> {
+        let value = Location::Memory(address(addr)?).read(m.borrow().cpu.state());
+        value
+            .map(INT::from)
+            .ok_or_else(|| format!("bad address {}", addr).into())
+    });
+    let m = machine.clone();
+    engine.register_fn("poke", move |addr: INT, value: INT| -> ScriptResult<()> {
+        m.borrow_mut()
+            .cpu
+            .poke(address(addr)?, &[value as u8])
+            .map_err(|err| err.to_string().into())
+    });
+
+    let m = machine.clone();
+    engine.register_fn("press", move |k: INT| -> ScriptResult<()> {
+        m.borrow_mut().cpu.press_key(key(k)?);
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("release", move |k: INT| -> ScriptResult<()> {
+        m.borrow_mut().cpu.release_key(key(k)?);
+        Ok(())
+    });
+
+    // runs up to `n` frames, returning why the debugger stopped or ""
+    let m = machine.clone();
+    engine.register_fn("frames", move |n: INT| -> ScriptResult<String> {
+        let mut machine = m.borrow_mut();
+        for _ in 0..n {
+            match machine.run_frame() {
+                Ok(Some(stop)) => return Ok(stop.to_string()),
+                Ok(None) => {}
+                Err(fault) => return Err(format!("CPU Error: {}", fault).into()),
+            }
+        }
+        Ok(String::new())
+    });
+    let m = machine.clone();
+    engine.register_fn("step", move |n: INT| -> ScriptResult<()> {
+        let mut machine = m.borrow_mut();
+        for _ in 0..n {
+            machine
+                .cpu
+                .execute_cycle()
+                .map_err(|fault| format!("CPU Error: {}", fault))?;
+        }
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("frame", move || m.borrow().frame);
+    let m = machine.clone();
+    engine.register_fn("reset", move || m.borrow_mut().cpu.soft_reset());
+
+    let m = machine.clone();
+    engine.register_fn("break_at", move |addr: &str| -> ScriptResult<bool> {
+        let address = parse_address(addr)?;
+        Ok(m.borrow_mut().cpu.debugger_mut().add_breakpoint(address))
+    });
+    let m = machine.clone();
+    engine.register_fn("unbreak", move |addr: &str| -> ScriptResult<bool> {
+        let address = parse_address(addr)?;
+        Ok(m.borrow_mut().cpu.debugger_mut().remove_breakpoint(address))
+    });
+    let m = machine.clone();
+    engine.register_fn("watch", move |name: &str| -> ScriptResult<bool> {
+        Ok(m.borrow_mut().cpu.watch(location(name)?))
+    });
+    let m = machine.clone();
+    engine.register_fn("unwatch", move |name: &str| -> ScriptResult<bool> {
+        Ok(m.borrow_mut()
+            .cpu
+            .debugger_mut()
+            .remove_watch(location(name)?))
+    });
+
+    let m = machine.clone();
+    engine.register_fn("pause", move || m.borrow_mut().paused = Some(true));
+    let m = machine.clone();
+    engine.register_fn("resume", move || m.borrow_mut().paused = Some(false));
+    let m = machine.clone();
+    engine.register_fn("quirks", move |name: &str| -> ScriptResult<()> {
+        let quirks = Quirks::preset(name).ok_or_else(|| format!("unknown quirks {}", name))?;
+        m.borrow_mut().cpu.set_quirks(quirks);
+        Ok(())
+    });
+
+    let m = machine.clone();
+    engine.register_fn("screenshot", move |path: &str| save(&m, path, 1));
+    let m = machine.clone();
+    engine.register_fn("screenshot", move |path: &str, scale: INT| {
+        save(&m, path, scale)
+    });
+
+    engine.register_fn("assert", |ok: bool, message: &str| -> ScriptResult<()> {
+        if ok {
+            Ok(())
+        } else {
+            Err(format!("Assertion failed: {}", message).into())
+        }
+    });
+    engine
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Script, String> {
+        let machine = Rc::new(RefCell::new(Machine {
+            cpu: Chip8::new(),
+            speed: Speed::new(IPF_MIN, Timing::Ipf),
+            frame: 0,
+            paused: None,
+        }));
+        let engine = engine(&machine);
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|err| err.to_string())?;
+
+        Ok(Script {
+            name: path.display().to_string(),
+            engine,
+            ast,
+            scope: Scope::new(),
+            machine,
+            state: Dynamic::from(Map::new()),
+            failed: false,
+        })
+    }
+
+    // runs the top level statements, returning false if the script failed
+    pub fn run(&mut self, cpu: &mut Chip8, speed: &mut Speed) -> bool {
+        self.call(cpu, speed, |engine, scope, ast| {
+            engine.run_ast_with_scope(scope, ast)
+        })
+    }
+
+    // called before each frame the host runs
+    pub fn frame(&mut self, cpu: &mut Chip8, speed: &mut Speed) {
+        self.hook(cpu, speed, ON_FRAME, vec![]);
+        self.machine.borrow_mut().frame += 1;
+    }
+
+    pub fn stopped(&mut self, cpu: &mut Chip8, speed: &mut Speed, stop: &Stop) {
+        self.hook(cpu, speed, ON_BREAK, vec![stop.to_string().into()]);
+    }
+
+    // a pause or resume the script requested since the last call
+    pub fn take_pause(&mut self) -> Option<bool> {
+        self.machine.borrow_mut().paused.take()
+    }
+
+    fn hook(&mut self, cpu: &mut Chip8, speed: &mut Speed, name: &str, args: Vec<Dynamic>) {
+        let defined = self
+            .ast
+            .iter_functions()
+            .any(|f| f.name == name && f.params.len() == args.len());
+        if self.failed || !defined {
+            return;
+        }
+
+        let mut state = mem::take(&mut self.state);
+        self.call(cpu, speed, |engine, scope, ast| {
+            let options = CallFnOptions::new()
+                .eval_ast(false)
+                .bind_this_ptr(&mut state);
+            engine
+                .call_fn_with_options::<Dynamic>(options, scope, ast, name, args)
+                .map(|_| ())
+        });
+        self.state = state;
+    }
+
+    fn call<F>(&mut self, cpu: &mut Chip8, speed: &mut Speed, f: F) -> bool
+    where
+        F: FnOnce(&Engine, &mut Scope<'static>, &AST) -> ScriptResult<()>,
+    {
+        {
+            let mut machine = self.machine.borrow_mut();
+            mem::swap(cpu, &mut machine.cpu);
+            mem::swap(speed, &mut machine.speed);
+        }
+        let result = f(&self.engine, &mut self.scope, &self.ast);
+        {
+            let mut machine = self.machine.borrow_mut();
+            mem::swap(cpu, &mut machine.cpu);
+            mem::swap(speed, &mut machine.speed);
+        }
+
+        if let Err(err) = result {
+            error!("Script error in {}: {}", self.name, err);
+            self.failed = true;
+        }
+        !self.failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn load(name: &str, source: &str) -> Script {
+        let path = env::temp_dir().join(format!("chip8-{}-{}.rhai", name, std::process::id()));
+        fs::write(&path, source).unwrap();
+        let script = Script::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        script
+    }
+
+    fn cpu() -> Chip8 {
+        // V0 = 5, then V0 += 1 forever
+        let mut cpu = Chip8::new();
+        cpu.load_rom(&[0x60, 0x05, 0x70, 0x01, 0x12, 0x02]).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn drive_machine() {
+        let mut script = load(
+            "drive",
+            r#"
+            set("V1", 7);
+            assert(get("v1") == 7, "set register");
+            poke(0x300, 0xAB);
+            assert(peek(0x300) == 0xAB && get("[300]") == 0xAB, "poke memory");
+
+            break_at("204");
+            let stop = frames(5);
+            assert(stop == "Breakpoint at 0204", stop);
+            assert(get("V0") == 6 && get("PC") == 0x204, "stopped at breakpoint");
+            step(2);
+            assert(get("V0") == 7, "stepped");
+            press(5);
+            "#,
+        );
+        let (mut cpu, mut speed) = (cpu(), Speed::new(10, Timing::Ipf));
+        assert!(script.run(&mut cpu, &mut speed));
+
+        assert_eq!(cpu.state().registers()[0..2], [7, 7]);
+        assert_eq!(cpu.state().memory()[0x300], 0xAB);
+        assert!(cpu.debugger().is_breakpoint(0x204));
+        assert_eq!(speed.ipf, 10);
+    }
+
+    #[test]
+    fn hooks() {
+        let mut script = load(
+            "hooks",
+            r#"
+            fn on_frame() {
+                this.frames = if this.frames == () { 1 } else { this.frames + 1 };
+                if this.frames == 2 { pause(); }
+            }
+            fn on_break(reason) {
+                if reason.starts_with("Breakpoint") { resume(); }
+            }
+            "#,
+        );
+        let (mut cpu, mut speed) = (cpu(), Speed::new(10, Timing::Ipf));
+        assert!(script.run(&mut cpu, &mut speed));
+
+        script.frame(&mut cpu, &mut speed);
+        assert_eq!(script.take_pause(), None);
+        script.frame(&mut cpu, &mut speed);
+        assert_eq!(script.take_pause(), Some(true));
+
+        script.stopped(&mut cpu, &mut speed, &Stop::Breakpoint(0x204));
+        assert_eq!(script.take_pause(), Some(false));
+    }
+
+    #[test]
+    fn errors_stop_hooks() {
+        let mut script = load(
+            "errors",
+            r#"
+            fn on_frame() { pause(); }
+            assert(get("V0") == 1, "boom");
+            "#,
+        );
+        let (mut cpu, mut speed) = (cpu(), Speed::new(10, Timing::Ipf));
+        assert!(!script.run(&mut cpu, &mut speed));
+
+        script.frame(&mut cpu, &mut speed);
+        assert_eq!(script.take_pause(), None);
+        assert!(Script::load(Path::new("missing.rhai")).is_err());
+    }
+}
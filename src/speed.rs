@@ -0,0 +1,173 @@
+use std::fmt;
+use std::time::Duration;
+
+pub const FRAME_HZ: u32 = 60;
+pub const IPF_MIN: u32 = 1;
+pub const IPF_MAX: u32 = 1000;
+
+const SLOW_MOTION: [f32; 4] = [1.0, 0.5, 0.25, 0.125];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Timing {
+    // a fixed number of instructions per frame
+    #[default]
+    Ipf,
+    // per-instruction COSMAC VIP machine cycles
+    Vip,
+}
+
+impl Timing {
+    pub fn cycle(self) -> Timing {
+        match self {
+            Timing::Ipf => Timing::Vip,
+            Timing::Vip => Timing::Ipf,
+        }
+    }
+}
+
+// emulation speed in instructions per 60 Hz frame, scaled by slow motion
+// or overridden by fast-forward while its key is held
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Speed {
+    pub ipf: u32,
+    pub timing: Timing,
+    pub slow_motion: usize,
+    pub fast_forward: bool,
+}
+
+impl Speed {
+    pub fn new(ipf: u32, timing: Timing) -> Speed {
+        Speed {
+            ipf: ipf.clamp(IPF_MIN, IPF_MAX),
+            timing,
+            slow_motion: 0,
+            fast_forward: false,
+        }
+    }
+
+    pub fn rate(&self) -> String {
+        match self.timing {
+            Timing::Ipf => format!("{:04}", self.ipf),
+            Timing::Vip => "VIP".to_owned(),
+        }
+    }
+
+    pub fn set_ipf(&mut self, ipf: u32) {
+        self.ipf = ipf.clamp(IPF_MIN, IPF_MAX);
+    }
+
+    #[inline(always)]
+    pub fn multiplier(&self) -> f32 {
+        SLOW_MOTION[self.slow_motion]
+    }
+
+    // wall-clock time per emulated frame, None when running uncapped
+    pub fn frame_duration(&self) -> Option<Duration> {
+        if self.fast_forward {
+            return None;
+        }
+
+        let nanos = 1e9 / (f64::from(FRAME_HZ) * f64::from(self.multiplier()));
+        Some(Duration::from_nanos(nanos as u64))
+    }
+
+    pub fn faster(&mut self) {
+        self.ipf = match self.ipf {
+            n if n < 10 => n + 1,
+            n if n < 50 => round(n, 5) + 5,
+            n => round(n, 10) + 10,
+        }
+        .min(IPF_MAX);
+    }
+
+    pub fn slower(&mut self) {
+        self.ipf = match self.ipf {
+            n if n <= 10 => n - 1,
+            n if n <= 50 => round(n - 1, 5),
+            n => round(n - 1, 10),
+        }
+        .max(IPF_MIN);
+    }
+
+    pub fn cycle_slow_motion(&mut self) {
+        self.slow_motion = (self.slow_motion + 1) % SLOW_MOTION.len();
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.fast_forward {
+            write!(f, ">>")
+        } else {
+            write!(f, "{}x", self.multiplier())
+        }
+    }
+}
+
+#[inline(always)]
+fn round(n: u32, nearest: u32) -> u32 {
+    (n / nearest) * nearest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn faster_slower() {
+        let mut speed = Speed::new(8, Timing::Ipf);
+        speed.faster();
+        speed.faster();
+        assert_eq!(speed.ipf, 10);
+        speed.faster();
+        assert_eq!(speed.ipf, 15);
+        speed.slower();
+        speed.slower();
+        assert_eq!(speed.ipf, 9);
+
+        let mut speed = Speed::new(5000, Timing::Ipf);
+        assert_eq!(speed.ipf, IPF_MAX);
+        speed.faster();
+        assert_eq!(speed.ipf, IPF_MAX);
+        speed.slower();
+        assert_eq!(speed.ipf, 990);
+
+        let mut speed = Speed::new(0, Timing::Ipf);
+        speed.slower();
+        assert_eq!(speed.ipf, IPF_MIN);
+    }
+
+    #[test]
+    fn frame_duration() {
+        let mut speed = Speed::new(8, Timing::Ipf);
+        assert_eq!(
+            speed.frame_duration(),
+            Some(Duration::from_nanos(16_666_666))
+        );
+        speed.cycle_slow_motion();
+        assert_eq!(speed.to_string(), "0.5x");
+        assert_eq!(
+            speed.frame_duration(),
+            Some(Duration::from_nanos(33_333_333))
+        );
+
+        speed.fast_forward = true;
+        assert_eq!(speed.to_string(), ">>");
+        assert_eq!(speed.frame_duration(), None);
+
+        for _ in 0..SLOW_MOTION.len() - 1 {
+            speed.cycle_slow_motion();
+        }
+        assert_eq!(speed.multiplier(), 1.0);
+    }
+
+    #[test]
+    fn timing_rate() {
+        let mut speed = Speed::new(8, Timing::Ipf);
+        assert_eq!(speed.rate(), "0008");
+        speed.timing = speed.timing.cycle();
+        assert_eq!(speed.rate(), "VIP");
+        assert_eq!(speed.timing.cycle(), Timing::Ipf);
+    }
+}
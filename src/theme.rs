@@ -0,0 +1,288 @@
+use config;
+use sdl2::pixels::Color;
+
+const THEMES_FILE: &str = "themes.toml";
+const N_PLANES: usize = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    // indexed by the combination of lit planes, 0 = pixel off
+    pub planes: [Color; N_PLANES],
+    pub text: Color,
+    pub address: Color,
+    pub instruction: Color,
+    pub background: Color,
+    pub panel: Color,
+    pub highlight: Color,
+    pub error: Color,
+    pub warn: Color,
+}
+
+impl Theme {
+    #[inline(always)]
+    pub fn pixel(&self, planes: usize) -> Color {
+        self.planes[planes % N_PLANES]
+    }
+
+    fn preset(name: &str, planes: [u32; N_PLANES], ui: [u32; 8]) -> Theme {
+        Theme {
+            name: name.to_owned(),
+            planes: [rgb(planes[0]), rgb(planes[1]), rgb(planes[2]), rgb(planes[3])],
+            text: rgb(ui[0]),
+            address: rgb(ui[1]),
+            instruction: rgb(ui[2]),
+            background: rgb(ui[3]),
+            panel: rgb(ui[4]),
+            highlight: rgb(ui[5]),
+            error: rgb(ui[6]),
+            warn: rgb(ui[7]),
+        }
+    }
+
+    pub fn presets() -> Vec<Theme> {
+        vec![
+            Theme::preset(
+                "default",
+                [0x8F9185, 0x11132B, 0x4E5058, 0x303246],
+                [
+                    0xA6ACCD, 0x82AAFF, 0xC692E9, 0x1B1F2B, 0x2A2E3E, 0x1B1F2B, 0xFF5370, 0xFFCB6B,
+                ],
+            ),
+            Theme::preset(
+                "phosphor",
+                [0x0A140C, 0x33FF66, 0x1A7F33, 0x99FFB3],
+                [
+                    0x66CC88, 0x33FF66, 0x99FFB3, 0x050A05, 0x0F1F12, 0x050A05, 0xFF6655, 0xCCFF66,
+                ],
+            ),
+            Theme::preset(
+                "amber",
+                [0x1A0F00, 0xFFB000, 0x805800, 0xFFD480],
+                [
+                    0xCC8C00, 0xFFB000, 0xFFD480, 0x0D0700, 0x241600, 0x0D0700, 0xFF5500, 0xFFE0A0,
+                ],
+            ),
+            Theme::preset(
+                "lcd",
+                [0x9BBC0F, 0x0F380F, 0x8BAC0F, 0x306230],
+                [
+                    0x0F380F, 0x306230, 0x0F380F, 0x8BAC0F, 0x9BBC0F, 0x8BAC0F, 0x0F380F, 0x306230,
+                ],
+            ),
+            Theme::preset(
+                "high-contrast",
+                [0x000000, 0xFFFFFF, 0xFFFF00, 0x00FFFF],
+                [
+                    0xFFFFFF, 0xFFFF00, 0x00FFFF, 0x000000, 0x1A1A1A, 0x404040, 0xFF0000, 0xFF8000,
+                ],
+            ),
+        ]
+    }
+
+    fn apply(&mut self, custom: &ThemeConfig) -> Result<(), String> {
+        self.name = custom.name.clone();
+
+        for (i, color) in custom.planes.iter().take(N_PLANES).enumerate() {
+            self.planes[i] = parse_color(color)?;
+        }
+
+        let ui = [
+            (&custom.text, &mut self.text),
+            (&custom.address, &mut self.address),
+            (&custom.instruction, &mut self.instruction),
+            (&custom.background, &mut self.background),
+            (&custom.panel, &mut self.panel),
+            (&custom.highlight, &mut self.highlight),
+            (&custom.error, &mut self.error),
+            (&custom.warn, &mut self.warn),
+        ];
+
+        for (value, color) in ui {
+            if let Some(value) = value {
+                *color = parse_color(value)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ThemesConfig {
+    theme: Option<String>,
+    #[serde(default)]
+    themes: Vec<ThemeConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ThemeConfig {
+    name: String,
+    base: Option<String>,
+    #[serde(default)]
+    planes: Vec<String>,
+    text: Option<String>,
+    address: Option<String>,
+    instruction: Option<String>,
+    background: Option<String>,
+    panel: Option<String>,
+    highlight: Option<String>,
+    error: Option<String>,
+    warn: Option<String>,
+}
+
+pub struct Themes {
+    themes: Vec<Theme>,
+    current: usize,
+}
+
+impl Default for Themes {
+    fn default() -> Self {
+        Themes {
+            themes: Theme::presets(),
+            current: 0,
+        }
+    }
+}
+
+impl Themes {
+    pub fn new() -> Themes {
+        Self::default()
+    }
+
+    pub fn load() -> Themes {
+        let mut themes = Themes::new();
+        if let Some(config) = config::load::<ThemesConfig>(THEMES_FILE) {
+            themes.extend(&config);
+        }
+        themes
+    }
+
+    fn extend(&mut self, config: &ThemesConfig) {
+        for custom in &config.themes {
+            let base = custom.base.as_ref().map_or("default", String::as_str);
+            let mut theme = match self.find(base) {
+                Some(i) => self.themes[i].clone(),
+                None => {
+                    warn!("Unknown base theme {} for {}", base, custom.name);
+                    self.themes[0].clone()
+                }
+            };
+
+            match theme.apply(custom) {
+                Ok(()) => match self.find(&custom.name) {
+                    Some(i) => self.themes[i] = theme,
+                    None => self.themes.push(theme),
+                },
+                Err(err) => error!("Invalid theme {}: {}", custom.name, err),
+            }
+        }
+
+        if let Some(ref name) = config.theme {
+            if !self.select(name) {
+                warn!("Unknown theme {}", name);
+            }
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.themes.iter().position(|theme| theme.name == name)
+    }
+
+    pub fn select(&mut self, name: &str) -> bool {
+        match self.find(name) {
+            Some(i) => {
+                self.current = i;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn current(&self) -> &Theme {
+        &self.themes[self.current]
+    }
+
+    pub fn cycle(&mut self) -> &Theme {
+        self.current = (self.current + 1) % self.themes.len();
+        self.current()
+    }
+}
+
+#[inline(always)]
+fn rgb(value: u32) -> Color {
+    Color::RGB((value >> 16) as u8, (value >> 8) as u8, value as u8)
+}
+
+pub fn parse_color(value: &str) -> Result<Color, String> {
+    let hex = value.trim().trim_start_matches('#');
+    let parsed = u32::from_str_radix(hex, 16).map_err(|_| format!("bad color {}", value));
+
+    match hex.len() {
+        6 => parsed.map(rgb),
+        8 => parsed.map(|v| {
+            Color::RGBA((v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8)
+        }),
+        _ => Err(format!("bad color {}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_colors() {
+        assert_eq!(parse_color("#8F9185"), Ok(Color::RGB(0x8F, 0x91, 0x85)));
+        assert_eq!(parse_color("11132b"), Ok(Color::RGB(0x11, 0x13, 0x2B)));
+        assert_eq!(
+            parse_color("#11132B80"),
+            Ok(Color::RGBA(0x11, 0x13, 0x2B, 0x80))
+        );
+        assert!(parse_color("#123").is_err());
+        assert!(parse_color("#GGGGGG").is_err());
+    }
+
+    #[test]
+    fn select_cycle() {
+        let mut themes = Themes::new();
+        assert_eq!(themes.current().name, "default");
+        assert!(themes.select("amber"));
+        assert_eq!(themes.current().name, "amber");
+        assert!(!themes.select("missing"));
+
+        let n = themes.themes.len();
+        for _ in 0..n {
+            themes.cycle();
+        }
+        assert_eq!(themes.current().name, "amber");
+    }
+
+    #[test]
+    fn custom_themes() {
+        let config: ThemesConfig = toml::from_str(
+            r##"
+            theme = "mine"
+
+            [[themes]]
+            name = "mine"
+            base = "lcd"
+            planes = ["#000000", "#FFFFFF"]
+            text = "#FF0000"
+            "##,
+        )
+        .unwrap();
+
+        let mut themes = Themes::new();
+        themes.extend(&config);
+
+        let lcd = Theme::presets().into_iter().find(|t| t.name == "lcd").unwrap();
+        let theme = themes.current();
+        assert_eq!(theme.name, "mine");
+        assert_eq!(theme.pixel(0), Color::RGB(0, 0, 0));
+        assert_eq!(theme.pixel(1), Color::RGB(0xFF, 0xFF, 0xFF));
+        assert_eq!(theme.pixel(2), lcd.planes[2]);
+        assert_eq!(theme.text, Color::RGB(0xFF, 0, 0));
+        assert_eq!(theme.panel, lcd.panel);
+    }
+}
@@ -1,7 +1,7 @@
 use std::cell::UnsafeCell;
 use std::collections::HashMap;
 use std::hash::Hash;
-use std::time::SystemTime;
+use std::time::{Duration, Instant};
 
 struct CacheValue<V> {
     v: UnsafeCell<V>,
@@ -49,35 +49,36 @@ impl<K: Eq + Hash, V> Cache<K, V> {
 
 
 pub struct FPSCounter {
-    last_frame: SystemTime,
-    last_fps: SystemTime,
+    last_frame: Instant,
+    last_fps: Instant,
     fps_actual: f32,
     frames: u32,
-    ms_per_frame: f32,
+    frame_time: Duration,
 }
 
 impl FPSCounter {
     pub fn new(fps: u32) -> FPSCounter {
         FPSCounter {
-            last_frame: SystemTime::now(),
-            last_fps: SystemTime::now(),
+            last_frame: Instant::now(),
+            last_fps: Instant::now(),
             fps_actual: 0.0,
             frames: 0,
-            ms_per_frame: 1000.0 / fps as f32,
+            frame_time: Duration::from_secs(1) / fps.max(1),
         }
     }
 
-    pub fn frame(&mut self) -> u64 {
-        let now = SystemTime::now();
-        let delta = now.duration_since(self.last_frame).unwrap().as_millis() as f32;
+    // returns the time left until the next frame is due
+    pub fn frame(&mut self) -> Duration {
+        let now = Instant::now();
+        let delta = now.duration_since(self.last_frame);
         self.frames += 1;
         self.last_frame = now;
-        (self.ms_per_frame - delta).max(0.0) as u64
+        self.frame_time.checked_sub(delta).unwrap_or_default()
     }
 
     pub fn fps(&mut self) -> f32 {
-        let now = SystemTime::now();
-        let delta = now.duration_since(self.last_fps).unwrap().as_millis();
+        let now = Instant::now();
+        let delta = now.duration_since(self.last_fps).as_millis();
 
         if delta > 1000 {
             self.fps_actual = self.frames as f32 / (delta as f32 / 1000.0);
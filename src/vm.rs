@@ -1,41 +1,50 @@
-use audio::Audio;
-use cpu::{Chip8, Chip8State};
+use audio::{self, Audio, AudioConfig};
+use browser::{self, Browser, Nav};
+use cheat::Cheats;
+use config::{Config, QuirksConfig, RomStore};
+use console::{self, Command, Console, Edit, Host};
+use control::{self, Server};
+use cpu::{Chip8, Chip8Error, Chip8State, Fault, Quirks};
+use debugger::Debugger;
 use display::{Display, TextureCache};
+use input::{Action, Bindings, Hotkey, Input};
 use logger::Logger;
-use nfd::Response;
+use logview::{LogView, SearchKey, WHEEL_LINES};
+use netplay::{Connecting, NetError, Netplay, Role};
 use rom;
+use romdb::RomDb;
+use scheduler::Scheduler;
+use script::Script;
+use sdl2::controller::GameController;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseWheelDirection;
 use sdl2::ttf::Sdl2TtfContext;
-use sdl2::{AudioSubsystem, EventPump, Sdl};
-use std::cmp::{max, min};
+use sdl2::{AudioSubsystem, EventPump, GameControllerSubsystem, Sdl};
+use speed::{Speed, Timing};
 use std::env::current_dir;
-use std::fs::File;
-use std::io::Read;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::thread;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use util::FPSCounter;
+use watch::{WatchMode, Watcher};
 
 
-const HZ_MAX: u32 = 2000;
-const HZ_MIN: u32 = 1;
-const HZ_DEFAULT: u32 = 500;
-const FPS_DEFAULT: u32 = 60;
 const DELAY_BG: u64 = 50;
-
-
-macro_rules! round {
-    ($num:expr, $nearest:expr) => {
-        ($num / $nearest) * $nearest
-    };
-}
+const FAST_FORWARD_MS: u64 = 12;
 
 pub struct VMArgs<'a> {
     pub sdl: &'a Sdl,
     pub ttf: &'a Sdl2TtfContext,
-    pub audio: &'a AudioSubsystem,
+    pub audio: Option<&'a AudioSubsystem>,
     pub log: &'static Logger,
     pub cache: &'a TextureCache,
+    pub rom: Option<&'a Path>,
+    pub script: Option<Script>,
+    pub netplay: Option<Role>,
+    pub control: Option<u16>,
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -48,93 +57,165 @@ pub enum CPUState {
 
 pub struct RunState {
     pub cpu_state: CPUState,
-    pub last_step: SystemTime,
     pub fps: i32,
-    pub hz: u32,
+    pub speed: Speed,
+    pub fault: Option<Fault>,
 }
 
 pub struct UpdateState<'a> {
     pub cpu: &'a Chip8State,
     pub run: &'a RunState,
+    pub bindings: &'a Bindings,
+    pub browser: Option<&'a Browser>,
+    pub log: &'a LogView,
+    pub console: &'a Console,
+    pub debugger: &'a Debugger,
+    pub cheats: &'a Cheats,
+    // the highlighted cheat while the cheat list is open
+    pub cheat_menu: Option<usize>,
 }
 
 pub struct VM<'a> {
     pub cpu: Chip8,
     display: Display<'a>,
-    audio: Audio,
+    audio: Box<dyn Audio>,
+    log: &'static Logger,
+    log_view: LogView,
+    console: Console,
+    // save states by slot, kept for the session
+    states: HashMap<u8, Chip8State>,
     events: EventPump,
+    controllers: Option<GameControllerSubsystem>,
+    pads: Vec<GameController>,
+    // keypad keys held through each device, None for the keyboard
+    held: HashMap<Option<i32>, u16>,
+    bindings: Bindings,
+    config: Config,
+    roms: RomStore,
+    romdb: RomDb,
+    rom: Option<String>,
+    rom_path: Option<PathBuf>,
+    browser: Option<Browser>,
+    cheat_menu: Option<usize>,
+    watcher: Option<Watcher>,
+    script: Option<Script>,
+    netplay: Option<Netplay>,
+    connecting: Option<Connecting>,
+    role: Option<Role>,
+    control: Option<Server>,
+    scheduler: Scheduler,
     state: RunState,
 }
 
 impl<'a> VM<'a> {
     pub fn new(args: VMArgs<'a>) -> VM<'a> {
-        let chip8 = Chip8::new();
+        let config = Config::load();
+        args.log.configure(&config.log);
+        let mut chip8 = Chip8::new();
+        chip8.set_quirks(config.default_quirks());
+        let controllers = args
+            .sdl
+            .game_controller()
+            .map_err(|err| warn!("Game controllers unavailable: {}", err))
+            .ok();
 
         VM {
-            display: Display::new(args.sdl, args.ttf, args.log, args.cache),
-            audio: Audio::new(args.audio),
+            display: Display::new(args.sdl, args.ttf, args.cache, &config),
+            audio: audio::open(args.audio, &config.audio),
+            log: args.log,
+            log_view: LogView::new(),
+            console: Console::new(),
+            states: HashMap::new(),
             events: args.sdl.event_pump().unwrap(),
+            controllers,
+            pads: Vec::new(),
+            held: HashMap::new(),
+            bindings: Bindings::load(),
             cpu: chip8,
+            roms: RomStore::load(),
+            romdb: RomDb::load(),
+            rom: None,
+            rom_path: args.rom.map(Path::to_path_buf),
+            browser: None,
+            cheat_menu: None,
+            watcher: None,
+            script: args.script,
+            netplay: None,
+            connecting: None,
+            role: args.netplay,
+            control: args.control.and_then(Server::start),
+            scheduler: Scheduler::new(Instant::now()),
             state: RunState {
                 cpu_state: CPUState::Stopped,
-                last_step: SystemTime::UNIX_EPOCH,
-                hz: HZ_DEFAULT,
+                speed: Speed::new(config.ipf, config.timing),
                 fps: 0,
+                fault: None,
             },
+            config,
         }
     }
 
     pub fn start(&mut self) {
         self.state.cpu_state = CPUState::Running;
-        self.state.last_step = SystemTime::now();
-        let mut fps = FPSCounter::new(FPS_DEFAULT);
+        self.scheduler.reset(Instant::now());
+        let mut fps = FPSCounter::new(self.config.fps);
 
-        self.cpu.load_rom(&rom::BOOT).unwrap();
+        match self.rom_path.clone() {
+            Some(path) => self.load_file(&path),
+            None => {
+                self.cpu.load_rom(rom::BOOT).unwrap();
+            }
+        }
         info!("Started");
 
+        if let Some(role) = self.role.take() {
+            match Connecting::start(&role) {
+                Ok(connecting) => self.connecting = Some(connecting),
+                Err(err) => error!("Netplay failed: {}", err),
+            }
+        }
+
+        if let Some(ref mut script) = self.script {
+            script.run(&mut self.cpu, &mut self.state.speed);
+        }
+        self.script_pause();
+
         'runloop: loop {
             if self.state.cpu_state == CPUState::Stopped {
                 break 'runloop;
             }
 
             self.handle_events();
-            let cycles = self.cycles_since();
-
-            if cycles > 0 {
-                for _ in 0..cycles {
-                    if let Err(err) = self.cpu.execute_cycle() {
-                        error!("CPU Error: {}", err);
-                        self.state.cpu_state = CPUState::Paused;
-                    }
-                }
-                self.state.last_step = SystemTime::now();
-            }
-
-            /*
-
-            */
-
-            if self.state.cpu_state == CPUState::OneStep {
-                self.state.cpu_state = CPUState::Paused;
-            }
+            self.serve();
+            self.check_watch();
+            self.check_netplay();
+            self.run_frames();
 
             self.state.fps = fps.fps() as i32;
+            self.log_view.update(self.log);
             self.display.update(&UpdateState {
                 cpu: self.cpu.state(),
                 run: &self.state,
+                bindings: &self.bindings,
+                browser: self.browser.as_ref(),
+                log: &self.log_view,
+                console: &self.console,
+                debugger: self.cpu.debugger(),
+                cheats: self.cpu.cheats(),
+                cheat_menu: self.cheat_menu,
             });
-            if self.cpu.state().st() > 0 {
-                self.audio.on();
-            } else {
-                self.audio.off();
+            if self.state.cpu_state != CPUState::Running {
+                self.audio.silence();
             }
 
-            let delay = fps.frame() as u64;
+            // with vsync, presenting already paces the loop to the display
+            let delay = fps.frame();
             let paused = self.state.cpu_state == CPUState::Paused;
-            let focused = self.display.focused();
-            let ms = delay * if paused || !focused { DELAY_BG } else { 1 };
-
-            thread::sleep(Duration::from_millis(ms));
+            if paused || !self.display.focused() {
+                thread::sleep(Duration::from_millis(DELAY_BG));
+            } else if !self.display.vsync() && !self.state.speed.fast_forward {
+                thread::sleep(delay);
+            }
         }
     }
 
@@ -142,77 +223,441 @@ impl<'a> VM<'a> {
         while let Some(event) = self.events.poll_event() {
             match event {
                 Event::Quit { .. } => self.quit(),
+                Event::TextInput { text, .. } if self.console.active() => {
+                    self.console.type_text(&text)
+                }
+                Event::KeyDown {
+                    keycode: Some(code),
+                    ..
+                } if self.console.active() => self.console_input(code),
+                Event::TextInput { text, .. } if self.log_view.searching() => {
+                    self.log_view.type_text(&text)
+                }
                 Event::KeyDown {
                     keycode: Some(code),
                     ..
-                } => match code {
-                    Keycode::LeftBracket => self.dec_hz(),
-                    Keycode::RightBracket => self.inc_hz(),
-                    Keycode::F1 => self.load_file(),
-                    Keycode::F2 => self.reload(),
-                    Keycode::F3 => self.restart(),
-                    Keycode::F5 => self.toggle_pause(),
-                    Keycode::F6 => self.advance(),
-                    Keycode::Num1 => self.key_down(0x1),
-                    Keycode::Num2 => self.key_down(0x2),
-                    Keycode::Num3 => self.key_down(0x3),
-                    Keycode::Num4 => self.key_down(0xC),
-                    Keycode::Q => self.key_down(0x4),
-                    Keycode::W => self.key_down(0x5),
-                    Keycode::E => self.key_down(0x6),
-                    Keycode::R => self.key_down(0xD),
-                    Keycode::A => self.key_down(0x7),
-                    Keycode::S => self.key_down(0x8),
-                    Keycode::D => self.key_down(0x9),
-                    Keycode::F => self.key_down(0xE),
-                    Keycode::Z => self.key_down(0xA),
-                    Keycode::X => self.key_down(0x0),
-                    Keycode::C => self.key_down(0xB),
-                    Keycode::V => self.key_down(0xF),
-                    _ => (),
-                },
+                } if self.log_view.searching() => self.search_input(code),
+                Event::KeyDown {
+                    keycode: Some(code),
+                    ..
+                } if self.browser.is_some() => self.browse_input(Input::Key(code)),
+                Event::KeyDown {
+                    keycode: Some(code),
+                    ..
+                } if self.cheat_menu.is_some() => self.cheat_input(Input::Key(code)),
+                Event::KeyDown {
+                    keycode: Some(code),
+                    ..
+                } => self.input_down(None, Input::Key(code)),
                 Event::KeyUp {
                     keycode: Some(code),
                     ..
-                } => match code {
-                    Keycode::Num1 => self.key_up(0x1),
-                    Keycode::Num2 => self.key_up(0x2),
-                    Keycode::Num3 => self.key_up(0x3),
-                    Keycode::Num4 => self.key_up(0xC),
-                    Keycode::Q => self.key_up(0x4),
-                    Keycode::W => self.key_up(0x5),
-                    Keycode::E => self.key_up(0x6),
-                    Keycode::R => self.key_up(0xD),
-                    Keycode::A => self.key_up(0x7),
-                    Keycode::S => self.key_up(0x8),
-                    Keycode::D => self.key_up(0x9),
-                    Keycode::F => self.key_up(0xE),
-                    Keycode::Z => self.key_up(0xA),
-                    Keycode::X => self.key_up(0x0),
-                    Keycode::C => self.key_up(0xB),
-                    Keycode::V => self.key_up(0xF),
-                    _ => (),
-                },
+                } => self.input_up(None, Input::Key(code)),
+                Event::ControllerButtonDown { button, .. } if self.browser.is_some() => {
+                    self.browse_input(Input::Pad(button))
+                }
+                Event::ControllerButtonDown { button, .. } if self.cheat_menu.is_some() => {
+                    self.cheat_input(Input::Pad(button))
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    self.input_down(Some(which), Input::Pad(button))
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    self.input_up(Some(which), Input::Pad(button))
+                }
+                Event::MouseWheel { y, direction, .. } => self.scroll_log(y, direction),
+                Event::ControllerDeviceAdded { which, .. } => self.add_pad(which),
+                Event::ControllerDeviceRemoved { which, .. } => self.remove_pad(which),
                 _ => (),
             }
         }
     }
 
-    fn load_file(&mut self) {
-        let current = format!("{}", current_dir().unwrap().display());
-        let mut bytes = Vec::new();
+    fn input_down(&mut self, source: Option<i32>, input: Input) {
+        match self.bindings.action(input) {
+            Some(Action::Key(k)) => {
+                *self.held.entry(source).or_insert(0) |= 1 << k;
+                self.key_down(k)
+            }
+            Some(Action::Hotkey(hotkey)) => self.hotkey(hotkey),
+            None => (),
+        }
+    }
+
+    fn input_up(&mut self, source: Option<i32>, input: Input) {
+        match self.bindings.action(input) {
+            Some(Action::Key(k)) => {
+                if let Some(keys) = self.held.get_mut(&source) {
+                    *keys &= !(1 << k);
+                }
+                // the key stays down while another device holds it
+                if self.held.values().all(|keys| keys & 1 << k == 0) {
+                    self.key_up(k)
+                }
+            }
+            Some(Action::Hotkey(Hotkey::FastForward)) => self.fast_forward(false),
+            _ => (),
+        }
+    }
+
+    fn add_pad(&mut self, index: u32) {
+        if let Some(ref controllers) = self.controllers {
+            match controllers.open(index) {
+                Ok(pad) => {
+                    info!("Controller connected: {}", pad.name());
+                    self.pads.push(pad);
+                }
+                Err(err) => error!("Error opening controller {}: {}", index, err),
+            }
+        }
+    }
+
+    fn remove_pad(&mut self, id: i32) {
+        if let Some(i) = self.pads.iter().position(|pad| pad.instance_id() == id) {
+            let pad = self.pads.remove(i);
+            info!("Controller disconnected: {}", pad.name());
+            let keys = self.held.remove(&Some(id)).unwrap_or(0);
+            let others = self.held.values().fold(0, |held, keys| held | keys);
+            for k in 0..16 {
+                if (keys & !others) & 1 << k != 0 {
+                    self.key_up(k);
+                }
+            }
+        }
+    }
+
+    fn hotkey(&mut self, hotkey: Hotkey) {
+        if local_only(hotkey) && self.netplaying(hotkey.name()) {
+            return;
+        }
+        match hotkey {
+            Hotkey::Load => self.toggle_browser(),
+            Hotkey::Reload => self.reload(),
+            Hotkey::Restart => self.restart(),
+            Hotkey::Pause => self.toggle_pause(),
+            Hotkey::Step => self.advance(),
+            Hotkey::Slower => self.slower(),
+            Hotkey::Faster => self.faster(),
+            Hotkey::FastForward => self.fast_forward(true),
+            Hotkey::SlowMotion => self.slow_motion(),
+            Hotkey::Timing => self.toggle_timing(),
+            Hotkey::Mute => self.adjust_audio(|audio| audio.mute = !audio.mute),
+            Hotkey::Waveform => {
+                self.adjust_audio(|audio| audio.waveform = audio.waveform.cycle())
+            }
+            Hotkey::VolumeDown => self.adjust_audio(AudioConfig::quieter),
+            Hotkey::VolumeUp => self.adjust_audio(AudioConfig::louder),
+            Hotkey::PitchDown => self.adjust_audio(AudioConfig::lower),
+            Hotkey::PitchUp => self.adjust_audio(AudioConfig::higher),
+            Hotkey::Theme => self.next_theme(),
+            Hotkey::Filter => self.next_filter(),
+            Hotkey::Scaling => self.display.toggle_scaling(),
+            Hotkey::Layout => self.display.toggle_mode(),
+            Hotkey::Fullscreen => self.display.toggle_fullscreen(),
+            Hotkey::Keymap => self.display.toggle_keymap(),
+            Hotkey::Watch => self.toggle_watch(),
+            Hotkey::ScrollUp => self.log_view.page_up(),
+            Hotkey::ScrollDown => self.log_view.page_down(),
+            Hotkey::Search => self.start_search(),
+            Hotkey::SaveLog => self.save_log(),
+            Hotkey::Console => self.open_console(),
+            Hotkey::Cheats => self.toggle_cheats(),
+        }
+    }
+
+    fn open_console(&mut self) {
+        self.console.open();
+        self.display.text_input(true);
+    }
+
+    fn console_input(&mut self, code: Keycode) {
+        let line = match Edit::from_key(code) {
+            Some(edit) => self.console.edit(edit),
+            None => None,
+        };
+        if !self.console.active() {
+            self.display.text_input(false);
+        }
+
+        if let Some(line) = line {
+            info!("> {}", line);
+            match line.parse::<Command>() {
+                Ok(ref command) if !command.read_only() && self.netplaying(&line) => (),
+                Ok(command) => console::execute(self, command),
+                Err(err) => warn!("{}", err),
+            }
+        }
+    }
+
+    fn scroll_log(&mut self, lines: i32, direction: MouseWheelDirection) {
+        let mouse = self.events.mouse_state();
+        if self.browser.is_none() && self.display.over_log(mouse.x(), mouse.y()) {
+            let lines = match direction {
+                MouseWheelDirection::Flipped => -lines,
+                _ => lines,
+            };
+            self.log_view.scroll(lines * WHEEL_LINES);
+        }
+    }
+
+    fn start_search(&mut self) {
+        self.log_view.start_search();
+        self.display.text_input(true);
+    }
 
-        match nfd::open_file_dialog(None, Some(&current)) {
-            Err(error) => error!("Error loading file: {}", error),
-            Ok(Response::OkayMultiple(_)) => error!("Multiple files selected"),
-            Ok(Response::Okay(path)) => {
-                let mut file = File::open(path).unwrap();
-                file.read_to_end(&mut bytes).unwrap();
-                self.cpu.hard_reset();
-                self.cpu.load_rom(&bytes).unwrap();
+    fn search_input(&mut self, code: Keycode) {
+        if let Some(key) = SearchKey::from_key(code) {
+            self.log_view.search_key(key);
+            if !self.log_view.searching() {
+                self.display.text_input(false);
             }
+        }
+    }
+
+    fn save_log(&mut self) {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        let path = PathBuf::from(format!("chip8-{}.log", secs));
+        match self.log.export(&path) {
+            Ok(n) => info!("Saved {} log messages to {}", n, path.display()),
+            Err(err) => error!("Error saving log to {}: {}", path.display(), err),
+        }
+    }
+
+    fn toggle_browser(&mut self) {
+        if self.browser.take().is_some() {
+            return;
+        }
+        self.cheat_menu = None;
+
+        let dir = self
+            .config
+            .recent
+            .first()
+            .and_then(|path| path.parent())
+            .filter(|dir| dir.is_dir())
+            .map(Path::to_path_buf)
+            .or_else(|| current_dir().ok())
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        let mut browser = Browser::new(&dir, &self.config.recent);
+        browser.update_preview(&self.romdb);
+        self.browser = Some(browser);
+    }
+
+    fn browse_input(&mut self, input: Input) {
+        if self.bindings.action(input) == Some(Action::Hotkey(Hotkey::Load)) {
+            self.browser = None;
+            return;
+        }
+
+        let nav = match input {
+            Input::Key(code) => Nav::from_key(code),
+            Input::Pad(button) => Nav::from_button(button),
+        };
+
+        let chosen = match (nav, self.browser.as_mut()) {
+            (Some(Nav::Close), _) => {
+                self.browser = None;
+                None
+            }
+            (Some(nav), Some(browser)) => {
+                let chosen = browser.navigate(nav);
+                browser.update_preview(&self.romdb);
+                chosen
+            }
+            _ => None,
+        };
+
+        if let Some(path) = chosen {
+            self.load_file(&path);
+        }
+    }
+
+    fn toggle_cheats(&mut self) {
+        self.cheat_menu = match self.cheat_menu {
+            Some(_) => None,
+            None => Some(0),
+        };
+        self.browser = None;
+    }
+
+    fn cheat_input(&mut self, input: Input) {
+        if self.bindings.action(input) == Some(Action::Hotkey(Hotkey::Cheats)) {
+            self.cheat_menu = None;
+            return;
+        }
+
+        let nav = match input {
+            Input::Key(code) => Nav::from_key(code),
+            Input::Pad(button) => Nav::from_button(button),
+        };
+        let selected = self.cheat_menu.unwrap_or(0);
+        let last = self.cpu.cheats().cheats().len().saturating_sub(1);
+        match nav {
+            Some(Nav::Up) => self.cheat_menu = Some(selected.saturating_sub(1)),
+            Some(Nav::Down) => self.cheat_menu = Some((selected + 1).min(last)),
+            Some(Nav::Open) => {
+                if let Some(cheat) = self.cpu.cheats_mut().toggle(selected) {
+                    let on = if cheat.enabled { "on" } else { "off" };
+                    info!("{}: {}", cheat.name, on);
+                }
+            }
+            Some(Nav::Back) | Some(Nav::Close) => self.cheat_menu = None,
             _ => (),
+        }
+    }
+
+    fn load_file(&mut self, path: &Path) {
+        if self.netplaying("load") {
+            return;
+        }
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                error!("Error loading {}: {}", path.display(), err);
+                return;
+            }
+        };
+
+        let name = path.file_stem().and_then(|stem| stem.to_str());
+        if let Err(err) = self.load_rom(name, &bytes) {
+            error!("Error loading {}: {}", path.display(), err);
+            return;
+        }
+
+        browser::push_recent(&mut self.config.recent, path);
+        self.config.save();
+        self.browser = None;
+        self.rom_path = Some(path.to_path_buf());
+        self.watch();
+    }
+
+    fn watch(&mut self) {
+        self.watcher = match self.rom_path {
+            Some(ref path) if self.config.watch != WatchMode::Off => Some(Watcher::new(path)),
+            _ => None,
+        };
+    }
+
+    fn toggle_watch(&mut self) {
+        self.config.watch = self.config.watch.cycle();
+        self.config.save();
+        info!("Watch: {}", self.config.watch);
+        self.watch();
+    }
+
+    fn check_watch(&mut self) {
+        let path = match self.watcher {
+            Some(ref mut watcher) => {
+                if !watcher.poll() {
+                    return;
+                }
+                watcher.path().to_path_buf()
+            }
+            None => return,
         };
+        if self.netplaying("reload") {
+            return;
+        }
+
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                error!("Error reloading {}: {}", path.display(), err);
+                return;
+            }
+        };
+
+        let result = match self.config.watch {
+            WatchMode::Preserve => self.cpu.patch_rom(&bytes),
+            _ => self.cpu.load_rom(&bytes),
+        };
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        match result {
+            Ok(_) => info!("Reloaded {} ({})", name, self.config.watch),
+            Err(err) => {
+                error!("Error reloading {}: {}", name, err);
+                return;
+            }
+        }
+
+        // an edited rom is a different rom as far as its settings go
+        let hash = rom::hash(&bytes);
+        if self.rom.as_ref() != Some(&hash) {
+            self.select_rom(path.file_stem().and_then(|stem| stem.to_str()), hash);
+        }
+    }
+
+    fn load_rom(&mut self, name: Option<&str>, bytes: &[u8]) -> Result<(), Chip8Error> {
+        // a bad file leaves the running machine alone
+        Chip8::check_rom(bytes)?;
+        self.cpu.hard_reset();
+        self.cpu.load_rom(bytes)?;
+        self.state.fault = None;
+        self.select_rom(name, rom::hash(bytes));
+        Ok(())
+    }
+
+    // binds the cheats, settings, quirks and keys for a rom to the machine
+    fn select_rom(&mut self, name: Option<&str>, hash: String) {
+        self.cpu.cheats_mut().load(&hash);
+        let profile = self.romdb.profile(&hash, &self.roms, &self.config);
+        let settings = profile.settings;
+        self.state.speed.set_ipf(profile.ipf);
+        self.cpu.set_quirks(profile.quirks);
+        self.bindings.select_rom(Some(&hash));
+        self.bindings.override_keys(&settings.keys);
+        self.apply_theme(settings.theme.clone());
+
+        if let Some(name) = name {
+            if settings.name.as_deref() != Some(name) {
+                self.roms
+                    .update(&hash, |settings| settings.name = Some(name.to_owned()));
+            }
+        }
+
+        self.rom = Some(hash);
+    }
+
+    fn apply_theme(&mut self, theme: Option<String>) {
+        if let Some(name) = theme.or_else(|| self.config.theme.clone()) {
+            if !self.display.select_theme(&name) {
+                warn!("Unknown theme {}", name);
+            }
+        }
+    }
+
+    fn next_theme(&mut self) {
+        self.display.next_theme();
+        let name = self.display.theme_name().to_owned();
+        match self.rom {
+            Some(ref hash) => self.roms.update(hash, |settings| settings.theme = Some(name)),
+            None => {
+                self.config.theme = Some(name);
+                self.config.save();
+            }
+        }
+    }
+
+    fn next_filter(&mut self) {
+        self.display.next_filter();
+        self.config.filter = Some(self.display.filter().to_string());
+        self.config.save();
+    }
+
+    fn save_speed(&mut self) {
+        let ipf = self.state.speed.ipf;
+        match self.rom {
+            Some(ref hash) => self.roms.update(hash, |settings| settings.ipf = Some(ipf)),
+            None => {
+                self.config.ipf = ipf;
+                self.config.save();
+            }
+        }
     }
 
     fn advance(&mut self) {
@@ -220,58 +665,96 @@ impl<'a> VM<'a> {
     }
 
     fn quit(&mut self) {
+        let (width, height) = self.display.window_size();
+        if (width, height) != (self.config.width, self.config.height) {
+            self.config.width = width;
+            self.config.height = height;
+            self.config.save();
+        }
         self.state.cpu_state = CPUState::Stopped;
     }
 
     fn reload(&mut self) {
         self.cpu.soft_reset();
+        self.scheduler.reset(Instant::now());
         self.state = RunState {
             cpu_state: CPUState::Running,
-            last_step: SystemTime::now(),
-            hz: HZ_DEFAULT,
+            speed: self.state.speed,
             fps: 0,
+            fault: None,
         };
         info!("Reloaded");
     }
 
     fn restart(&mut self) {
-        self.cpu.load_rom(&rom::BOOT).unwrap();
+        self.cpu.load_rom(rom::BOOT).unwrap();
         self.cpu.hard_reset();
+        self.cpu.set_quirks(self.config.default_quirks());
+        self.bindings.select_rom(None);
+        self.apply_theme(None);
+        self.rom = None;
+        self.rom_path = None;
+        self.watch();
+        self.scheduler.reset(Instant::now());
         self.state = RunState {
             cpu_state: CPUState::Running,
-            last_step: SystemTime::now(),
-            hz: HZ_DEFAULT,
+            speed: Speed::new(self.config.ipf, self.config.timing),
             fps: 0,
+            fault: None,
         };
         info!("Restarted");
     }
 
+    // keys go through the peer while netplaying so both apply them together
     fn key_down(&mut self, k: usize) {
-        self.cpu.press_key(k);
+        match self.netplay {
+            Some(ref mut netplay) => netplay.press(k),
+            None => self.cpu.press_key(k),
+        }
     }
 
     fn key_up(&mut self, k: usize) {
-        self.cpu.release_key(k);
+        match self.netplay {
+            Some(ref mut netplay) => netplay.release(k),
+            None => self.cpu.release_key(k),
+        }
     }
 
-    fn dec_hz(&mut self) {
-        let inc = match self.state.hz {
-            n if n < 20 => n - 1,
-            n if n <= 100 => round!(n, 10) - 10,
-            n => round!(n, 100) - 100,
-        };
+    fn slower(&mut self) {
+        self.state.speed.slower();
+        self.save_speed();
+    }
 
-        self.state.hz = min(max(inc, HZ_MIN), HZ_MAX);
+    fn faster(&mut self) {
+        self.state.speed.faster();
+        self.save_speed();
     }
 
-    fn inc_hz(&mut self) {
-        let dec = match self.state.hz {
-            n if n < 20 => n + 1,
-            n if n <= 100 => round!(n, 10) + 10,
-            n => round!(n, 100) + 100,
-        };
+    fn fast_forward(&mut self, on: bool) {
+        if self.state.speed.fast_forward != on {
+            self.state.speed.fast_forward = on;
+            self.scheduler.reset(Instant::now());
+        }
+    }
+
+    fn slow_motion(&mut self) {
+        self.state.speed.cycle_slow_motion();
+        info!("Speed: {}", self.state.speed);
+    }
 
-        self.state.hz = min(max(dec, HZ_MAX), HZ_MAX);
+    fn toggle_timing(&mut self) {
+        let timing = self.state.speed.timing.cycle();
+        self.state.speed.timing = timing;
+        self.config.timing = timing;
+        self.config.save();
+        info!("Timing: {:?}", timing);
+    }
+
+    fn adjust_audio<F: FnOnce(&mut AudioConfig)>(&mut self, f: F) {
+        f(&mut self.config.audio);
+        self.audio.configure(&self.config.audio);
+        self.config.save();
+        info!("Audio: {}", self.config.audio);
     }
 
     fn toggle_pause(&mut self) {
@@ -282,26 +765,252 @@ impl<'a> VM<'a> {
             }
             CPUState::Paused => {
                 info!("Resumed");
+                self.state.fault = None;
+                self.scheduler.reset(Instant::now());
                 CPUState::Running
             }
             state => state,
         };
     }
 
-    fn cycles_since(&self) -> u32 {
-        match self.state.cpu_state {
-            CPUState::Stopped => 0,
-            CPUState::Paused => 0,
-            CPUState::OneStep => 1,
-            CPUState::Running => {
-                let now = SystemTime::now();
-                let elapsed = now
-                    .duration_since(self.state.last_step)
-                    .unwrap()
-                    .as_millis() as u32;
-                let ms_per_cycle = 1000 / self.state.hz;
-                elapsed / ms_per_cycle
+    fn fault(&mut self, fault: Fault) {
+        error!("CPU Error: {}", fault);
+        self.publish(control::Event::Fault(fault.to_string()));
+        let cpu = self.cpu.state();
+        let v = cpu.registers();
+        let hex = |regs: &[u8]| {
+            regs.iter()
+                .map(|r| format!("{:02X}", r))
+                .collect::<Vec<String>>()
+                .join(" ")
+        };
+        info!("V0-V7 {}", hex(&v[..8]));
+        info!("V8-VF {}", hex(&v[8..]));
+        info!(
+            "PC {:04X} I {:04X} SP {:X} DT {:02X} ST {:02X}",
+            fault.pc, fault.i, fault.sp, cpu.dt(), cpu.st()
+        );
+        self.state.cpu_state = CPUState::Paused;
+        self.state.fault = Some(fault);
+    }
+
+    // runs one frame, returning false if it faulted or the debugger stopped it
+    fn run_frame(&mut self) -> bool {
+        // the frame waits for the peer's keys rather than blocking the window
+        let synced = match self.netplay {
+            Some(ref mut netplay) => netplay.before_frame(&mut self.cpu),
+            None => Ok(true),
+        };
+        match synced {
+            Ok(true) => (),
+            Ok(false) => return false,
+            Err(err) => self.end_netplay(err),
+        }
+
+        if let Some(ref mut script) = self.script {
+            script.frame(&mut self.cpu, &mut self.state.speed);
+        }
+        if self.script_pause() {
+            return false;
+        }
+
+        let result = match self.state.speed.timing {
+            Timing::Ipf => self.cpu.run_frame(self.state.speed.ipf),
+            Timing::Vip => self.cpu.run_vip_frame(),
+        };
+        if let Err(fault) = result {
+            self.fault(fault);
+            return false;
+        }
+        self.audio.frame(self.cpu.sound());
+
+        let synced = match self.netplay {
+            Some(ref mut netplay) => netplay.after_frame(&self.cpu),
+            None => Ok(()),
+        };
+        if let Err(err) = synced {
+            self.end_netplay(err);
+        }
+
+        if let Some(stop) = self.cpu.take_stop() {
+            info!("{}", stop);
+            self.publish(control::Event::Stop(stop));
+            self.state.cpu_state = CPUState::Paused;
+            if let Some(ref mut script) = self.script {
+                script.stopped(&mut self.cpu, &mut self.state.speed, &stop);
             }
+            self.script_pause();
+            return false;
         }
+        true
+    }
+
+    // answers control requests between frames
+    fn serve(&mut self) {
+        let calls = match self.control {
+            Some(ref server) => server.calls(),
+            None => return,
+        };
+        for call in calls {
+            if call.read_only() || self.netplay.is_none() {
+                call.answer(self);
+            } else {
+                call.refuse("not available during netplay");
+            }
+        }
+    }
+
+    fn publish(&self, event: control::Event) {
+        if let Some(ref server) = self.control {
+            server.publish(&event);
+        }
+    }
+
+    // starts the session once the peer has connected
+    fn check_netplay(&mut self) {
+        let result = match self.connecting {
+            Some(ref connecting) => connecting.poll(&mut self.cpu, &mut self.state.speed),
+            None => return,
+        };
+        match result {
+            Some(Ok(netplay)) => self.netplay = Some(netplay),
+            Some(Err(err)) => error!("Netplay failed: {}", err),
+            None => return,
+        }
+        self.connecting = None;
+        self.scheduler.reset(Instant::now());
+    }
+
+    // refuses an action that would put the peers out of step
+    fn netplaying(&self, action: &str) -> bool {
+        if self.netplay.is_some() {
+            warn!("Can't {} during netplay", action);
+        }
+        self.netplay.is_some()
+    }
+
+    // carries on alone once the peer is gone or out of step
+    fn end_netplay(&mut self, err: NetError) {
+        error!("Netplay ended: {}", err);
+        self.netplay = None;
+    }
+
+    // applies a pause or resume the script asked for, returning true if paused
+    fn script_pause(&mut self) -> bool {
+        let request = self.script.as_mut().and_then(Script::take_pause);
+        if let Some(paused) = request {
+            Host::pause(self, paused);
+        }
+        request == Some(true)
+    }
+
+    fn run_frames(&mut self) {
+        let now = Instant::now();
+        let frames = match self.state.cpu_state {
+            CPUState::Stopped | CPUState::Paused => Some(0),
+            CPUState::OneStep => Some(1),
+            CPUState::Running => match self.state.speed.frame_duration() {
+                Some(frame) => Some(self.scheduler.advance(now, frame)),
+                None => None,
+            },
+        };
+
+        // fast-forward runs as many frames as fit in the budget
+        let budget = now + Duration::from_millis(FAST_FORWARD_MS);
+        let mut n = 0;
+        while match frames {
+            Some(frames) => n < frames,
+            None => Instant::now() < budget,
+        } {
+            if !self.run_frame() {
+                break;
+            }
+            n += 1;
+        }
+
+        if self.state.cpu_state == CPUState::OneStep {
+            self.state.cpu_state = CPUState::Paused;
+        }
+    }
+}
+
+// hotkeys that change the machine or its speed on this side alone
+fn local_only(hotkey: Hotkey) -> bool {
+    matches!(
+        hotkey,
+        Hotkey::Load
+            | Hotkey::Reload
+            | Hotkey::Restart
+            | Hotkey::Pause
+            | Hotkey::Step
+            | Hotkey::Slower
+            | Hotkey::Faster
+            | Hotkey::FastForward
+            | Hotkey::SlowMotion
+            | Hotkey::Timing
+            | Hotkey::Watch
+            | Hotkey::Cheats
+    )
+}
+
+impl<'a> Host for VM<'a> {
+    fn cpu(&mut self) -> &mut Chip8 {
+        &mut self.cpu
+    }
+
+    fn speed(&mut self) -> &mut Speed {
+        &mut self.state.speed
+    }
+
+    fn states(&mut self) -> &mut HashMap<u8, Chip8State> {
+        &mut self.states
+    }
+
+    fn logger(&self) -> &Logger {
+        self.log
+    }
+
+    fn load(&mut self, path: &Path) {
+        self.load_file(path);
+    }
+
+    fn reset(&mut self) {
+        self.reload();
+    }
+
+    fn pause(&mut self, paused: bool) {
+        let running = self.state.cpu_state == CPUState::Running;
+        if running == paused {
+            self.toggle_pause();
+        }
+    }
+
+    fn run_frames(&mut self, n: u32) {
+        self.pause(true);
+        for _ in 0..n {
+            if !self.run_frame() {
+                break;
+            }
+        }
+    }
+
+    fn set_quirks(&mut self, quirks: Quirks) {
+        self.cpu.set_quirks(quirks);
+        let saved = Some(QuirksConfig::Custom(quirks));
+        match self.rom {
+            Some(ref hash) => self.roms.update(hash, |settings| settings.quirks = saved),
+            None => {
+                self.config.quirks = saved;
+                self.config.save();
+            }
+        }
+    }
+
+    fn fault(&mut self, fault: Fault) {
+        VM::fault(self, fault);
+    }
+
+    fn quit(&mut self) {
+        VM::quit(self);
     }
 }
@@ -0,0 +1,127 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_MS: u64 = 250;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchMode {
+    #[default]
+    Off,
+    // hard reset and reload the ROM
+    Reset,
+    // swap in the new program, keeping registers, timers and the screen
+    Preserve,
+}
+
+impl WatchMode {
+    pub fn cycle(self) -> WatchMode {
+        match self {
+            WatchMode::Off => WatchMode::Reset,
+            WatchMode::Reset => WatchMode::Preserve,
+            WatchMode::Preserve => WatchMode::Off,
+        }
+    }
+}
+
+impl fmt::Display for WatchMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WatchMode::Off => write!(f, "off"),
+            WatchMode::Reset => write!(f, "reset"),
+            WatchMode::Preserve => write!(f, "preserve"),
+        }
+    }
+}
+
+type Stamp = Option<(SystemTime, u64)>;
+
+// polls a file's modification time and size
+pub struct Watcher {
+    path: PathBuf,
+    stamp: Stamp,
+    pending: bool,
+    last_poll: Instant,
+}
+
+impl Watcher {
+    pub fn new(path: &Path) -> Watcher {
+        Watcher {
+            path: path.to_path_buf(),
+            stamp: stamp(path),
+            pending: false,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < Duration::from_millis(POLL_MS) {
+            return false;
+        }
+
+        self.last_poll = Instant::now();
+        self.check()
+    }
+
+    // a change is reported once the file has stopped changing for one poll,
+    // so a ROM still being written by the assembler is not picked up halfway
+    fn check(&mut self) -> bool {
+        let stamp = stamp(&self.path);
+        if stamp != self.stamp {
+            self.stamp = stamp;
+            self.pending = true;
+            return false;
+        }
+
+        let changed = self.pending && stamp.is_some();
+        self.pending = false;
+        changed
+    }
+}
+
+fn stamp(path: &Path) -> Stamp {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn cycle_modes() {
+        let mode = WatchMode::default();
+        assert_eq!(mode.cycle(), WatchMode::Reset);
+        assert_eq!(mode.cycle().cycle().cycle(), mode);
+    }
+
+    #[test]
+    fn settled_changes() {
+        let path = env::temp_dir().join(format!("chip8-watch-{}.ch8", std::process::id()));
+        fs::write(&path, [0x00, 0xE0]).unwrap();
+
+        let mut watcher = Watcher::new(&path);
+        assert!(!watcher.check());
+
+        fs::write(&path, [0x00, 0xE0, 0x12, 0x00]).unwrap();
+        assert!(!watcher.check());
+        assert!(watcher.check());
+        assert!(!watcher.check());
+
+        fs::remove_file(&path).unwrap();
+        assert!(!watcher.check());
+        assert!(!watcher.check());
+
+        fs::write(&path, [0x00, 0xE0]).unwrap();
+        assert!(!watcher.check());
+        assert!(watcher.check());
+        fs::remove_file(&path).unwrap();
+    }
+}